@@ -6,7 +6,10 @@ use rustsearch::index::{Query, SearchType};
 
 use std::fs;
 
-use rustsearch::helpers::{read_file_to_string, Config};
+use rustsearch::helpers::{read_and_clean_file_to_iter, read_file_to_string, Config};
+use rustsearch::index::index10_0::{kmp_table_slice, kmp_truefalse_slice};
+use rustsearch::index::index10_1::{boyer_moore_preprocess, boyer_moore_truefalse};
+use rustsearch::index::index10_2::apostolico_giancarlo_truefalse;
 use rustsearch::index::{
     
     gen_query::{gen_a_lot_of_runs_bool, gen_a_lot_of_runs_tries},
@@ -96,17 +99,30 @@ pub fn indexing_11_1(c: &mut Criterion) {
     index_template(c, "11_1");
 }
 
+pub fn indexing_11_3(c: &mut Criterion) {
+    index_template(c, "11_3");
+}
+
 // Timing search times
 pub fn bool_searching_template(c: &mut Criterion, i_string: &str) {
     let files = fs::read_dir("../../data.nosync/");
 
     let boolean_searchtype = match i_string {
         "7_0" => " ",
+        "7_1" => "Simd",
         "8_0" => "Naive",
         "8_1" => "DeMorgan",
         "8_2" => "BinarySearch",
         "8_3" => "Hybrid",
         "8_4" => "Bitvecs",
+        "8_5" => "Difference",
+        "8_6" => "SkipPointers",
+        "8_7" => "Roaring",
+        "8_8" => "Simd",
+        "8_9" => "TopK",
+        "11_2" => "Positional",
+        "11_3" => "Phrases",
+        "12_0" => " ",
         _ => panic!(),
     };
 
@@ -155,6 +171,10 @@ pub fn searching_index_7_0(c: &mut Criterion) {
     bool_searching_template(c, "7_0");
 }
 
+pub fn searching_index_7_1(c: &mut Criterion) {
+    bool_searching_template(c, "7_1");
+}
+
 pub fn searching_index_8_0(c: &mut Criterion) {
     bool_searching_template(c, "8_0");
 }
@@ -175,6 +195,38 @@ pub fn searching_index_8_4(c: &mut Criterion) {
     bool_searching_template(c, "8_4");
 }
 
+pub fn searching_index_8_5(c: &mut Criterion) {
+    bool_searching_template(c, "8_5");
+}
+
+pub fn searching_index_8_6(c: &mut Criterion) {
+    bool_searching_template(c, "8_6");
+}
+
+pub fn searching_index_8_7(c: &mut Criterion) {
+    bool_searching_template(c, "8_7");
+}
+
+pub fn searching_index_8_8(c: &mut Criterion) {
+    bool_searching_template(c, "8_8");
+}
+
+pub fn searching_index_8_9(c: &mut Criterion) {
+    bool_searching_template(c, "8_9");
+}
+
+pub fn searching_index_11_2(c: &mut Criterion) {
+    bool_searching_template(c, "11_2");
+}
+
+pub fn searching_index_11_3(c: &mut Criterion) {
+    bool_searching_template(c, "11_3");
+}
+
+pub fn searching_index_12_0(c: &mut Criterion) {
+    bool_searching_template(c, "12_0");
+}
+
 pub fn prefix_search_template(c: &mut Criterion, i_string: &str, prefix_bool: bool) {
     let files = fs::read_dir("data/");
     let searchtype_string = match prefix_bool {
@@ -252,6 +304,14 @@ pub fn prefix_search_index_9_1(c: &mut Criterion) {
     prefix_search_template(c, "9_1", true)
 }
 
+pub fn find_word_12_1(c: &mut Criterion) {
+    prefix_search_template(c, "12_1", false)
+}
+
+pub fn prefix_search_index_12_1(c: &mut Criterion) {
+    prefix_search_template(c, "12_1", true)
+}
+
 pub fn full_text_searching_template(c: &mut Criterion, i_string: &str) {
     let files = fs::read_dir("data/");
 
@@ -259,8 +319,13 @@ pub fn full_text_searching_template(c: &mut Criterion, i_string: &str) {
         "10_0" => SearchType::ExactSearch("KMP".to_string()),
         "10_1" => SearchType::ExactSearch("BoyerMoore".to_string()),
         "10_2" => SearchType::ExactSearch("ApostolicoGiancarlo".to_string()),
+        "10_3" => SearchType::ExactSearch("AhoCorasick".to_string()),
+        "10_5" => SearchType::ExactSearch("Myers".to_string()),
+        "10_6" => SearchType::ExactSearch("Folded/accents".to_string()),
         "11_0" => SearchType::FuzzySearch,
         "11_1" => SearchType::ExactSearch("TripleBoyerMoore".to_string()),
+        "11_3" => SearchType::ExactSearch("NgramBoyerMoore".to_string()),
+        "13_0" => SearchType::ExactSearch("SuffixArray".to_string()),
         _ => panic!(),
     };
 
@@ -313,6 +378,18 @@ pub fn full_text_search_10_2(c: &mut Criterion) {
     full_text_searching_template(c, "10_2")
 }
 
+pub fn full_text_search_10_3(c: &mut Criterion) {
+    full_text_searching_template(c, "10_3")
+}
+
+pub fn full_text_search_10_5(c: &mut Criterion) {
+    full_text_searching_template(c, "10_5")
+}
+
+pub fn full_text_search_10_6(c: &mut Criterion) {
+    full_text_searching_template(c, "10_6")
+}
+
 pub fn full_text_search_11_0(c: &mut Criterion) {
     full_text_searching_template(c, "11_0")
 }
@@ -321,6 +398,121 @@ pub fn full_text_search_11_1(c: &mut Criterion) {
     full_text_searching_template(c, "11_1")
 }
 
+pub fn full_text_search_11_3(c: &mut Criterion) {
+    full_text_searching_template(c, "11_3")
+}
+
+pub fn full_text_search_13_0(c: &mut Criterion) {
+    full_text_searching_template(c, "13_0")
+}
+
+
+// Timing the exact matching on its own, with every article as chars, the way the searches
+// used to read them, against the articles as bytes
+pub fn exact_matching_chars_vs_bytes(c: &mut Criterion) {
+    let files = fs::read_dir("data/");
+
+    for dir in files.unwrap() {
+        if dir.as_ref().unwrap().path().is_dir() {
+            continue;
+        }
+
+        let file_path = dir.unwrap().path().into_os_string().into_string().unwrap();
+
+        if &file_path[0..9] != "data/West" {
+            continue;
+        }
+
+        let filesize = match file_path.rsplit_once('_') {
+            Some((_, suffix)) => suffix.split_once('.').unwrap().0,
+            None => continue,
+        };
+
+        let full_text_queries: Vec<String> = gen_a_lot_of_runs_full_text(file_path.clone(), 100);
+        let config = Config {
+            file_path: file_path.to_owned(),
+            indexno: "10".to_string(),
+        };
+        let articles: Vec<String> = read_and_clean_file_to_iter(&config)
+            .unwrap()
+            .into_iter()
+            .map(|(_, contents)| contents.join(" "))
+            .collect();
+
+        let mut group = c.benchmark_group(format!("Exact matching chars vs bytes {}", filesize));
+        group.bench_function("BoyerMoore chars", |b| {
+            b.iter(|| {
+                for query in &full_text_queries {
+                    let p: Vec<char> = query.chars().collect();
+                    let (big_l_prime, l_prime, r, _) = boyer_moore_preprocess(&p);
+                    for article in &articles {
+                        let t: Vec<char> = article.chars().collect();
+                        boyer_moore_truefalse(&p, &t, (&big_l_prime, &l_prime, &r));
+                    }
+                }
+            })
+        });
+        group.bench_function("BoyerMoore bytes", |b| {
+            b.iter(|| {
+                for query in &full_text_queries {
+                    let p = query.as_bytes();
+                    let (big_l_prime, l_prime, r, _) = boyer_moore_preprocess(p);
+                    for article in &articles {
+                        boyer_moore_truefalse(p, article.as_bytes(), (&big_l_prime, &l_prime, &r));
+                    }
+                }
+            })
+        });
+        group.bench_function("ApostolicoGiancarlo chars", |b| {
+            b.iter(|| {
+                for query in &full_text_queries {
+                    let p: Vec<char> = query.chars().collect();
+                    let (big_l_prime, l_prime, r, n) = boyer_moore_preprocess(&p);
+                    for article in &articles {
+                        let t: Vec<char> = article.chars().collect();
+                        apostolico_giancarlo_truefalse(&p, &t, (&big_l_prime, &l_prime, &r, &n));
+                    }
+                }
+            })
+        });
+        group.bench_function("ApostolicoGiancarlo bytes", |b| {
+            b.iter(|| {
+                for query in &full_text_queries {
+                    let p = query.as_bytes();
+                    let (big_l_prime, l_prime, r, n) = boyer_moore_preprocess(p);
+                    for article in &articles {
+                        let t = article.as_bytes();
+                        apostolico_giancarlo_truefalse(p, t, (&big_l_prime, &l_prime, &r, &n));
+                    }
+                }
+            })
+        });
+        group.bench_function("KMP chars", |b| {
+            b.iter(|| {
+                for query in &full_text_queries {
+                    let p: Vec<char> = query.chars().collect();
+                    let table = kmp_table_slice(&p);
+                    for article in &articles {
+                        let t: Vec<char> = article.chars().collect();
+                        kmp_truefalse_slice(&t, &p, &table);
+                    }
+                }
+            })
+        });
+        group.bench_function("KMP bytes", |b| {
+            b.iter(|| {
+                for query in &full_text_queries {
+                    let p = query.as_bytes();
+                    let table = kmp_table_slice(p);
+                    for article in &articles {
+                        kmp_truefalse_slice(article.as_bytes(), p, &table);
+                    }
+                }
+            })
+        });
+        group.finish();
+    }
+}
 
 //criterion_group!(benches,indexing_7,indexing_8_0,indexing_9_1,indexing_9_0,searching_index_7_0,searching_index_8_0,searching_index_8_1,searching_index_8_2,searching_index_8_3,searching_index_8_4,find_word_9_0,find_word_9_1,prefix_search_index_9_0,prefix_search_index_9_1);
 criterion_group!(
@@ -333,21 +525,39 @@ criterion_group!(
                 indexing_9_0,
                 indexing_10_0,
                 indexing_11_0,
+                indexing_11_3,
                 searching_index_7_0,
+                searching_index_7_1,
                 searching_index_8_0,
                 searching_index_8_1,
                 searching_index_8_2,
                 searching_index_8_3,
                 searching_index_8_4,
+                searching_index_8_5,
+                searching_index_8_6,
+                searching_index_8_7,
+                searching_index_8_8,
+                searching_index_8_9,
+                searching_index_11_2,
+                searching_index_11_3,
+                searching_index_12_0,
                 find_word_9_0,
                 find_word_9_1,
                 prefix_search_index_9_0,
                 prefix_search_index_9_1,
+                find_word_12_1,
+                prefix_search_index_12_1,
                 full_text_search_10_0,
                 full_text_search_10_1,
                 full_text_search_11_0,
                 full_text_search_11_1,
-                full_text_search_10_2
+                full_text_search_11_3,
+                full_text_search_10_2,
+                full_text_search_10_3,
+                full_text_search_10_5,
+                full_text_search_10_6,
+                full_text_search_13_0,
+                exact_matching_chars_vs_bytes
 );
 
 criterion_main!(benches);
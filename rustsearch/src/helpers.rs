@@ -1,10 +1,13 @@
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
+use std::sync::OnceLock;
 
 use regex::Regex;
 
+use crate::index::index12_0::MappedIndex;
 use crate::index::{Index, Search};
+use crate::parsing::{BODY_FIELD, TITLE_FIELD};
 
 pub struct Config {
     pub file_path: String,
@@ -34,12 +37,21 @@ impl Config {
             "6" => Ok(Box::new(Index::index6(&self)?)),
             "7" => Ok(Box::new(Index::index7(&self)?)),
             "7_0" => Ok(Box::new(Index::index7(&self)?)),
+            "7_1" => Ok(Box::new(Index::index7(self)?)),
             "8" => Ok(Box::new(Index::index8(&self)?)),
             "8_0" => Ok(Box::new(Index::index8(&self)?)),
             "8_1" => Ok(Box::new(Index::index8(&self)?)),
             "8_2" => Ok(Box::new(Index::index8(&self)?)),
             "8_3" => Ok(Box::new(Index::index8(&self)?)),
             "8_4" => Ok(Box::new(Index::index8(&self)?)),
+            "8_5" => Ok(Box::new(Index::index8(self)?)),
+            "8_6" => Ok(Box::new(Index::index8_6(self)?)),
+            "8.6" => Ok(Box::new(Index::index8_6(self)?)),
+            "8_7" => Ok(Box::new(Index::index8_7(self)?)),
+            "8.7" => Ok(Box::new(Index::index8_7(self)?)),
+            "8_8" => Ok(Box::new(Index::index8(self)?)),
+            "8_9" => Ok(Box::new(Index::index8_9(self)?)),
+            "8.9" => Ok(Box::new(Index::index8_9(self)?)),
             "9_0" => Ok(Box::new(Index::index9_0(&self)?)),
             "9.0" => Ok(Box::new(Index::index9_0(&self)?)),
             "9_1" => Ok(Box::new(Index::index9_1(&self)?)),
@@ -48,9 +60,25 @@ impl Config {
             "10_0" => Ok(Box::new(Index::index10(&self)?)),
             "10_1" => Ok(Box::new(Index::index10(&self)?)),
             "10_2" => Ok(Box::new(Index::index10(&self)?)),
+            "10_3" => Ok(Box::new(Index::index10(self)?)),
+            "10_5" => Ok(Box::new(Index::index10(self)?)),
+            "10_6" => Ok(Box::new(Index::index10_6(self)?)),
+            "10.6" => Ok(Box::new(Index::index10_6(self)?)),
+            "10_4" => Ok(Box::new(Index::index10_4(self)?)),
+            "10.4" => Ok(Box::new(Index::index10_4(self)?)),
             "11" => Ok(Box::new(Index::index11(&self)?)),
             "11_0" => Ok(Box::new(Index::index11(&self)?)),
             "11_1" => Ok(Box::new(Index::index11(&self)?)),
+            "11_2" => Ok(Box::new(Index::index11_2(self)?)),
+            "11.2" => Ok(Box::new(Index::index11_2(self)?)),
+            "11_3" => Ok(Box::new(Index::index11_3(self)?)),
+            "11.3" => Ok(Box::new(Index::index11_3(self)?)),
+            "12" => Ok(Box::new(MappedIndex::index12_0(self)?)),
+            "12_0" => Ok(Box::new(MappedIndex::index12_0(self)?)),
+            "12_1" => Ok(Box::new(Index::index12_1(self)?)),
+            "12.1" => Ok(Box::new(Index::index12_1(self)?)),
+            "13" => Ok(Box::new(Index::index13_0(self)?)),
+            "13_0" => Ok(Box::new(Index::index13_0(self)?)),
             _ => unimplemented!(),
         }
     }
@@ -65,7 +93,7 @@ pub fn read_and_clean_file_to_iter(
     config: &Config,
 ) -> Result<Vec<(String, Vec<String>)>, Box<dyn Error>> {
     let filecontents = fs::read_to_string(&config.file_path)?;
-    let re = Regex::new(r"\. |\.\n|\.\r\n|\n\n|; |[\[\]\{\}\\\n\(\) ,:/=?!*]").unwrap();
+    let re = word_separators();
 
     // Articles are seperated by the delimiter "---END.OF.DOCUMENT---"
     // In each article, it is assumed that the first line is the title, ending in a '.'
@@ -93,6 +121,43 @@ pub fn read_and_clean_file_to_iter(
     Ok(articles_iter)
 }
 
+// What the words of an article are split on. ':' is among them, so no word contains one.
+fn word_separators() -> &'static Regex {
+    static SEPARATORS: OnceLock<Regex> = OnceLock::new();
+    SEPARATORS.get_or_init(|| {
+        Regex::new(r"\. |\.\n|\.\r\n|\n\n|; |[\[\]\{\}\\\n\(\) ,:/=?!*]").unwrap()
+    })
+}
+
+// The term a word of a field is indexed under. Words of the body are indexed as they are, and
+// words of any other field as "field:word", which can't be a word of the body.
+pub fn field_term(field: &str, word: &str) -> String {
+    match field == BODY_FIELD {
+        true => word.to_string(),
+        false => format!("{}:{}", field, word),
+    }
+}
+
+// The terms of the named fields of an article, split into words the way the body is
+pub fn field_terms(fields: &[(&str, &str)]) -> Vec<String> {
+    fields
+        .iter()
+        .flat_map(|(field, text)| {
+            word_separators()
+                .split(text)
+                .filter(|w| !w.is_empty())
+                .map(|w| field_term(field, w))
+        })
+        .collect()
+}
+
+// Every term an article is indexed under: the words of its body, then those of its title
+pub fn article_terms(title: &str, contents: &[String]) -> Vec<String> {
+    let mut terms = contents.to_vec();
+    terms.extend(field_terms(&[(TITLE_FIELD, title)]));
+    terms
+}
+
 pub fn word_freq() {
     let file5mb = "data/WestburyLab.wikicorp.201004_5MB.txt";
     let file_contents = fs::read_to_string(&file5mb.to_string()).unwrap();
@@ -116,19 +181,3 @@ pub fn word_freq() {
     println!("{:#?}", word_freq)
 }
 
-pub fn write_article_files(config: &Config) {
-    let articles_iter = read_and_clean_file_to_iter(&config).unwrap();
-
-    let mut count = 0;
-
-    for (title, contents) in articles_iter {
-        if title != "" {
-            let x = contents.join(" ");
-            fs::write(format!("data/individual_articles/{:08}.txt", count), x).unwrap();
-            count += 1;
-        }
-        if count > 99999 {
-            panic!("Too many articles. It's over 99999.")
-        }
-    }
-}
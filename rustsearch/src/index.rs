@@ -1,20 +1,44 @@
 pub mod index6;
 pub mod index7;
+pub mod index7_1;
 pub mod index8_0;
 pub mod index8_1;
 pub mod index8_2;
 pub mod index8_3;
 pub mod index8_4;
+pub mod index8_5;
+pub mod index8_6;
+pub mod index8_7;
+pub mod index8_8;
+pub mod index8_9;
 pub mod index9_0;
 pub mod index9_1;
 pub mod index10_0;
 pub mod index10_1;
 pub mod index10_2;
+pub mod index10_3;
+pub mod index10_4;
+pub mod index10_5;
+pub mod index10_6;
 pub mod index11_1;
 pub mod index11_0;
+pub mod index11_2;
+pub mod index11_3;
+pub mod index12_0;
+pub mod index12_1;
+pub mod index13_0;
 
 pub mod gen_query;
+pub mod incremental;
+pub mod merge;
+pub mod ranking;
+pub mod segments;
+pub mod wal;
+pub mod concurrent;
+pub mod docstore;
+pub mod shards;
 
+#[derive(Clone)]
 pub struct Index<T> {
     database: T,
     article_titles: ArticleTitles,
@@ -26,6 +50,25 @@ pub struct Index<T> {
 // }
 type ArticleTitles = Vec<String>;
 
+// The articles a pattern occurs in, in increasing order, each with the byte offsets of the
+// occurrences in the text of the article. char_offsets turns them into offsets in characters.
+pub type Occurrences = Vec<(usize, Vec<usize>)>;
+
+// Byte offsets in text, in increasing order, as offsets in characters
+pub fn char_offsets(text: &[u8], byte_offsets: &[usize]) -> Vec<usize> {
+    let mut chars = 0;
+    let mut counted = 0;
+    byte_offsets
+        .iter()
+        .map(|&offset| {
+            // Every byte but the continuation bytes of UTF-8 starts a character
+            chars += text[counted..offset].iter().filter(|&&b| b & 0xC0 != 0x80).count();
+            counted = offset;
+            chars
+        })
+        .collect()
+}
+
 pub struct Query {
     pub search_string: String,
     pub search_type: SearchType
@@ -37,6 +80,7 @@ pub enum SearchType {
     PrefixSearch,
     ExactSearch(String),
     FuzzySearch,
+    TermSearch(String),
 }
 
 impl std::fmt::Display for SearchType {
@@ -47,6 +91,7 @@ impl std::fmt::Display for SearchType {
             SearchType::PrefixSearch => write!(f, "PrefixSearch"),
             SearchType::ExactSearch(x) => write!(f, "ExactSearch ({})", x),
             SearchType::FuzzySearch => write!(f, "FuzzySearch"),
+            SearchType::TermSearch(x) => write!(f, "TermSearch ({})", x),
         }
     }
 }
@@ -55,3 +100,14 @@ pub trait Search {
     fn search(&self, query: &Query) -> ArticleTitles;
 }
 
+impl<T> Index<T> {
+    // The titles of the articles with the number of occurrences in each, most occurrences first
+    pub fn titles_by_occurrences(&self, occurrences: &Occurrences) -> Vec<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = occurrences
+            .iter()
+            .map(|(a_no, offsets)| (self.article_titles[*a_no].to_owned(), offsets.len()))
+            .collect();
+        counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+        counts
+    }
+}
@@ -0,0 +1,369 @@
+use std::sync::{Arc, RwLock};
+
+use crate::index::incremental::Documents;
+use crate::index::segments::TieredMergePolicy;
+use crate::index::Index;
+
+use super::*;
+
+// Lets many threads search an index while a single IndexWriter adds articles to it.
+//
+// What is published is a list of immutable segments: the index the writer started from, and then
+// one index per commit with the articles added since the one before. The list is an Arc behind a
+// lock, which is only held long enough to copy or swap the Arc. A search takes a snapshot and runs
+// without the lock, so it sees the index exactly as it was at one commit, however many commits
+// happen while it runs. The writer adds to a segment of its own, and a commit copies the list of
+// Arcs, and only the segments that the merge policy merges. Merging small segments keeps the
+// number of segments searches go through logarithmic in the number of articles.
+
+// The segments of the index as of one commit, in the order of their articles
+pub struct Snapshot<T> {
+    segments: Vec<Arc<Index<T>>>,
+}
+
+impl<T> Snapshot<T> {
+    pub fn segments(&self) -> &[Arc<Index<T>>] {
+        &self.segments
+    }
+}
+
+// Every segment holds later articles than the ones before it, so the titles found in each of them
+// can simply be appended
+impl<T> Search for Snapshot<T>
+where
+    Index<T>: Search,
+{
+    fn search(&self, query: &Query) -> ArticleTitles {
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.search(query))
+            .collect()
+    }
+}
+
+pub struct IndexReader<T> {
+    current: Arc<RwLock<Arc<Snapshot<T>>>>,
+}
+
+impl<T> Clone for IndexReader<T> {
+    fn clone(&self) -> Self {
+        IndexReader {
+            current: Arc::clone(&self.current),
+        }
+    }
+}
+
+impl<T> IndexReader<T> {
+    // The index as of the last commit. It doesn't change while it is held.
+    pub fn snapshot(&self) -> Arc<Snapshot<T>> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+}
+
+impl<T> Search for IndexReader<T>
+where
+    Index<T>: Search,
+{
+    fn search(&self, query: &Query) -> ArticleTitles {
+        self.snapshot().search(query)
+    }
+}
+
+// There is only ever one writer per index, since it can't be cloned
+pub struct IndexWriter<T> {
+    current: Arc<RwLock<Arc<Snapshot<T>>>>,
+    // The articles added since the last commit
+    pending: Index<T>,
+    // The number of committed articles, which the articles of the pending segment come after
+    n_committed: usize,
+    policy: TieredMergePolicy,
+}
+
+impl<T: Default> IndexWriter<T>
+where
+    Index<T>: Documents,
+{
+    pub fn new(index: Index<T>) -> Self {
+        IndexWriter::with_merge_policy(index, TieredMergePolicy::default())
+    }
+
+    pub fn with_merge_policy(index: Index<T>, policy: TieredMergePolicy) -> Self {
+        assert!(policy.segment_size > 0 && policy.merge_factor > 1);
+        let n_committed = index.n_articles();
+        let snapshot = Snapshot {
+            segments: vec![Arc::new(index)],
+        };
+        IndexWriter {
+            current: Arc::new(RwLock::new(Arc::new(snapshot))),
+            pending: IndexWriter::empty_segment(),
+            n_committed,
+            policy,
+        }
+    }
+
+    fn empty_segment() -> Index<T> {
+        Index {
+            database: T::default(),
+            article_titles: Vec::new(),
+        }
+    }
+
+    pub fn reader(&self) -> IndexReader<T> {
+        IndexReader {
+            current: Arc::clone(&self.current),
+        }
+    }
+
+    // Returns the article number of the new article. Readers only see it after the next commit.
+    pub fn add_document(&mut self, title: &str, contents: &[String]) -> usize {
+        self.n_committed + self.pending.add_document(title, contents)
+    }
+
+    // The number of articles added since the last commit
+    pub fn n_pending(&self) -> usize {
+        self.pending.n_articles()
+    }
+
+    // Publishes every article added so far to the readers at once
+    pub fn commit(&mut self) {
+        if self.n_pending() == 0 {
+            return;
+        }
+        self.n_committed += self.n_pending();
+        let segment = Arc::new(std::mem::replace(
+            &mut self.pending,
+            IndexWriter::empty_segment(),
+        ));
+        // This is the only writer, so the list can't change between reading and replacing it
+        let mut segments = self.current.read().unwrap().segments.clone();
+        segments.push(segment);
+        self.merge_segments(&mut segments);
+        *self.current.write().unwrap() = Arc::new(Snapshot { segments });
+    }
+
+    // Merges segments until the merge policy finds nothing more to merge. Segments without
+    // articles are dropped.
+    fn merge_segments(&self, segments: &mut Vec<Arc<Index<T>>>) {
+        loop {
+            let sizes: Vec<usize> = segments.iter().map(|s| s.n_articles()).collect();
+            let range = match self.policy.find_merge(&sizes) {
+                Some(range) => range,
+                None => return,
+            };
+            let mut merged = IndexWriter::empty_segment();
+            for segment in &segments[range.clone()] {
+                merged.append(segment);
+            }
+            let merged = Some(Arc::new(merged)).filter(|m| m.n_articles() > 0);
+            segments.splice(range, merged);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::thread;
+
+    use crate::index::index9_0::TrieLin;
+    use crate::index::index9_1::Trie;
+
+    use super::*;
+
+    fn words(s: &str) -> Vec<String> {
+        s.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    fn query(search_string: &str, search_type: SearchType) -> Query {
+        Query {
+            search_string: search_string.to_string(),
+            search_type,
+        }
+    }
+
+    fn boolean(search_string: &str) -> Query {
+        query(
+            search_string,
+            SearchType::BooleanSearch("Naive".to_string()),
+        )
+    }
+
+    fn empty_index8() -> Index<HashMap<String, Vec<usize>>> {
+        Index {
+            database: HashMap::new(),
+            article_titles: Vec::new(),
+        }
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn handles_can_be_shared_between_threads() {
+        assert_send_sync::<IndexReader<HashMap<String, Vec<usize>>>>();
+        assert_send_sync::<IndexReader<Trie>>();
+        assert_send_sync::<IndexReader<TrieLin>>();
+        assert_send_sync::<IndexWriter<HashMap<String, Vec<usize>>>>();
+        assert_send_sync::<IndexWriter<Trie>>();
+    }
+
+    #[test]
+    fn readers_only_see_committed_articles() {
+        let mut writer = IndexWriter::new(empty_index8());
+        let reader = writer.reader();
+        assert_eq!(writer.add_document("A", &words("mutual aid")), 0);
+        assert_eq!(writer.n_pending(), 1);
+        assert!(reader.search(&boolean("mutual")).is_empty());
+
+        writer.commit();
+        assert_eq!(writer.n_pending(), 0);
+        let before = reader.snapshot();
+        assert_eq!(reader.search(&boolean("mutual")), vec!["A"]);
+
+        writer.add_document("B", &words("mutual benefit"));
+        writer.commit();
+        assert_eq!(reader.search(&boolean("mutual")), vec!["A", "B"]);
+        assert_eq!(reader.clone().search(&boolean("benefit")), vec!["B"]);
+
+        // A snapshot taken earlier keeps the index as it was
+        assert_eq!(before.search(&boolean("mutual")), vec!["A"]);
+    }
+
+    #[test]
+    fn commits_share_the_segments_before_them() {
+        let mut writer = IndexWriter::new(empty_index8());
+        let reader = writer.reader();
+        writer.add_document("A", &words("mutual aid"));
+        writer.commit();
+        let before = reader.snapshot();
+        assert_eq!(writer.add_document("B", &words("mutual benefit")), 1);
+        assert_eq!(writer.add_document("C", &words("aid")), 2);
+        writer.commit();
+        // Committing nothing publishes nothing new
+        writer.commit();
+
+        // The empty index the writer started from is dropped by the first commit
+        let after = reader.snapshot();
+        assert_eq!(before.segments().len(), 1);
+        assert_eq!(after.segments().len(), 2);
+        for (old, new) in before.segments().iter().zip(after.segments()) {
+            assert!(Arc::ptr_eq(old, new));
+        }
+        assert_eq!(reader.search(&boolean("aid")), vec!["A", "C"]);
+        assert_eq!(reader.search(&boolean("!benefit")), vec!["A", "C"]);
+    }
+
+    #[test]
+    fn commits_merge_small_segments() {
+        let policy = TieredMergePolicy {
+            segment_size: 2,
+            merge_factor: 3,
+        };
+        let mut writer = IndexWriter::with_merge_policy(empty_index8(), policy);
+        let mut tries = IndexWriter::with_merge_policy(
+            Index {
+                database: TrieLin::new(),
+                article_titles: Vec::new(),
+            },
+            policy,
+        );
+        let reader = writer.reader();
+        let tries_reader = tries.reader();
+        let mut expected = Vec::new();
+        for i in 0..30 {
+            let title = format!("article {}", i);
+            let contents = words(&format!("common word{} parity{}", i, i % 2));
+            writer.add_document(&title, &contents);
+            tries.add_document(&title, &contents);
+            writer.commit();
+            tries.commit();
+            expected.push(title);
+
+            let sizes: Vec<usize> = reader
+                .snapshot()
+                .segments()
+                .iter()
+                .map(|s| s.n_articles())
+                .collect();
+            assert_eq!(sizes.iter().sum::<usize>(), i + 1);
+            // Fewer than merge_factor segments are left in every tier
+            let max_segments = (policy.merge_factor - 1) * (policy.tier(30) + 1);
+            assert!(sizes.len() <= max_segments, "{:?}", sizes);
+            assert_eq!(reader.search(&boolean("common")), expected);
+            assert_eq!(
+                tries_reader.search(&query("parity0", SearchType::SingleWordSearch)),
+                expected.iter().step_by(2).cloned().collect::<Vec<_>>()
+            );
+        }
+        let mut word2 = vec!["article 2".to_string()];
+        word2.extend((20..30).map(|i| format!("article {}", i)));
+        assert_eq!(
+            tries_reader.search(&query("word2*", SearchType::PrefixSearch)),
+            word2
+        );
+    }
+
+    #[test]
+    fn writer_adds_to_tries() {
+        let mut writer = IndexWriter::new(Index {
+            database: Trie::new(),
+            article_titles: Vec::new(),
+        });
+        let reader = writer.reader();
+        writer.add_document("A", &words("anarchy"));
+        writer.add_document("B", &words("anarchism"));
+        writer.commit();
+        let old = reader.snapshot();
+        writer.add_document("C", &words("anarchist"));
+        writer.commit();
+
+        let prefix = query("anarch*", SearchType::PrefixSearch);
+        assert_eq!(old.search(&prefix), vec!["A", "B"]);
+        assert_eq!(reader.search(&prefix), vec!["A", "B", "C"]);
+        assert_eq!(
+            reader.search(&query("anarchist", SearchType::SingleWordSearch)),
+            vec!["C"]
+        );
+    }
+
+    #[test]
+    fn searches_during_writes_see_whole_commits() {
+        const N_ARTICLES: usize = 200;
+        const BATCH: usize = 4;
+        let mut writer = IndexWriter::new(empty_index8());
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let reader = writer.reader();
+                thread::spawn(move || {
+                    let mut last_seen = 0;
+                    while last_seen < N_ARTICLES {
+                        let snapshot = reader.snapshot();
+                        let found = snapshot.search(&boolean("common"));
+                        // Every commit adds a whole batch, and the articles the snapshot has are
+                        // exactly the ones found
+                        assert_eq!(found.len() % BATCH, 0);
+                        assert!(found.len() >= last_seen);
+                        let expected: Vec<String> =
+                            (0..found.len()).map(|i| format!("article {}", i)).collect();
+                        assert_eq!(found, expected);
+                        assert_eq!(snapshot.search(&boolean("!common")), Vec::<String>::new());
+                        last_seen = found.len();
+                    }
+                })
+            })
+            .collect();
+
+        for i in 0..N_ARTICLES {
+            writer.add_document(
+                &format!("article {}", i),
+                &words(&format!("common word{}", i)),
+            );
+            if writer.n_pending() == BATCH {
+                writer.commit();
+            }
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+}
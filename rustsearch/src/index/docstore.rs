@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use super::{char_offsets, Index, Occurrences};
+
+// The text of the articles, kept in the index for the searches that verify their candidates
+// against it. Articles are appended to blocks of about block_size bytes, and every full block is
+// compressed with LZ4 on its own, so reading an article only decompresses its block. The last
+// few blocks read are kept decompressed, since the candidates of a query are read in increasing
+// order and neighbouring articles share a block.
+
+pub const BLOCK_SIZE: usize = 64 * 1024;
+pub const CACHE_BLOCKS: usize = 16;
+
+// The decompressed blocks read last, at most capacity of them. When it is full, the block that
+// was read the longest ago is dropped. There are few blocks, so finding it is a scan.
+struct BlockCache {
+    capacity: usize,
+    blocks: HashMap<usize, (Arc<[u8]>, u64)>,
+    clock: u64,
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity,
+            blocks: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, block: usize) -> Option<Arc<[u8]>> {
+        self.clock += 1;
+        let (data, last_read) = self.blocks.get_mut(&block)?;
+        *last_read = self.clock;
+        Some(data.clone())
+    }
+
+    fn insert(&mut self, block: usize, data: Arc<[u8]>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.blocks.len() >= self.capacity {
+            let oldest = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (_, last_read))| *last_read)
+                .map(|(&b, _)| b)
+                .unwrap();
+            self.blocks.remove(&oldest);
+        }
+        self.clock += 1;
+        self.blocks.insert(block, (data, self.clock));
+    }
+}
+
+pub struct DocumentStore {
+    block_size: usize,
+    // Compressed, with the decompressed size in front
+    blocks: Vec<Box<[u8]>>,
+    // For every article, its block and where it is in the decompressed block
+    articles: Vec<(usize, usize, usize)>,
+    // The block being filled, which is compressed when it is full
+    open_block: Vec<u8>,
+    cache: Mutex<BlockCache>,
+}
+
+impl Default for DocumentStore {
+    fn default() -> Self {
+        Self::new(BLOCK_SIZE, CACHE_BLOCKS)
+    }
+}
+
+// A copy starts with an empty cache
+impl Clone for DocumentStore {
+    fn clone(&self) -> Self {
+        DocumentStore {
+            block_size: self.block_size,
+            blocks: self.blocks.clone(),
+            articles: self.articles.clone(),
+            open_block: self.open_block.clone(),
+            cache: Mutex::new(BlockCache::new(self.cache.lock().unwrap().capacity)),
+        }
+    }
+}
+
+impl DocumentStore {
+    pub fn new(block_size: usize, cache_blocks: usize) -> Self {
+        DocumentStore {
+            block_size,
+            blocks: Vec::new(),
+            articles: Vec::new(),
+            open_block: Vec::new(),
+            cache: Mutex::new(BlockCache::new(cache_blocks)),
+        }
+    }
+
+    pub fn from_articles<S: AsRef<[u8]>>(articles: &[S]) -> Self {
+        let mut store = Self::default();
+        for article in articles {
+            store.push(article.as_ref());
+        }
+        store
+    }
+
+    // Adds the next article, whose number is the number of articles before it
+    pub fn push(&mut self, article: &[u8]) {
+        let start = self.open_block.len();
+        self.open_block.extend_from_slice(article);
+        self.articles
+            .push((self.blocks.len(), start, self.open_block.len()));
+        if self.open_block.len() >= self.block_size {
+            let compressed = lz4_flex::block::compress_prepend_size(&self.open_block);
+            self.blocks.push(compressed.into_boxed_slice());
+            self.open_block.clear();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.articles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.articles.is_empty()
+    }
+
+    pub fn n_blocks(&self) -> usize {
+        self.blocks.len() + !self.open_block.is_empty() as usize
+    }
+
+    // The bytes kept for the text, the open block included
+    pub fn stored_size(&self) -> usize {
+        self.blocks.iter().map(|b| b.len()).sum::<usize>() + self.open_block.len()
+    }
+
+    fn block(&self, block: usize) -> Arc<[u8]> {
+        if let Some(data) = self.cache.lock().unwrap().get(block) {
+            return data;
+        }
+        let data: Arc<[u8]> = lz4_flex::block::decompress_size_prepended(&self.blocks[block])
+            .expect("A block of the document store is corrupt")
+            .into();
+        self.cache.lock().unwrap().insert(block, data.clone());
+        data
+    }
+
+    pub fn get(&self, article: usize) -> Option<Vec<u8>> {
+        let &(block, start, end) = self.articles.get(article)?;
+        if block == self.blocks.len() {
+            return Some(self.open_block[start..end].to_vec());
+        }
+        Some(self.block(block)[start..end].to_vec())
+    }
+}
+
+// An index database together with the text of its articles. It derefs to the database, so the
+// postings are used as before.
+#[derive(Clone)]
+pub struct Stored<T> {
+    pub postings: T,
+    pub documents: DocumentStore,
+}
+
+impl<T> Stored<T> {
+    pub fn new(postings: T, documents: DocumentStore) -> Self {
+        Stored {
+            postings,
+            documents,
+        }
+    }
+}
+
+impl<T> Deref for Stored<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.postings
+    }
+}
+
+impl<T> Index<Stored<T>> {
+    // The cleaned text of the article, with the words joined by spaces
+    pub fn article_text(&self, art_no: usize) -> String {
+        String::from_utf8(self.article_bytes(art_no)).expect("Articles are stored as UTF-8")
+    }
+
+    // The same text as bytes, for the searches that match bytes, without checking it is UTF-8
+    pub fn article_bytes(&self, art_no: usize) -> Vec<u8> {
+        self.database
+            .documents
+            .get(art_no)
+            .unwrap_or_else(|| panic!("Article number {} not found in the index", art_no))
+    }
+
+    // The occurrences with offsets in characters of the article text instead of bytes
+    pub fn char_occurrences(&self, occurrences: Occurrences) -> Occurrences {
+        occurrences
+            .into_iter()
+            .map(|(art_no, offsets)| {
+                let text = self.article_bytes(art_no);
+                (art_no, char_offsets(&text, &offsets))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn articles_across_blocks() {
+        let articles: Vec<String> = (0..200)
+            .map(|i| format!("article {} {}", i, "word ".repeat(i % 7)))
+            .collect();
+        let mut store = DocumentStore::new(100, 2);
+        for article in &articles {
+            store.push(article.as_bytes());
+        }
+        assert_eq!(store.len(), 200);
+        assert!(store.n_blocks() > 10);
+        // Out of order, so blocks are dropped from the cache and read again
+        for i in (0..200).rev().chain(0..200).step_by(3) {
+            assert_eq!(store.get(i).unwrap(), articles[i].as_bytes(), "{}", i);
+        }
+        assert_eq!(store.get(200), None);
+        assert_eq!(store.clone().get(199).unwrap(), articles[199].as_bytes());
+    }
+
+    #[test]
+    fn text_is_compressed() {
+        let article = "the state is a political philosophy and the state ".repeat(100);
+        let store = DocumentStore::from_articles(&vec![article.clone(); 50]);
+        assert!(store.stored_size() * 5 < 50 * article.len());
+        assert_eq!(store.get(31).unwrap(), article.as_bytes());
+        assert_eq!(DocumentStore::default().get(0), None);
+    }
+
+    #[test]
+    fn occurrences_in_characters() {
+        let articles = ["Ça dorada and ÇA DORADA", "ca dorada"];
+        let index = Index {
+            database: Stored::new((), DocumentStore::from_articles(&articles)),
+            article_titles: vec!["article 0".to_string(), "article 1".to_string()],
+        };
+        assert_eq!(
+            index.char_occurrences(vec![(0, vec![0, 4, 17]), (1, vec![3])]),
+            vec![(0, vec![0, 3, 15]), (1, vec![3])]
+        );
+        assert_eq!(char_offsets("øre".as_bytes(), &[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn least_recently_read_block_is_dropped() {
+        let mut cache = BlockCache::new(2);
+        cache.insert(0, Arc::from(&b"zero"[..]));
+        cache.insert(1, Arc::from(&b"one"[..]));
+        assert!(cache.get(0).is_some());
+        cache.insert(2, Arc::from(&b"two"[..]));
+        assert!(cache.get(1).is_none());
+        assert_eq!(&*cache.get(0).unwrap(), b"zero");
+        assert_eq!(&*cache.get(2).unwrap(), b"two");
+
+        let mut cache = BlockCache::new(0);
+        cache.insert(0, Arc::from(&b"zero"[..]));
+        assert!(cache.get(0).is_none());
+    }
+}
@@ -55,6 +55,14 @@ pub fn ast_to_string(node: AstNode) -> String {
             x.push_str(&y);
             x
         }
+        AstNode::Binary(BinaryOp::Near { distance, ordered }, left_child, right_child) => {
+            let mut x = ast_to_string(*left_child);
+            let y = ast_to_string(*right_child);
+            let op = if ordered { "onear" } else { "near" };
+            x.push_str(&format!(" {}/{} ", op, distance));
+            x.push_str(&y);
+            x
+        }
         AstNode::Name(word) => word,
     }
 }
@@ -97,7 +105,12 @@ pub fn gen_a_lot_of_runs_bool(file_path: String, number: usize) -> Vec<Vec<Strin
 
     let index8 = Index::index8(&config).unwrap();
 
-    let mut database_words = index8.database.keys().collect::<Vec<&String>>();
+    // Only words of the body, not the "field:word" terms of the other fields
+    let mut database_words = index8
+        .database
+        .keys()
+        .filter(|word| !word.contains(':'))
+        .collect::<Vec<&String>>();
     database_words.sort();
 
     let boolean_queries = (0..=7)
@@ -119,7 +132,12 @@ pub fn gen_a_lot_of_runs_tries(file_path: String, number: usize, prefix: bool) -
 
     let index8 = Index::index8(&config).unwrap();
 
-    let mut database_words = index8.database.keys().collect::<Vec<&String>>();
+    // Only words of the body, not the "field:word" terms of the other fields
+    let mut database_words = index8
+        .database
+        .keys()
+        .filter(|word| !word.contains(':'))
+        .collect::<Vec<&String>>();
     database_words.sort();
 
     let search_queries = match prefix {
@@ -0,0 +1,682 @@
+use std::collections::HashMap;
+
+use crate::helpers::article_terms;
+use crate::index::index9_0::{TrieLin, TrieNodeLin};
+use crate::index::index9_1::{Trie, TrieNode};
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::*;
+
+// The indices that can take new articles after they are built. Article numbers are handed out in
+// increasing order, so appending a new article keeps every postings list sorted.
+pub trait Documents {
+    fn add_document(&mut self, title: &str, contents: &[String]) -> usize;
+
+    // The article numbers matching the query, before deleted articles are filtered out
+    fn matching_articles(&self, query: &Query) -> Vec<usize>;
+
+    // Whether matching_articles can answer queries of this kind
+    fn supports(&self, search_type: &SearchType) -> bool;
+
+    // Renumbers every article to new_ids[article] and drops the articles mapped to None.
+    // The new numbers must keep the order of the old ones.
+    fn purge(&mut self, new_ids: &[Option<usize>]);
+
+    // Adds the articles of other after the ones of the index, in the same order
+    fn append(&mut self, other: &Self);
+
+    fn n_articles(&self) -> usize;
+
+    fn title(&self, article: usize) -> &str;
+}
+
+// An index that can be changed after it is built. Deleted articles are only marked, and filtered
+// out of the results at query time, until compact removes them for good.
+pub struct IncrementalIndex<T> {
+    index: Index<T>,
+    deleted: Vec<bool>,
+    n_deleted: usize,
+}
+
+impl<T> IncrementalIndex<T>
+where
+    Index<T>: Documents,
+{
+    pub fn new(index: Index<T>) -> Self {
+        IncrementalIndex {
+            deleted: vec![false; index.n_articles()],
+            index,
+            n_deleted: 0,
+        }
+    }
+
+    pub fn get_index(&self) -> &Index<T> {
+        &self.index
+    }
+
+    // Returns the article number of the new article
+    pub fn add_document(&mut self, title: &str, contents: &[String]) -> usize {
+        self.deleted.push(false);
+        self.index.add_document(title, contents)
+    }
+
+    // Returns false if there is no such article, or it is already deleted
+    pub fn delete_document(&mut self, article: usize) -> bool {
+        match self.deleted.get_mut(article) {
+            Some(deleted) if !*deleted => {
+                *deleted = true;
+                self.n_deleted += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Replaces the article with a new version, which gets a new article number
+    pub fn update_document(
+        &mut self,
+        article: usize,
+        title: &str,
+        contents: &[String],
+    ) -> Option<usize> {
+        match self.delete_document(article) {
+            true => Some(self.add_document(title, contents)),
+            false => None,
+        }
+    }
+
+    pub fn is_deleted(&self, article: usize) -> bool {
+        self.deleted.get(article).copied().unwrap_or(false)
+    }
+
+    pub fn n_deleted(&self) -> usize {
+        self.n_deleted
+    }
+
+    pub fn search_articles(&self, query: &Query) -> Vec<usize> {
+        let mut articles = self.index.matching_articles(query);
+        articles.retain(|&a| !self.deleted[a]);
+        articles
+    }
+
+    // Removes the deleted articles from the index and numbers the rest from 0 again. Returns the
+    // new number of every old article, or None if it was deleted.
+    pub fn compact(&mut self) -> Vec<Option<usize>> {
+        let mut next = 0;
+        let new_ids: Vec<Option<usize>> = self
+            .deleted
+            .iter()
+            .map(|&deleted| match deleted {
+                true => None,
+                false => {
+                    next += 1;
+                    Some(next - 1)
+                }
+            })
+            .collect();
+        self.index.purge(&new_ids);
+        self.deleted = vec![false; next];
+        self.n_deleted = 0;
+        new_ids
+    }
+}
+
+impl<T> Search for IncrementalIndex<T>
+where
+    Index<T>: Documents,
+{
+    fn search(&self, query: &Query) -> ArticleTitles {
+        self.search_articles(query)
+            .into_iter()
+            .map(|a| self.index.title(a).to_string())
+            .collect()
+    }
+}
+
+fn purge_titles(article_titles: &mut Vec<String>, new_ids: &[Option<usize>]) {
+    let mut i = 0;
+    article_titles.retain(|_| {
+        i += 1;
+        new_ids[i - 1].is_some()
+    });
+}
+
+fn purge_articlevec(articles: &mut Vec<usize>, new_ids: &[Option<usize>]) {
+    *articles = articles.iter().filter_map(|&a| new_ids[a]).collect();
+}
+
+fn append_titles(article_titles: &mut Vec<String>, other: &[String]) -> usize {
+    let offset = article_titles.len();
+    article_titles.extend_from_slice(other);
+    offset
+}
+
+fn append_articlevec(articles: &mut Option<Vec<usize>>, other: &Option<Vec<usize>>, offset: usize) {
+    if let Some(other) = other {
+        articles
+            .get_or_insert_with(Vec::new)
+            .extend(other.iter().map(|&a| a + offset));
+    }
+}
+
+fn bitvec_to_articles(bitvec: Vec<usize>, n_titles: usize) -> Vec<usize> {
+    let arch_bits = usize::BITS as usize;
+    let mut articles: Vec<usize> = Vec::new();
+    for (i, word) in bitvec.into_iter().enumerate() {
+        for bit in 0..arch_bits {
+            if word & (1 << bit) != 0 && i * arch_bits + bit < n_titles {
+                articles.push(i * arch_bits + bit);
+            }
+        }
+    }
+    articles
+}
+
+impl Documents for Index<HashMap<String, Vec<usize>>> {
+    fn add_document(&mut self, title: &str, contents: &[String]) -> usize {
+        let article = self.article_titles.len();
+        self.article_titles.push(title.to_string());
+        for word in article_terms(title, contents) {
+            let v = self.database.entry(word).or_default();
+            if v.last() != Some(&article) {
+                v.push(article)
+            }
+        }
+        article
+    }
+
+    fn matching_articles(&self, query: &Query) -> Vec<usize> {
+        match &query.search_type {
+            SearchType::SingleWordSearch => self
+                .database
+                .get(&query.search_string)
+                .cloned()
+                .unwrap_or_default(),
+            SearchType::BooleanSearch(_) => match Expr::from_string(&query.search_string) {
+                Ok(Expr(ExprData::HasNodes(node))) => self.evaluate_syntax_tree_naive(node),
+                _ => Vec::new(), // Either an error or the expression has no nodes
+            },
+            _ => unimplemented!(),
+        }
+    }
+
+    fn supports(&self, search_type: &SearchType) -> bool {
+        matches!(
+            search_type,
+            SearchType::SingleWordSearch | SearchType::BooleanSearch(_)
+        )
+    }
+
+    fn purge(&mut self, new_ids: &[Option<usize>]) {
+        purge_titles(&mut self.article_titles, new_ids);
+        self.database.retain(|_, articles| {
+            purge_articlevec(articles, new_ids);
+            !articles.is_empty()
+        });
+    }
+
+    fn append(&mut self, other: &Self) {
+        let offset = append_titles(&mut self.article_titles, &other.article_titles);
+        for (word, articles) in &other.database {
+            self.database
+                .entry(word.clone())
+                .or_default()
+                .extend(articles.iter().map(|&a| a + offset));
+        }
+    }
+
+    fn n_articles(&self) -> usize {
+        self.article_titles.len()
+    }
+
+    fn title(&self, article: usize) -> &str {
+        &self.article_titles[article]
+    }
+}
+
+// Returns whether the node is still needed, i.e. it is final or has children
+fn purge_trie_node(node: &mut TrieNode, new_ids: &[Option<usize>]) -> bool {
+    if let Some(articles) = node.article_vec.as_mut() {
+        purge_articlevec(articles, new_ids);
+        if articles.is_empty() {
+            node.article_vec = None;
+        }
+    }
+    node.children_map
+        .retain(|_, child| purge_trie_node(child, new_ids));
+    node.article_vec.is_some() || !node.children_map.is_empty()
+}
+
+fn append_trie_node(node: &mut TrieNode, other: &TrieNode, offset: usize) {
+    append_articlevec(&mut node.article_vec, &other.article_vec, offset);
+    for (&c, other_child) in &other.children_map {
+        let child = node.children_map.entry(c).or_insert_with(TrieNode::new);
+        append_trie_node(child, other_child, offset);
+    }
+}
+
+impl Documents for Index<Trie> {
+    fn add_document(&mut self, title: &str, contents: &[String]) -> usize {
+        let article = self.article_titles.len();
+        self.article_titles.push(title.to_string());
+        // Keeps the bitvectors large enough for the new article
+        self.database.n_titles = self.article_titles.len();
+        for word in contents {
+            self.database.insert(word, article);
+        }
+        article
+    }
+
+    fn matching_articles(&self, query: &Query) -> Vec<usize> {
+        if self.article_titles.is_empty() {
+            return Vec::new();
+        }
+        let bitvec = match &query.search_type {
+            SearchType::SingleWordSearch => self.database.find_single(&query.search_string),
+            SearchType::PrefixSearch => self.database.find_prefix(&query.search_string),
+            _ => unimplemented!(),
+        };
+        bitvec_to_articles(bitvec, self.database.n_titles)
+    }
+
+    fn supports(&self, search_type: &SearchType) -> bool {
+        matches!(
+            search_type,
+            SearchType::SingleWordSearch | SearchType::PrefixSearch
+        )
+    }
+
+    fn purge(&mut self, new_ids: &[Option<usize>]) {
+        purge_titles(&mut self.article_titles, new_ids);
+        purge_trie_node(&mut self.database.root, new_ids);
+        self.database.n_titles = self.article_titles.len();
+    }
+
+    fn append(&mut self, other: &Self) {
+        let offset = append_titles(&mut self.article_titles, &other.article_titles);
+        append_trie_node(&mut self.database.root, &other.database.root, offset);
+        self.database.n_titles = self.article_titles.len();
+    }
+
+    fn n_articles(&self) -> usize {
+        self.article_titles.len()
+    }
+
+    fn title(&self, article: usize) -> &str {
+        &self.article_titles[article]
+    }
+}
+
+fn purge_trie_lin_node(node: &mut TrieNodeLin, new_ids: &[Option<usize>]) -> bool {
+    if let Some(articles) = node.article_vec.as_mut() {
+        purge_articlevec(articles, new_ids);
+        if articles.is_empty() {
+            node.article_vec = None;
+        }
+    }
+    node.children_vec
+        .retain_mut(|(_, child)| purge_trie_lin_node(child, new_ids));
+    node.article_vec.is_some() || !node.children_vec.is_empty()
+}
+
+fn append_trie_lin_node(node: &mut TrieNodeLin, other: &TrieNodeLin, offset: usize) {
+    append_articlevec(&mut node.article_vec, &other.article_vec, offset);
+    for (c, other_child) in &other.children_vec {
+        let i = match node.children_vec.iter().position(|(d, _)| d == c) {
+            Some(i) => i,
+            None => {
+                node.insert_child(*c);
+                node.children_vec.len() - 1
+            }
+        };
+        append_trie_lin_node(&mut node.children_vec[i].1, other_child, offset);
+    }
+}
+
+impl Documents for Index<TrieLin> {
+    fn add_document(&mut self, title: &str, contents: &[String]) -> usize {
+        let article = self.article_titles.len();
+        self.article_titles.push(title.to_string());
+        // Keeps the bitvectors large enough for the new article
+        self.database.n_titles = self.article_titles.len();
+        for word in contents {
+            self.database.insert(word, article);
+        }
+        article
+    }
+
+    fn matching_articles(&self, query: &Query) -> Vec<usize> {
+        if self.article_titles.is_empty() {
+            return Vec::new();
+        }
+        let bitvec = match &query.search_type {
+            SearchType::SingleWordSearch => self.database.find_single(&query.search_string),
+            SearchType::PrefixSearch => self.database.find_prefix(&query.search_string),
+            _ => unimplemented!(),
+        };
+        bitvec_to_articles(bitvec, self.database.n_titles)
+    }
+
+    fn supports(&self, search_type: &SearchType) -> bool {
+        matches!(
+            search_type,
+            SearchType::SingleWordSearch | SearchType::PrefixSearch
+        )
+    }
+
+    fn purge(&mut self, new_ids: &[Option<usize>]) {
+        purge_titles(&mut self.article_titles, new_ids);
+        purge_trie_lin_node(&mut self.database.root, new_ids);
+        self.database.n_titles = self.article_titles.len();
+    }
+
+    fn append(&mut self, other: &Self) {
+        let offset = append_titles(&mut self.article_titles, &other.article_titles);
+        append_trie_lin_node(&mut self.database.root, &other.database.root, offset);
+        self.database.n_titles = self.article_titles.len();
+    }
+
+    fn n_articles(&self) -> usize {
+        self.article_titles.len()
+    }
+
+    fn title(&self, article: usize) -> &str {
+        &self.article_titles[article]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::*;
+    use crate::index::gen_query::{gen_a_lot_of_runs_bool, gen_a_lot_of_runs_tries};
+
+    const FILE: &str = "data/WestburyLab.wikicorp.201004_100KB.txt";
+
+    fn words(s: &str) -> Vec<String> {
+        s.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    fn query(search_string: &str, search_type: SearchType) -> Query {
+        Query {
+            search_string: search_string.to_string(),
+            search_type,
+        }
+    }
+
+    fn boolean(search_string: &str) -> Query {
+        query(
+            search_string,
+            SearchType::BooleanSearch("Naive".to_string()),
+        )
+    }
+
+    fn empty_index8() -> IncrementalIndex<HashMap<String, Vec<usize>>> {
+        IncrementalIndex::new(Index {
+            database: HashMap::new(),
+            article_titles: Vec::new(),
+        })
+    }
+
+    fn empty_index9() -> IncrementalIndex<Trie> {
+        IncrementalIndex::new(Index {
+            database: Trie::new(),
+            article_titles: Vec::new(),
+        })
+    }
+
+    fn articles() -> Vec<(String, Vec<String>)> {
+        read_and_clean_file_to_iter(&Config {
+            file_path: FILE.to_string(),
+            indexno: "8".to_string(),
+        })
+        .unwrap()
+        .into_iter()
+        .filter(|(title, _)| !title.is_empty())
+        .collect()
+    }
+
+    #[test]
+    fn add_delete_and_update_in_index8() {
+        let mut index = empty_index8();
+        let a = index.add_document("A", &words("mutual aid society"));
+        let b = index.add_document("B", &words("mutual benefit"));
+        assert_eq!((a, b), (0, 1));
+        assert_eq!(index.search(&boolean("mutual")), vec!["A", "B"]);
+
+        assert!(index.delete_document(a));
+        assert!(!index.delete_document(a));
+        assert!(!index.delete_document(7));
+        assert_eq!(index.search(&boolean("mutual")), vec!["B"]);
+        // Negations don't bring deleted articles back
+        assert_eq!(index.search(&boolean("!benefit")), Vec::<String>::new());
+
+        let c = index.update_document(b, "B v2", &words("state")).unwrap();
+        assert_eq!(c, 2);
+        assert_eq!(index.search(&boolean("mutual")), Vec::<String>::new());
+        assert_eq!(index.search(&boolean("state")), vec!["B v2"]);
+        assert_eq!(index.update_document(b, "B v3", &words("state")), None);
+        assert_eq!(
+            index.search(&query("state", SearchType::SingleWordSearch)),
+            vec!["B v2"]
+        );
+    }
+
+    #[test]
+    fn added_titles_are_searchable_by_field() {
+        let mut index = empty_index8();
+        index.add_document("Mutual aid", &words("a book"));
+        index.add_document("Book", &words("mutual"));
+        assert_eq!(index.search(&boolean("title:Book")), vec!["Book"]);
+        assert_eq!(index.search(&boolean("book")), vec!["Mutual aid"]);
+        assert_eq!(
+            index.search(&boolean("title:aid | mutual")),
+            vec!["Mutual aid", "Book"]
+        );
+    }
+
+    #[test]
+    fn compaction_purges_deleted_articles() {
+        let mut index = empty_index8();
+        index.add_document("A", &words("one two"));
+        index.add_document("B", &words("two three"));
+        index.add_document("C", &words("three four"));
+        index.delete_document(1);
+        assert_eq!(index.n_deleted(), 1);
+
+        assert_eq!(index.compact(), vec![Some(0), None, Some(1)]);
+        assert_eq!(index.n_deleted(), 0);
+        assert_eq!(index.get_index().article_titles, vec!["A", "C"]);
+        assert_eq!(index.get_index().database.get("three"), Some(&vec![1]));
+        assert_eq!(index.get_index().database.get("two"), Some(&vec![0]));
+        assert_eq!(index.get_index().database.get("five"), None);
+        assert_eq!(index.search(&boolean("!one")), vec!["C"]);
+
+        // New articles continue after the compacted ones
+        assert_eq!(index.add_document("D", &words("four")), 2);
+        assert_eq!(index.search(&boolean("four")), vec!["C", "D"]);
+    }
+
+    #[test]
+    fn add_delete_and_compact_in_index9() {
+        let mut index = empty_index9();
+        assert_eq!(
+            index.search(&query("word*", SearchType::PrefixSearch)),
+            Vec::<String>::new()
+        );
+        index.add_document("A", &words("word world"));
+        index.add_document("B", &words("would"));
+        // Bitvectors have to grow past one word
+        for i in 0..100 {
+            index.add_document(&format!("filler {}", i), &words("filler"));
+        }
+        index.add_document("Z", &words("wordy"));
+        assert_eq!(
+            index.search(&query("wor*", SearchType::PrefixSearch)),
+            vec!["A", "Z"]
+        );
+
+        index.delete_document(0);
+        assert_eq!(
+            index.search(&query("wor*", SearchType::PrefixSearch)),
+            vec!["Z"]
+        );
+        assert_eq!(
+            index.search(&query("word", SearchType::SingleWordSearch)),
+            Vec::<String>::new()
+        );
+
+        index.compact();
+        assert_eq!(index.get_index().database.n_titles, 102);
+        // The nodes only used by the deleted article are gone
+        assert!(index
+            .get_index()
+            .database
+            .find_single(&"world".to_string())
+            .is_empty());
+        assert_eq!(
+            index.search(&query("wo*", SearchType::PrefixSearch)),
+            vec!["B", "Z"]
+        );
+    }
+
+    #[test]
+    fn index9_0_can_be_changed_too() {
+        let mut index = IncrementalIndex::new(Index {
+            database: TrieLin::new(),
+            article_titles: Vec::new(),
+        });
+        index.add_document("A", &words("word world"));
+        index.add_document("B", &words("word"));
+        index.delete_document(1);
+        assert_eq!(
+            index.search(&query("wor*", SearchType::PrefixSearch)),
+            vec!["A"]
+        );
+        index.delete_document(0);
+        index.compact();
+        assert_eq!(
+            index.search(&query("wor*", SearchType::PrefixSearch)),
+            Vec::<String>::new()
+        );
+        index.add_document("C", &words("worm"));
+        assert_eq!(
+            index.search(&query("wor*", SearchType::PrefixSearch)),
+            vec!["C"]
+        );
+    }
+
+    #[test]
+    fn incremental_index8_matches_a_rebuild() {
+        let articles = articles();
+        let mut index = empty_index8();
+        for (title, contents) in &articles {
+            index.add_document(title, contents);
+        }
+        let config = Config {
+            file_path: FILE.to_string(),
+            indexno: "8".to_string(),
+        };
+        let rebuilt = Index::index8(&config).unwrap();
+        assert_eq!(index.get_index().database, rebuilt.database);
+
+        // Delete the second article and build a reference without it
+        index.delete_document(1);
+        let mut reference = empty_index8();
+        for (i, (title, contents)) in articles.iter().enumerate() {
+            if i != 1 {
+                reference.add_document(title, contents);
+            }
+        }
+
+        let queries = gen_a_lot_of_runs_bool(FILE.to_string(), 10);
+        for depth_vec in &queries {
+            for q in depth_vec {
+                assert_eq!(index.search(&boolean(q)), reference.search(&boolean(q)));
+            }
+        }
+        index.compact();
+        assert_eq!(index.get_index().database, reference.get_index().database);
+        assert_eq!(
+            index.get_index().article_titles,
+            reference.get_index().article_titles
+        );
+    }
+
+    #[test]
+    fn incremental_index9_matches_a_rebuild() {
+        let articles = articles();
+        let mut index = empty_index9();
+        let mut reference = empty_index9();
+        for (i, (title, contents)) in articles.iter().enumerate() {
+            index.add_document(title, contents);
+            if i != 2 {
+                reference.add_document(title, contents);
+            }
+        }
+        index.delete_document(2);
+
+        for q in gen_a_lot_of_runs_tries(FILE.to_string(), 100, true) {
+            let q = query(&q, SearchType::PrefixSearch);
+            assert_eq!(index.search(&q), reference.search(&q));
+        }
+        index.compact();
+        for q in gen_a_lot_of_runs_tries(FILE.to_string(), 100, true) {
+            let q = query(&q, SearchType::PrefixSearch);
+            assert_eq!(index.search(&q), reference.search(&q));
+        }
+    }
+
+    // Builds one index of all the articles, and one appending an index of the first half and an
+    // index of the second
+    fn whole_and_appended<T>(empty: impl Fn() -> Index<T>) -> (Index<T>, Index<T>)
+    where
+        Index<T>: Documents,
+    {
+        let articles = articles();
+        let (mut whole, mut first, mut second) = (empty(), empty(), empty());
+        for (i, (title, contents)) in articles.iter().enumerate() {
+            whole.add_document(title, contents);
+            match i < articles.len() / 2 {
+                true => first.add_document(title, contents),
+                false => second.add_document(title, contents),
+            };
+        }
+        first.append(&second);
+        (whole, first)
+    }
+
+    #[test]
+    fn appended_indexes_match_one_index() {
+        let (whole, appended) = whole_and_appended(|| Index {
+            database: HashMap::new(),
+            article_titles: Vec::new(),
+        });
+        assert_eq!(appended.article_titles, whole.article_titles);
+        for depth_vec in gen_a_lot_of_runs_bool(FILE.to_string(), 3) {
+            for q in depth_vec {
+                let q = boolean(&q);
+                assert_eq!(appended.matching_articles(&q), whole.matching_articles(&q));
+            }
+        }
+
+        let queries = gen_a_lot_of_runs_tries(FILE.to_string(), 100, true);
+        let (whole, appended) = whole_and_appended(|| Index {
+            database: Trie::new(),
+            article_titles: Vec::new(),
+        });
+        let (whole_lin, appended_lin) = whole_and_appended(|| Index {
+            database: TrieLin::new(),
+            article_titles: Vec::new(),
+        });
+        for q in queries {
+            let q = query(&q, SearchType::PrefixSearch);
+            assert_eq!(appended.matching_articles(&q), whole.matching_articles(&q));
+            assert_eq!(
+                appended_lin.matching_articles(&q),
+                whole_lin.matching_articles(&q)
+            );
+        }
+    }
+}
@@ -1,15 +1,25 @@
 #![allow(non_snake_case)]
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::fs;
 
 use crate::helpers::*;
 use crate::index::Index;
 
-use super::{ArticleTitles, Query, Search, SearchType};
+use super::docstore::{DocumentStore, Stored};
+use super::index10_5::error_budget;
+use super::{ArticleTitles, Occurrences, Query, Search, SearchType};
 
 pub fn kmp_table_chars(query: &String) -> Vec<i32> {
     let query: Vec<char> = query.chars().collect();
+    kmp_table_slice(&query)
+}
+
+pub fn kmp_table(query_words: &Vec<&str>) -> Vec<i32> {
+    kmp_table_slice(query_words)
+}
+
+// The failure table of KMP over any alphabet: chars, bytes or words
+pub fn kmp_table_slice<C: Eq>(query: &[C]) -> Vec<i32> {
     let mut T: Vec<i32> = vec![0; query.len() + 1];
     let mut pos: usize = 1;
     let mut cnd: i32 = 0;
@@ -31,48 +41,23 @@ pub fn kmp_table_chars(query: &String) -> Vec<i32> {
     T
 }
 
-pub fn kmp_table(query_words: &Vec<&str>) -> Vec<i32> {
-    let mut T: Vec<i32> = vec![0; query_words.len() + 1];
-    let mut pos: usize = 1;
-    let mut cnd: i32 = 0;
-    T[0] = -1;
-
-    while pos < query_words.len() {
-        if query_words[pos] == query_words[cnd as usize] {
-            T[pos] = T[cnd as usize]
-        } else {
-            T[pos] = cnd;
-            while cnd >= 0 && query_words[pos] != query_words[cnd as usize] {
-                cnd = T[cnd as usize];
-            }
-        }
-        pos += 1;
-        cnd += 1;
-    }
-    T[pos] = cnd;
-    T
-}
-
 pub fn kmp_truefalse(file_contents: String, query: &String, T: &Vec<i32>) -> bool {
-    // let mut P: Vec<usize> = Vec::new();
-    let mut j = 0;
-    let mut k = 0;
-
     let file_vec: Vec<char> = file_contents.chars().collect();
     let p: Vec<char> = query.chars().collect();
+    kmp_truefalse_slice(&file_vec, &p, T)
+}
 
-    // dbg!(&T, &file_contents, &query_words);
-    // let mut counter = 0;
+// T is the table of p from kmp_table_slice, so it can be reused for every text
+pub fn kmp_truefalse_slice<C: Eq>(file_vec: &[C], p: &[C], T: &[i32]) -> bool {
+    let mut j = 0;
+    let mut k = 0;
 
     while j < file_vec.len() {
-        // counter += 1;
         if p[k] == file_vec[j] {
             j += 1;
             k += 1;
             if k == p.len() {
                 // Occurence found
-                // println!("KMP ran {counter} iterations");
-                // return Some(j - k);
                 return true;
             }
         } else {
@@ -85,24 +70,25 @@ pub fn kmp_truefalse(file_contents: String, query: &String, T: &Vec<i32>) -> boo
             }
         }
     }
-    // println!("KMP ran {counter} iterations");
     false
 }
 
 pub fn kmp_allmatches(file_contents: String, query_words: &Vec<&str>, T: &Vec<i32>) -> Vec<usize> {
+    let file_vec: Vec<&str> = file_contents.split_ascii_whitespace().collect();
+    kmp_allmatches_slice(&file_vec, query_words, T)
+}
+
+// The start of every occurrence of p in file_vec, overlapping ones included
+pub fn kmp_allmatches_slice<C: Eq>(file_vec: &[C], p: &[C], T: &[i32]) -> Vec<usize> {
     let mut P: Vec<usize> = Vec::new();
     let mut j = 0;
     let mut k = 0;
 
-    let file_vec: Vec<&str> = file_contents.split_ascii_whitespace().collect();
-
-    // dbg!(&T, &file_contents, &query_words);
-
     while j < file_vec.len() {
-        if query_words[k] == file_vec[j] {
+        if p[k] == file_vec[j] {
             j += 1;
             k += 1;
-            if k == query_words.len() {
+            if k == p.len() {
                 // Occurence found
                 P.push(j - k);
                 k = T[k] as usize;
@@ -120,13 +106,12 @@ pub fn kmp_allmatches(file_contents: String, query_words: &Vec<&str>, T: &Vec<i3
     P
 }
 
-impl Index<HashMap<String, HashSet<usize>>> {
+impl Index<Stored<HashMap<String, HashSet<usize>>>> {
     pub fn index10(config: &Config) -> Result<Self, Box<dyn Error>> {
-        // Read all articles, run them through the regex and write each individual article to a file named in the format 0000xxxx.txt
-        write_article_files(config);
-
         // Setup
         let mut database: HashMap<String, HashSet<usize>> = HashMap::new();
+        // The searches verify their candidates against the text of the articles
+        let mut documents = DocumentStore::default();
 
         let articles_iter = read_and_clean_file_to_iter(config)?;
         let mut article_titles: Vec<String> = Vec::new();
@@ -136,6 +121,7 @@ impl Index<HashMap<String, HashSet<usize>>> {
         for (title, contents) in articles_iter {
             if title != "" {
                 article_titles.push(title.to_string());
+                documents.push(contents.join(" ").as_bytes());
                 for word in contents {
                     let articles = database.entry(word.to_string()).or_default();
                     articles.insert(article_no);
@@ -145,7 +131,7 @@ impl Index<HashMap<String, HashSet<usize>>> {
         }
 
         Ok(Index {
-            database,
+            database: Stored::new(database, documents),
             article_titles,
         })
     }
@@ -177,20 +163,15 @@ impl Index<HashMap<String, HashSet<usize>>> {
         // let query_words: Vec<&str> = query.split(' ').collect();
         let mut result: Vec<usize> = Vec::new();
         // Do the KMP preprocessing
-        let T = kmp_table_chars(&query);
+        // Over bytes rather than chars, as a UTF-8 pattern only matches UTF-8 text at char
+        // boundaries
+        let T = kmp_table_slice(query.as_bytes());
         // dbg!(&art_intersect);
 
         for art_no in art_intersect {
-            // Read the file
-            let file_contents =
-                fs::read_to_string(format!("data/individual_articles/{:08}.txt", art_no)).expect(
-                    format!(
-                        "Article number {} not found in data/individual_articles/",
-                        art_no
-                    )
-                    .as_str(),
-                );
-            if kmp_truefalse(file_contents, &query, &T) {
+            // Read the article
+            let file_contents = self.article_bytes(art_no);
+            if kmp_truefalse_slice(&file_contents, query.as_bytes(), &T) {
                 result.push(art_no) // There was at least one occurence
             }
         }
@@ -212,15 +193,8 @@ impl Index<HashMap<String, HashSet<usize>>> {
             .collect();
         let mut result: Vec<usize> = Vec::new();
         for art_no in art_intersect {
-            // Read the file
-            let file_contents =
-                fs::read_to_string(format!("data/individual_articles/{:08}.txt", art_no)).expect(
-                    format!(
-                        "Article number {} not found in data/individual_articles/",
-                        art_no
-                    )
-                    .as_str(),
-                );
+            // Read the article
+            let file_contents = self.article_text(art_no);
             // Ladies and gentlemen, behold the power of the .contains function
             if file_contents.contains(&query[..]) {
                 result.push(art_no)
@@ -232,9 +206,46 @@ impl Index<HashMap<String, HashSet<usize>>> {
             .map(|a_no| self.article_titles[*a_no].to_owned())
             .collect()
     }
+
+    // The articles with every word of the phrase, in increasing order
+    pub fn phrase_candidates(&self, query: &str) -> Vec<usize> {
+        let mut sets: Vec<&HashSet<usize>> = Vec::new();
+        for word in query.split(' ') {
+            match self.database.get(word) {
+                Some(articles) => sets.push(articles),
+                None => return Vec::new(),
+            }
+        }
+        // Go through the smallest set, and look its articles up in the others
+        sets.sort_unstable_by_key(|articles| articles.len());
+        let (smallest, rest) = sets.split_first().unwrap();
+        let mut art_intersect: Vec<usize> = smallest
+            .iter()
+            .copied()
+            .filter(|ar_no| rest.iter().all(|articles| articles.contains(ar_no)))
+            .collect();
+        art_intersect.sort_unstable();
+        art_intersect
+    }
+
+    // Every occurrence of the phrase, as byte offsets in the articles. char_occurrences turns
+    // them into offsets in characters.
+    pub fn kmp_occurrences(&self, query: &str) -> Occurrences {
+        let p = query.as_bytes();
+        let T = kmp_table_slice(p);
+        let mut result = Occurrences::new();
+        for art_no in self.phrase_candidates(query) {
+            let file_contents = self.article_bytes(art_no);
+            let offsets = kmp_allmatches_slice(&file_contents, p, &T);
+            if !offsets.is_empty() {
+                result.push((art_no, offsets))
+            }
+        }
+        result
+    }
 }
 
-impl Search for Index<HashMap<String, HashSet<usize>>> {
+impl Search for Index<Stored<HashMap<String, HashSet<usize>>>> {
     fn search(&self, query: &Query) -> ArticleTitles {
         match &query.search_type {
             SearchType::SingleWordSearch => self.single_search(&query.search_string),
@@ -245,6 +256,12 @@ impl Search for Index<HashMap<String, HashSet<usize>>> {
             SearchType::ExactSearch(x) if x == "ApostolicoGiancarlo" => {
                 self.apostolico_giancarlo_search(&query.search_string)
             }
+            SearchType::ExactSearch(x) if x == "AhoCorasick" => {
+                self.aho_corasick_search(&query.search_string)
+            }
+            SearchType::ExactSearch(x) if error_budget(x).is_some() => {
+                self.approximate_search(&query.search_string, error_budget(x).unwrap())
+            }
             SearchType::ExactSearch(x) if x == "dumide" => self.dumidesearch(&query.search_string),
             _ => unimplemented!(),
         }
@@ -298,6 +315,23 @@ mod tests {
         assert_eq!(kmp_table, vec![-1, 0, 0, 0, -1, 0, 2, 0]);
     }
 
+    #[test]
+    fn kmp_over_bytes_and_chars() {
+        let text = "smørrebrød og rødgrød";
+        for (query, found) in [("rødgrød", true), ("ød og", true), ("rö", false)] {
+            let T = kmp_table_slice(query.as_bytes());
+            assert_eq!(
+                kmp_truefalse_slice(text.as_bytes(), query.as_bytes(), &T),
+                found
+            );
+            let T = kmp_table_chars(&query.to_string());
+            assert_eq!(
+                kmp_truefalse(text.to_string(), &query.to_string(), &T),
+                found
+            );
+        }
+    }
+
     #[test]
     fn kmp_1() {
         let file_contents: String = "A".to_string();
@@ -340,7 +374,7 @@ mod tests {
     }
 
     // ==================== Test the actual index ====================
-    fn setup_test() -> Index<HashMap<String, HashSet<usize>>> {
+    fn setup_test() -> Index<Stored<HashMap<String, HashSet<usize>>>> {
         {
             let mut database: HashMap<String, HashSet<usize>> = HashMap::new();
 
@@ -366,47 +400,30 @@ mod tests {
                 article_titles.push(format!("article {}", i).to_string());
             }
 
-            // Write the actual files
-            fs::write(
-                format!("data/individual_articles/{:08}.txt", 100),
-                "word1 word1 word2 word2 word3 word3 word4 word4",
-            )
-            .unwrap();
-            fs::write(
-                format!("data/individual_articles/{:08}.txt", 101),
-                "word1 word2 word3 word4 word4 word3 word2 word1",
-            )
-            .unwrap();
-            fs::write(
-                format!("data/individual_articles/{:08}.txt", 102),
-                "word2 word4 word1 word3",
-            )
-            .unwrap();
-            fs::write(
-                format!("data/individual_articles/{:08}.txt", 103),
-                "word1word2word3word4 word1 word4 word1 word2 word1 word3 word1",
-            )
-            .unwrap();
-            fs::write(
-                format!("data/individual_articles/{:08}.txt", 104),
-                "word3 word3 word2 word3 word3 word3 word2 word3 word2 word2 word2 word3 word3 word3 word3 word3 word2 word3 word3 word3 word2",
-            )
-            .unwrap();
-            fs::write(
-                format!("data/individual_articles/{:08}.txt", 105),
-                "word2 word3 word4",
-            )
-            .unwrap();
+            // The text of the articles
+            let mut documents = DocumentStore::default();
+            for i in 0..110 {
+                let text = match i {
+                    100 => "word1 word1 word2 word2 word3 word3 word4 word4",
+                    101 => "word1 word2 word3 word4 word4 word3 word2 word1",
+                    102 => "word2 word4 word1 word3",
+                    103 => "word1word2word3word4 word1 word4 word1 word2 word1 word3 word1",
+                    104 => "word3 word3 word2 word3 word3 word3 word2 word3 word2 word2 word2 word3 word3 word3 word3 word3 word2 word3 word3 word3 word2",
+                    105 => "word2 word3 word4",
+                    _ => "",
+                };
+                documents.push(text.as_bytes());
+            }
 
             Index {
-                database,
+                database: Stored::new(database, documents),
                 article_titles,
             }
         }
     }
 
     fn search_match(
-        index: Index<HashMap<String, HashSet<usize>>>,
+        index: Index<Stored<HashMap<String, HashSet<usize>>>>,
         query: Query,
         expected: Vec<String>,
     ) {
@@ -416,6 +433,48 @@ mod tests {
         )
     }
 
+    #[test]
+    fn occurrences_with_offsets() {
+        let index = setup_test();
+        let query = "word3 word3";
+        // Overlapping occurrences count too
+        let naive = |art_no: usize| -> Vec<usize> {
+            let text = index.article_text(art_no);
+            (0..text.len())
+                .filter(|&i| text[i..].starts_with(query))
+                .collect()
+        };
+        let expected: Occurrences = vec![(100, naive(100)), (104, naive(104))];
+        assert_eq!(expected[0].1, vec![24]);
+        assert_eq!(index.kmp_occurrences(query), expected);
+        assert_eq!(index.boyer_moore_occurrences(query), expected);
+        assert_eq!(
+            index.titles_by_occurrences(&expected),
+            vec![
+                ("article 104".to_string(), 9),
+                ("article 100".to_string(), 1)
+            ]
+        );
+        assert_eq!(index.kmp_occurrences("word4 word2"), vec![]);
+        assert_eq!(
+            index.phrase_candidates("word3 nowhere"),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn phrase_candidates_have_every_word() {
+        let index = setup_test();
+        let expected = vec![100, 101, 102, 103];
+        assert_eq!(index.phrase_candidates("word1 word2 word4"), expected);
+        // Only word1 leaves out articles, so every article has to be checked against it
+        assert_eq!(index.phrase_candidates("word4 word2 word1"), expected);
+        assert_eq!(
+            index.phrase_candidates("word2 word2"),
+            vec![100, 101, 102, 103, 104, 105]
+        );
+    }
+
     #[test]
     fn find_a_word() {
         let index = setup_test();
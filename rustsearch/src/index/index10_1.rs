@@ -2,12 +2,12 @@
 use std::{
     cmp::{max, min},
     collections::{HashMap, HashSet},
-    fs,
+    hash::Hash,
 };
 
-use super::{index10_2::calculate_shift_at_mismatch, Index};
+use super::{docstore::Stored, index10_2::calculate_shift_at_mismatch, Index, Occurrences};
 
-pub fn z_alg(s: &Vec<&char>) -> Vec<usize> {
+pub fn z_alg<C: Eq>(s: &[C]) -> Vec<usize> {
     let n = s.len();
     let mut z = vec![0; n];
     let mut l = 0;
@@ -48,9 +48,9 @@ pub fn compute_L_primes(N: &Vec<usize>) -> (Vec<usize>, Vec<usize>) {
     (L_prime, l_prime)
 }
 
-pub fn compute_R(p: &Vec<char>) -> HashMap<&char, usize> {
+pub fn compute_R<C: Eq + Hash>(p: &[C]) -> HashMap<&C, usize> {
     let n = p.len();
-    let mut R: HashMap<&char, usize> = HashMap::new();
+    let mut R: HashMap<&C, usize> = HashMap::new();
     for i in 0..n {
         R.insert(&p[i], i);
         // R.entry(&p[n - 1 - i]).or_default().push(n - 1 - i)
@@ -58,26 +58,26 @@ pub fn compute_R(p: &Vec<char>) -> HashMap<&char, usize> {
     R
 }
 
-pub fn boyer_moore_preprocess(
-    p: &Vec<char>,
-) -> (Vec<usize>, Vec<usize>, HashMap<&char, usize>, Vec<usize>) {
+pub fn boyer_moore_preprocess<C: Eq + Hash>(
+    p: &[C],
+) -> (Vec<usize>, Vec<usize>, HashMap<&C, usize>, Vec<usize>) {
     // Compute N[j](P) values
-    let p_rev: Vec<&char> = p.iter().rev().collect();
+    let p_rev: Vec<&C> = p.iter().rev().collect();
     let N: Vec<usize> = z_alg(&p_rev).iter().rev().map(|x| *x).collect();
 
     // Compute L' values
     let (L_prime, l_prime) = compute_L_primes(&N);
 
     // Compute R values
-    let R: HashMap<&char, usize> = compute_R(p);
+    let R: HashMap<&C, usize> = compute_R(p);
 
     (L_prime, l_prime, R, N)
 }
 
-pub fn boyer_moore(
-    p: &Vec<char>,
-    t: &Vec<char>,
-    (L_prime, l_prime, R): (&Vec<usize>, &Vec<usize>, &HashMap<&char, usize>),
+pub fn boyer_moore<C: Eq + Hash>(
+    p: &[C],
+    t: &[C],
+    (L_prime, l_prime, R): (&Vec<usize>, &Vec<usize>, &HashMap<&C, usize>),
 ) -> Vec<usize> {
     // Search stage
     let n = p.len();
@@ -144,10 +144,10 @@ pub fn boyer_moore(
     match_indices
 }
 
-pub fn boyer_moore_truefalse(
-    p: &Vec<char>,
-    t: &Vec<char>,
-    (L_prime, l_prime, R): (&Vec<usize>, &Vec<usize>, &HashMap<&char, usize>),
+pub fn boyer_moore_truefalse<C: Eq + Hash>(
+    p: &[C],
+    t: &[C],
+    (L_prime, l_prime, R): (&Vec<usize>, &Vec<usize>, &HashMap<&C, usize>),
 ) -> bool {
     // Search stage
     let n = p.len();
@@ -171,9 +171,10 @@ pub fn boyer_moore_truefalse(
     false
 }
 
-impl Index<HashMap<String, HashSet<usize>>> {
+impl Index<Stored<HashMap<String, HashSet<usize>>>> {
     pub fn boyer_moore_search(&self, query: &String) -> Vec<String> {
-        let p: Vec<char> = query.chars().collect();
+        // Bytes rather than chars, as a UTF-8 pattern only matches UTF-8 text at char boundaries
+        let p = query.as_bytes();
 
         // Split sentence into words
         // Get article set for each word, and find intersection
@@ -189,22 +190,12 @@ impl Index<HashMap<String, HashSet<usize>>> {
         // dbg!(format!("From 10.1: Searching through {} articles for query {}",art_intersect.len(), query));
 
         let mut result: Vec<usize> = Vec::new();
-        let (L_prime, l_prime, R, _) = boyer_moore_preprocess(&p);
+        let (L_prime, l_prime, R, _) = boyer_moore_preprocess(p);
 
         for art_no in art_intersect {
-            // Read the file
-            let t: Vec<char> =
-                fs::read_to_string(format!("data/individual_articles/{:08}.txt", art_no))
-                    .expect(
-                        format!(
-                            "Article number {} not found in data/individual_articles/",
-                            art_no
-                        )
-                        .as_str(),
-                    )
-                    .chars()
-                    .collect();
-            if boyer_moore_truefalse(&p, &t, (&L_prime, &l_prime, &R)) {
+            // Read the article
+            let t = self.article_bytes(art_no);
+            if boyer_moore_truefalse(p, &t, (&L_prime, &l_prime, &R)) {
                 result.push(art_no) // There was at least one occurence
             }
         }
@@ -214,11 +205,38 @@ impl Index<HashMap<String, HashSet<usize>>> {
             .map(|a_no| self.article_titles[*a_no].to_owned())
             .collect()
     }
+
+    // Every occurrence of the phrase, as byte offsets in the articles
+    pub fn boyer_moore_occurrences(&self, query: &str) -> Occurrences {
+        self.boyer_moore_occurrences_in(query.as_bytes(), self.phrase_candidates(query))
+    }
+}
+
+impl<T> Index<Stored<T>> {
+    // Every occurrence of p in the candidate articles, which are in increasing order, as byte
+    // offsets in the articles
+    pub fn boyer_moore_occurrences_in(&self, p: &[u8], candidates: Vec<usize>) -> Occurrences {
+        let (L_prime, l_prime, R, _) = boyer_moore_preprocess(p);
+        let mut result = Occurrences::new();
+        for art_no in candidates {
+            let t = self.article_bytes(art_no);
+            // boyer_moore gives where the matches end
+            let offsets: Vec<usize> = boyer_moore(p, &t, (&L_prime, &l_prime, &R))
+                .into_iter()
+                .map(|k| k + 1 - p.len())
+                .collect();
+            if !offsets.is_empty() {
+                result.push((art_no, offsets))
+            }
+        }
+        result
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::index::docstore::DocumentStore;
 
     // #[test]
     // #[ignore]
@@ -378,4 +396,67 @@ mod tests {
         let (L_prime, l_prime, R, _) = boyer_moore_preprocess(&p);
         assert_eq!(boyer_moore(&p, &t, (&L_prime, &l_prime, &R)), vec![29]);
     }
+
+    // The end of every match, as a byte offset, in text as chars and as bytes
+    fn char_and_byte_matches(p: &str, t: &str) -> (Vec<usize>, Vec<usize>) {
+        let p_chars: Vec<char> = p.chars().collect();
+        let t_chars: Vec<char> = t.chars().collect();
+        let (L_prime, l_prime, R, _) = boyer_moore_preprocess(&p_chars);
+        let byte_ends: Vec<usize> = t.char_indices().map(|(i, c)| i + c.len_utf8()).collect();
+        let char_matches = boyer_moore(&p_chars, &t_chars, (&L_prime, &l_prime, &R))
+            .iter()
+            .map(|&k| byte_ends[k] - 1)
+            .collect();
+        let (L_prime, l_prime, R, _) = boyer_moore_preprocess(p.as_bytes());
+        let byte_matches = boyer_moore(p.as_bytes(), t.as_bytes(), (&L_prime, &l_prime, &R));
+        (char_matches, byte_matches)
+    }
+
+    #[test]
+    fn bytes_match_where_chars_do() {
+        let t = "smørrebrød og rødgrød med fløde, å ø æ, naïve café façade";
+        for p in ["rød", "ød", "å ø", "café", "e c", "naïve", "fløde, å"] {
+            let (char_matches, byte_matches) = char_and_byte_matches(p, t);
+            assert!(!byte_matches.is_empty(), "{}", p);
+            assert_eq!(byte_matches, char_matches, "{}", p);
+        }
+        // 'ø' and 'ö' share their first byte
+        assert_eq!(char_and_byte_matches("öd", t), (vec![], vec![]));
+    }
+
+    #[test]
+    fn byte_searches_with_non_ascii_phrases() {
+        let articles = [
+            "the naïve café on the façade",
+            "a naive cafe on the facade",
+            "naïve art in the café",
+        ];
+        let mut database: HashMap<String, HashSet<usize>> = HashMap::new();
+        let mut article_titles: Vec<String> = Vec::new();
+        for (art_no, contents) in articles.iter().enumerate() {
+            for word in contents.split(' ') {
+                database.entry(word.to_string()).or_default().insert(art_no);
+            }
+            article_titles.push(format!("article {}", art_no));
+        }
+        let index = Index {
+            database: Stored::new(database, DocumentStore::from_articles(&articles)),
+            article_titles,
+        };
+        for (query, expected) in [
+            ("naïve café", vec!["article 0"]),
+            ("the café", vec!["article 2"]),
+            ("naive cafe", vec!["article 1"]),
+        ] {
+            let query = query.to_string();
+            for mut result in [
+                index.kmp_search(&query),
+                index.boyer_moore_search(&query),
+                index.apostolico_giancarlo_search(&query),
+            ] {
+                result.sort();
+                assert_eq!(result, expected, "{}", query);
+            }
+        }
+    }
 }
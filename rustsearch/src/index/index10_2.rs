@@ -2,20 +2,15 @@
 use std::{
     cmp::max,
     collections::{HashMap, HashSet},
-    fs,
+    hash::Hash,
 };
 
-use super::{index10_1::boyer_moore_preprocess, Index};
+use super::{docstore::Stored, index10_1::boyer_moore_preprocess, Index};
 
-pub fn apostolico_giancarlo(
-    p: &Vec<char>,
-    t: &Vec<char>,
-    (L_prime, l_prime, R, N): (
-        &Vec<usize>,
-        &Vec<usize>,
-        &HashMap<&char, usize>,
-        &Vec<usize>,
-    ),
+pub fn apostolico_giancarlo<C: Eq + Hash>(
+    p: &[C],
+    t: &[C],
+    (L_prime, l_prime, R, N): (&Vec<usize>, &Vec<usize>, &HashMap<&C, usize>, &Vec<usize>),
 ) -> Vec<usize> {
     // Search stage
     let n = p.len();
@@ -63,7 +58,7 @@ pub fn apostolico_giancarlo(
                 break 'phase;
             }
             if M[h] as usize > N[i] && N[i] < i + 1 {
-                assert_ne!(p[i - N[i]], t[h - N[i]]);
+                assert!(p[i - N[i]] != t[h - N[i]]);
                 M[j] = (j - h) as i32;
                 j += calculate_shift_at_mismatch(
                     &t[h - N[i]],
@@ -79,15 +74,10 @@ pub fn apostolico_giancarlo(
     match_indices
 }
 
-pub fn apostolico_giancarlo_truefalse(
-    p: &Vec<char>,
-    t: &Vec<char>,
-    (L_prime, l_prime, R, N): (
-        &Vec<usize>,
-        &Vec<usize>,
-        &HashMap<&char, usize>,
-        &Vec<usize>,
-    ),
+pub fn apostolico_giancarlo_truefalse<C: Eq + Hash>(
+    p: &[C],
+    t: &[C],
+    (L_prime, l_prime, R, N): (&Vec<usize>, &Vec<usize>, &HashMap<&C, usize>, &Vec<usize>),
 ) -> bool {
     // Search stage
     let n = p.len();
@@ -128,7 +118,7 @@ pub fn apostolico_giancarlo_truefalse(
                 return true;
             }
             if M[h] as usize > N[i] && N[i] < i + 1 {
-                assert_ne!(p[i - N[i]], t[h - N[i]]);
+                assert!(p[i - N[i]] != t[h - N[i]]);
                 M[j] = (j - h) as i32;
                 j += calculate_shift_at_mismatch(
                     &t[h - N[i]],
@@ -144,11 +134,11 @@ pub fn apostolico_giancarlo_truefalse(
     false
 }
 
-pub fn calculate_shift_at_mismatch(
-    th: &char,
+pub fn calculate_shift_at_mismatch<C: Eq + Hash>(
+    th: &C,
     i: usize,
     n: usize,
-    (L_prime, l_prime, R): (&Vec<usize>, &Vec<usize>, &HashMap<&char, usize>),
+    (L_prime, l_prime, R): (&Vec<usize>, &Vec<usize>, &HashMap<&C, usize>),
 ) -> usize {
     // Bad character rule
     let bc_shift = match R.get(th) {
@@ -170,9 +160,10 @@ pub fn calculate_shift_at_mismatch(
     max(bc_shift, gs_shift)
 }
 
-impl Index<HashMap<String, HashSet<usize>>> {
+impl Index<Stored<HashMap<String, HashSet<usize>>>> {
     pub fn apostolico_giancarlo_search(&self, query: &String) -> Vec<String> {
-        let p: Vec<char> = query.chars().collect();
+        // Bytes rather than chars, as a UTF-8 pattern only matches UTF-8 text at char boundaries
+        let p = query.as_bytes();
 
         // Split sentence into words
         // Get article set for each word, and find intersection
@@ -186,22 +177,12 @@ impl Index<HashMap<String, HashSet<usize>>> {
             .collect();
 
         let mut result: Vec<usize> = Vec::new();
-        let (L_prime, l_prime, R, N) = boyer_moore_preprocess(&p);
+        let (L_prime, l_prime, R, N) = boyer_moore_preprocess(p);
 
         for art_no in art_intersect {
-            // Read the file
-            let t: Vec<char> =
-                fs::read_to_string(format!("data/individual_articles/{:08}.txt", art_no))
-                    .expect(
-                        format!(
-                            "Article number {} not found in data/individual_articles/",
-                            art_no
-                        )
-                        .as_str(),
-                    )
-                    .chars()
-                    .collect();
-            if apostolico_giancarlo_truefalse(&p, &t, (&L_prime, &l_prime, &R, &N)) {
+            // Read the article
+            let t = self.article_bytes(art_no);
+            if apostolico_giancarlo_truefalse(p, &t, (&L_prime, &l_prime, &R, &N)) {
                 result.push(art_no) // There was at least one occurence
             }
         }
@@ -253,4 +234,15 @@ mod tests {
             vec![49, 52, 73, 85, 95, 107]
         );
     }
+
+    #[test]
+    fn apostolico_giancarlo_over_bytes() {
+        let t = "smørrebrød og rødgrød med fløde, rødgrød";
+        let p = "rødgrød";
+        let (L_prime, l_prime, R, N) = boyer_moore_preprocess(p.as_bytes());
+        let matches =
+            apostolico_giancarlo(p.as_bytes(), t.as_bytes(), (&L_prime, &l_prime, &R, &N));
+        let expected: Vec<usize> = t.match_indices(p).map(|(i, _)| i + p.len() - 1).collect();
+        assert_eq!(matches, expected);
+    }
 }
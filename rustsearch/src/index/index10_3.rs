@@ -0,0 +1,207 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::docstore::Stored;
+use super::Index;
+
+// Aho-Corasick: a trie of the patterns, where every node also links to the node of its longest
+// proper suffix that is in the trie. A mismatch follows those links instead of starting over, so
+// every pattern is found in a single pass over the text.
+struct Node {
+    next: HashMap<u8, usize>,
+    fail: usize,
+    // The patterns ending here, including those ending in a suffix of this node
+    outputs: Vec<usize>,
+}
+
+pub struct AhoCorasick {
+    nodes: Vec<Node>,
+    pattern_lens: Vec<usize>,
+}
+
+impl AhoCorasick {
+    pub fn new(patterns: &[&str]) -> Self {
+        let mut nodes = vec![Node {
+            next: HashMap::new(),
+            fail: 0,
+            outputs: Vec::new(),
+        }];
+        for (p, pattern) in patterns.iter().enumerate() {
+            let mut node = 0;
+            for &b in pattern.as_bytes() {
+                node = match nodes[node].next.get(&b) {
+                    Some(&child) => child,
+                    None => {
+                        nodes.push(Node {
+                            next: HashMap::new(),
+                            fail: 0,
+                            outputs: Vec::new(),
+                        });
+                        let child = nodes.len() - 1;
+                        nodes[node].next.insert(b, child);
+                        child
+                    }
+                };
+            }
+            nodes[node].outputs.push(p);
+        }
+
+        // Breadth first, so the suffix links of the shorter nodes are done first
+        let mut queue: VecDeque<usize> = nodes[0].next.values().copied().collect();
+        while let Some(node) = queue.pop_front() {
+            let children: Vec<(u8, usize)> =
+                nodes[node].next.iter().map(|(&b, &c)| (b, c)).collect();
+            for (b, child) in children {
+                let mut fail = nodes[node].fail;
+                while fail != 0 && !nodes[fail].next.contains_key(&b) {
+                    fail = nodes[fail].fail;
+                }
+                let fail = match nodes[fail].next.get(&b) {
+                    Some(&f) if f != child => f,
+                    _ => 0,
+                };
+                nodes[child].fail = fail;
+                let inherited = nodes[fail].outputs.clone();
+                nodes[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        AhoCorasick {
+            nodes,
+            pattern_lens: patterns.iter().map(|p| p.len()).collect(),
+        }
+    }
+
+    // Every occurrence of every pattern, as (pattern, byte offset of its start), in the order
+    // they end in the text
+    pub fn find_all(&self, text: &[u8]) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        let mut node = 0;
+        for (i, b) in text.iter().enumerate() {
+            loop {
+                if let Some(&next) = self.nodes[node].next.get(b) {
+                    node = next;
+                    break;
+                }
+                if node == 0 {
+                    break;
+                }
+                node = self.nodes[node].fail;
+            }
+            for &p in &self.nodes[node].outputs {
+                matches.push((p, i + 1 - self.pattern_lens[p]));
+            }
+        }
+        matches
+    }
+}
+
+impl Index<Stored<HashMap<String, HashSet<usize>>>> {
+    // The phrases are separated by ';'. For each article where at least one phrase occurs, the
+    // phrases found and where, as (phrase, byte offset), after one pass over the article.
+    pub fn aho_corasick_matches(&self, query: &str) -> Vec<(usize, Vec<(usize, usize)>)> {
+        let phrases: Vec<&str> = query
+            .split(';')
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .collect();
+
+        // An article can only contain a phrase if it contains all the words of the phrase
+        let mut candidates: Vec<usize> = phrases
+            .iter()
+            .flat_map(|phrase| self.phrase_candidates(phrase))
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let automaton = AhoCorasick::new(&phrases);
+        let mut result = Vec::new();
+        for art_no in candidates {
+            // Read the article
+            let file_contents = self.article_bytes(art_no);
+            let matches = automaton.find_all(&file_contents);
+            if !matches.is_empty() {
+                result.push((art_no, matches));
+            }
+        }
+        result
+    }
+
+    pub fn aho_corasick_search(&self, query: &str) -> Vec<String> {
+        self.aho_corasick_matches(query)
+            .iter()
+            .map(|(a_no, _)| self.article_titles[*a_no].to_owned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::docstore::DocumentStore;
+    use crate::index::{Query, Search, SearchType};
+
+    #[test]
+    fn finds_overlapping_patterns() {
+        let automaton = AhoCorasick::new(&["he", "she", "his", "hers"]);
+        assert_eq!(automaton.find_all(b"ushers"), vec![(1, 1), (0, 2), (3, 2)]);
+        assert_eq!(automaton.find_all(b"ahishe"), vec![(2, 1), (1, 3), (0, 4)]);
+        assert_eq!(automaton.find_all(b"nothing"), vec![]);
+    }
+
+    #[test]
+    fn same_matches_as_a_naive_scan() {
+        let patterns = ["a", "ab", "bab", "bc", "bca", "c", "caa", "aaa"];
+        let text = b"abccabaabcaaababcbcaaa";
+        let mut naive: Vec<(usize, usize)> = Vec::new();
+        for end in 1..=text.len() {
+            for (p, pattern) in patterns.iter().enumerate() {
+                if text[..end].ends_with(pattern.as_bytes()) {
+                    naive.push((p, end - pattern.len()));
+                }
+            }
+        }
+        let mut found = AhoCorasick::new(&patterns).find_all(text);
+        // Patterns ending at the same place may come in any order
+        found.sort_by_key(|&(p, start)| (start + patterns[p].len(), p));
+        assert_eq!(found, naive);
+    }
+
+    fn setup_test() -> Index<Stored<HashMap<String, HashSet<usize>>>> {
+        let articles = [
+            "the watch list has phrases",
+            "a list of watch phrases",
+            "watch the watch list",
+        ];
+        let mut database: HashMap<String, HashSet<usize>> = HashMap::new();
+        let mut article_titles: Vec<String> = Vec::new();
+        for (art_no, contents) in articles.iter().enumerate() {
+            for word in contents.split(' ') {
+                database.entry(word.to_string()).or_default().insert(art_no);
+            }
+            article_titles.push(format!("article {}", art_no));
+        }
+        Index {
+            database: Stored::new(database, DocumentStore::from_articles(&articles)),
+            article_titles,
+        }
+    }
+
+    #[test]
+    fn reports_phrases_per_article() {
+        let index = setup_test();
+        assert_eq!(
+            index.aho_corasick_matches("watch list; phrases ;list of"),
+            vec![
+                (0, vec![(0, 4), (1, 19)]),
+                (1, vec![(2, 2), (1, 16)]),
+                (2, vec![(0, 10)]),
+            ]
+        );
+        let query = Query {
+            search_string: "the watch; nowhere to be found".to_string(),
+            search_type: SearchType::ExactSearch("AhoCorasick".to_string()),
+        };
+        assert_eq!(index.search(&query), vec!["article 0", "article 2"]);
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+
+use regex::Regex;
+use regex_syntax::hir::{Class, Hir, HirKind, Literal, RepetitionKind, RepetitionRange};
+
+use crate::helpers::*;
+use crate::index::Index;
+
+use super::docstore::{DocumentStore, Stored};
+use super::*;
+
+// Regex search over the articles. The index has the articles each trigram (three bytes in a row)
+// of the article text occurs in. Before the regex is run, the pattern is turned into a condition
+// on trigrams every match has to fulfill, in the style of Google Code Search, and the regex is
+// only run on the text of the articles that fulfill it.
+
+pub type Trigram = [u8; 3];
+
+// The articles with a match, in order, each with the byte ranges of its matches
+pub type RegexMatches = Vec<(usize, Vec<(usize, usize)>)>;
+
+// Strings are expanded into the set of strings they can be until there are more than this
+const MAX_EXACT: usize = 16;
+
+// The trigrams an article must have to match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Required {
+    Anything,
+    Trigram(Trigram),
+    All(Vec<Required>),
+    Any(Vec<Required>),
+}
+
+impl Required {
+    fn and(self, other: Required) -> Required {
+        match (self, other) {
+            (Required::Anything, r) | (r, Required::Anything) => r,
+            (Required::All(mut a), Required::All(b)) => {
+                a.extend(b);
+                Required::All(a)
+            }
+            (Required::All(mut a), r) | (r, Required::All(mut a)) => {
+                a.push(r);
+                Required::All(a)
+            }
+            (a, b) => Required::All(vec![a, b]),
+        }
+    }
+
+    fn or(self, other: Required) -> Required {
+        match (self, other) {
+            (Required::Anything, _) | (_, Required::Anything) => Required::Anything,
+            (Required::Any(mut a), Required::Any(b)) => {
+                a.extend(b);
+                Required::Any(a)
+            }
+            (Required::Any(mut a), r) | (r, Required::Any(mut a)) => {
+                a.push(r);
+                Required::Any(a)
+            }
+            (a, b) => Required::Any(vec![a, b]),
+        }
+    }
+
+    // Every trigram of the string. Strings shorter than a trigram require nothing.
+    fn from_string(s: &[u8]) -> Required {
+        s.windows(3)
+            .map(|w| Required::Trigram([w[0], w[1], w[2]]))
+            .fold(Required::Anything, Required::and)
+    }
+
+    fn from_strings(strings: &BTreeSet<Vec<u8>>) -> Required {
+        strings
+            .iter()
+            .map(|s| Required::from_string(s))
+            .reduce(Required::or)
+            .unwrap_or(Required::Anything)
+    }
+}
+
+// What is known about the strings a part of the pattern matches: either every string it can
+// match, or just the trigrams they must have
+enum Info {
+    Exact(BTreeSet<Vec<u8>>),
+    Required(Required),
+}
+
+impl Info {
+    fn into_required(self) -> Required {
+        match self {
+            Info::Exact(strings) => Required::from_strings(&strings),
+            Info::Required(required) => required,
+        }
+    }
+}
+
+fn class_strings(class: &Class) -> Option<BTreeSet<Vec<u8>>> {
+    let mut strings = BTreeSet::new();
+    match class {
+        Class::Unicode(class) => {
+            for range in class.iter() {
+                for c in range.start()..=range.end() {
+                    if strings.len() == MAX_EXACT {
+                        return None;
+                    }
+                    strings.insert(c.to_string().into_bytes());
+                }
+            }
+        }
+        Class::Bytes(class) => {
+            for range in class.iter() {
+                for b in range.start()..=range.end() {
+                    if strings.len() == MAX_EXACT {
+                        return None;
+                    }
+                    strings.insert(vec![b]);
+                }
+            }
+        }
+    }
+    Some(strings)
+}
+
+fn analyze(hir: &Hir) -> Info {
+    let empty = || Info::Exact(BTreeSet::from([Vec::new()]));
+    match hir.kind() {
+        HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => empty(),
+        HirKind::Literal(Literal::Unicode(c)) => {
+            Info::Exact(BTreeSet::from([c.to_string().into_bytes()]))
+        }
+        HirKind::Literal(Literal::Byte(b)) => Info::Exact(BTreeSet::from([vec![*b]])),
+        HirKind::Class(class) => match class_strings(class) {
+            Some(strings) => Info::Exact(strings),
+            None => Info::Required(Required::Anything),
+        },
+        HirKind::Group(group) => analyze(&group.hir),
+        HirKind::Repetition(repetition) => {
+            let at_least_once = match &repetition.kind {
+                RepetitionKind::ZeroOrOne | RepetitionKind::ZeroOrMore => false,
+                RepetitionKind::OneOrMore => true,
+                RepetitionKind::Range(RepetitionRange::Exactly(m))
+                | RepetitionKind::Range(RepetitionRange::AtLeast(m))
+                | RepetitionKind::Range(RepetitionRange::Bounded(m, _)) => *m > 0,
+            };
+            match at_least_once {
+                true => Info::Required(analyze(&repetition.hir).into_required()),
+                false => Info::Required(Required::Anything),
+            }
+        }
+        HirKind::Concat(parts) => {
+            // Neighbouring exact parts are joined, so the trigrams across them count too. Once
+            // they are cut apart, only the trigrams are kept.
+            let mut cut = false;
+            let mut required = Required::Anything;
+            let mut exact: Option<BTreeSet<Vec<u8>>> = Some(BTreeSet::from([Vec::new()]));
+            for part in parts {
+                match (exact.take(), analyze(part)) {
+                    (Some(left), Info::Exact(right)) if left.len() * right.len() <= MAX_EXACT => {
+                        exact = Some(
+                            left.iter()
+                                .flat_map(|l| right.iter().map(move |r| [&l[..], r].concat()))
+                                .collect(),
+                        );
+                    }
+                    (left, Info::Exact(right)) => {
+                        cut = true;
+                        if let Some(left) = left {
+                            required = required.and(Required::from_strings(&left));
+                        }
+                        exact = Some(right);
+                    }
+                    (left, Info::Required(right)) => {
+                        cut = true;
+                        if let Some(left) = left {
+                            required = required.and(Required::from_strings(&left));
+                        }
+                        required = required.and(right);
+                    }
+                }
+            }
+            match (exact, cut) {
+                (Some(strings), false) => Info::Exact(strings),
+                (Some(strings), true) => {
+                    Info::Required(required.and(Required::from_strings(&strings)))
+                }
+                (None, _) => Info::Required(required),
+            }
+        }
+        HirKind::Alternation(alternatives) => {
+            let infos: Vec<Info> = alternatives.iter().map(analyze).collect();
+            let mut union = BTreeSet::new();
+            for info in &infos {
+                match info {
+                    Info::Exact(strings) if union.len() + strings.len() <= MAX_EXACT => {
+                        union.extend(strings.iter().cloned())
+                    }
+                    _ => {
+                        return Info::Required(
+                            infos
+                                .into_iter()
+                                .map(Info::into_required)
+                                .reduce(Required::or)
+                                .unwrap_or(Required::Anything),
+                        )
+                    }
+                }
+            }
+            Info::Exact(union)
+        }
+    }
+}
+
+// The trigrams every match of the pattern has
+pub fn required_trigrams(pattern: &str) -> Result<Required, Box<dyn Error>> {
+    let hir = regex_syntax::Parser::new().parse(pattern)?;
+    Ok(analyze(&hir).into_required())
+}
+
+fn trigrams(text: &[u8]) -> impl Iterator<Item = Trigram> + '_ {
+    text.windows(3).map(|w| [w[0], w[1], w[2]])
+}
+
+impl Index<Stored<HashMap<Trigram, Vec<usize>>>> {
+    pub fn index10_4(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let mut database: HashMap<Trigram, Vec<usize>> = HashMap::new();
+        // The regex is run on the cleaned text of the articles
+        let mut documents = DocumentStore::default();
+        let mut article_titles: Vec<String> = Vec::new();
+        for (title, contents) in read_and_clean_file_to_iter(config)? {
+            if !title.is_empty() {
+                article_titles.push(title);
+                let article_number = article_titles.len() - 1;
+                let text = contents.join(" ");
+                documents.push(text.as_bytes());
+                for trigram in trigrams(text.as_bytes()) {
+                    let v = database.entry(trigram).or_default();
+                    if v.last() != Some(&article_number) {
+                        v.push(article_number)
+                    }
+                }
+            }
+        }
+
+        Ok(Index {
+            database: Stored::new(database, documents),
+            article_titles,
+        })
+    }
+
+    // The articles that have the required trigrams, or None when that is every article
+    pub fn candidates(&self, required: &Required) -> Option<Vec<usize>> {
+        match required {
+            Required::Anything => None,
+            Required::Trigram(trigram) => {
+                Some(self.database.get(trigram).cloned().unwrap_or_default())
+            }
+            Required::All(parts) => parts
+                .iter()
+                .filter_map(|part| self.candidates(part))
+                .reduce(|a, b| {
+                    let b: HashSet<usize> = b.into_iter().collect();
+                    a.into_iter().filter(|art| b.contains(art)).collect()
+                }),
+            Required::Any(parts) => {
+                let mut union: Vec<usize> = Vec::new();
+                for part in parts {
+                    union.extend(self.candidates(part)?);
+                }
+                union.sort_unstable();
+                union.dedup();
+                Some(union)
+            }
+        }
+    }
+
+    // The byte ranges are those of the cleaned text of the articles
+    pub fn regex_matches(&self, pattern: &str) -> Result<RegexMatches, Box<dyn Error>> {
+        let re = Regex::new(pattern)?;
+        let candidates = match self.candidates(&required_trigrams(pattern)?) {
+            Some(candidates) => candidates,
+            None => (0..self.article_titles.len()).collect(),
+        };
+
+        let mut result = Vec::new();
+        for art_no in candidates {
+            let file_contents = self.article_text(art_no);
+            let spans: Vec<(usize, usize)> = re
+                .find_iter(&file_contents)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            if !spans.is_empty() {
+                result.push((art_no, spans));
+            }
+        }
+        Ok(result)
+    }
+
+    // Patterns that aren't valid regexes match nothing
+    pub fn regex_search(&self, pattern: &str) -> ArticleTitles {
+        match self.regex_matches(pattern) {
+            Ok(matches) => matches
+                .into_iter()
+                .map(|(art_no, _)| self.article_titles[art_no].clone())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl Search for Index<Stored<HashMap<Trigram, Vec<usize>>>> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch => {
+                self.regex_search(&format!(r"\b{}\b", regex::escape(&query.search_string)))
+            }
+            SearchType::ExactSearch(x) if x == "Regex" => self.regex_search(&query.search_string),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigram(s: &str) -> Required {
+        let b = s.as_bytes();
+        Required::Trigram([b[0], b[1], b[2]])
+    }
+
+    #[test]
+    fn trigrams_of_literals_and_alternatives() {
+        assert_eq!(
+            required_trigrams("anarch").unwrap(),
+            Required::All(vec![
+                trigram("ana"),
+                trigram("nar"),
+                trigram("arc"),
+                trigram("rch")
+            ])
+        );
+        assert_eq!(
+            required_trigrams("abc|xyz").unwrap(),
+            Required::Any(vec![trigram("abc"), trigram("xyz")])
+        );
+        // Small classes are expanded, so are case insensitive letters
+        assert_eq!(
+            required_trigrams("(?i:ab)[cd]").unwrap(),
+            Required::from_strings(&BTreeSet::from_iter(
+                ["Abc", "Abd", "aBc", "aBd", "ABc", "ABd", "abc", "abd"]
+                    .iter()
+                    .map(|s| s.as_bytes().to_vec())
+            ))
+        );
+        // Strings too many to expand are cut into parts
+        assert_eq!(
+            required_trigrams("abc[a-z]xyz").unwrap(),
+            Required::All(vec![trigram("abc"), trigram("xyz")])
+        );
+        assert_eq!(
+            required_trigrams("(abcd)+x?").unwrap(),
+            Required::All(vec![trigram("abc"), trigram("bcd")])
+        );
+        // Nothing is known of a match of a.*, and ab is too short
+        assert_eq!(required_trigrams("a.*|xyz").unwrap(), Required::Anything);
+        assert_eq!(required_trigrams("ab").unwrap(), Required::Anything);
+        assert!(required_trigrams("(").is_err());
+    }
+
+    fn setup_real() -> Index<Stored<HashMap<Trigram, Vec<usize>>>> {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "10_4".to_string(),
+        ]);
+        Index::index10_4(&config).unwrap()
+    }
+
+    fn search(
+        index: &Index<Stored<HashMap<Trigram, Vec<usize>>>>,
+        pattern: &str,
+    ) -> HashSet<String> {
+        index.regex_search(pattern).into_iter().collect()
+    }
+
+    fn titles(titles: &[&str]) -> HashSet<String> {
+        titles.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn regex_search_in_wiki100_kb() {
+        let index = setup_real();
+        assert_eq!(search(&index, "autis[mt]"), titles(&["Autism"]));
+        assert_eq!(search(&index, r"\bbi-hemi\w+"), titles(&["Albedo"]));
+        assert_eq!(search(&index, "qqqqq|autism"), titles(&["Autism"]));
+        assert_eq!(search(&index, "zzzzz|qqqqq"), titles(&[]));
+        assert_eq!(search(&index, "(("), titles(&[]));
+    }
+
+    #[test]
+    fn filtering_doesnt_lose_matches() {
+        let index = setup_real();
+        for pattern in [
+            "the [a-z]+ of",
+            "(?i)AUTISM",
+            "[0-9]{4}",
+            "x*",
+            "is(m|t)s? ",
+            "é",
+        ] {
+            let re = Regex::new(pattern).unwrap();
+            let expected: RegexMatches = (0..index.article_titles.len())
+                .map(|art_no| {
+                    let text = index.article_text(art_no);
+                    let spans = re.find_iter(&text).map(|m| (m.start(), m.end())).collect();
+                    (art_no, spans)
+                })
+                .filter(|(_, spans): &(usize, Vec<(usize, usize)>)| !spans.is_empty())
+                .collect();
+            assert_eq!(
+                index.regex_matches(pattern).unwrap(),
+                expected,
+                "{}",
+                pattern
+            );
+        }
+    }
+}
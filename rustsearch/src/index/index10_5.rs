@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet};
+
+use super::docstore::Stored;
+use super::Index;
+
+// Approximate phrase search: the phrase matches wherever a part of the article is at most k
+// edits (inserted, deleted or changed characters) away from it. The edit distances are computed
+// with Myers' bit-parallel algorithm, one bit per character of the phrase, in blocks of 64.
+
+pub const DEFAULT_ERRORS: usize = 1;
+
+// "Myers" allows the default number of errors, "Myers/k" allows k
+pub fn error_budget(search_type: &str) -> Option<usize> {
+    match search_type.strip_prefix("Myers")? {
+        "" => Some(DEFAULT_ERRORS),
+        k => k.strip_prefix('/')?.parse().ok(),
+    }
+}
+
+pub struct Myers {
+    // For each character, the blocks with a bit set where it is in the pattern
+    peq: HashMap<char, Vec<u64>>,
+    len: usize,
+    n_blocks: usize,
+}
+
+// One column of one block. The vertical deltas of the column are kept in pv (+1) and mv (-1), and
+// the horizontal delta coming in at the top of the block is h_in. Returns the horizontal delta
+// going out at the row of high_bit.
+fn advance_block(pv: &mut u64, mv: &mut u64, eq: u64, h_in: i32, high_bit: u64) -> i32 {
+    let xv = eq | *mv;
+    let eq = eq | (h_in < 0) as u64;
+    let xh = ((eq & *pv).wrapping_add(*pv) ^ *pv) | eq;
+    let mut ph = *mv | !(xh | *pv);
+    let mut mh = *pv & xh;
+    let h_out = match (ph & high_bit != 0, mh & high_bit != 0) {
+        (true, _) => 1,
+        (_, true) => -1,
+        _ => 0,
+    };
+    ph <<= 1;
+    mh <<= 1;
+    match h_in {
+        h if h < 0 => mh |= 1,
+        h if h > 0 => ph |= 1,
+        _ => {}
+    }
+    *pv = mh | !(xv | ph);
+    *mv = ph & xv;
+    h_out
+}
+
+impl Myers {
+    pub fn new(pattern: &[char]) -> Self {
+        let n_blocks = pattern.len().div_ceil(64);
+        let mut peq: HashMap<char, Vec<u64>> = HashMap::new();
+        for (i, c) in pattern.iter().enumerate() {
+            peq.entry(*c).or_insert_with(|| vec![0; n_blocks])[i / 64] |= 1 << (i % 64);
+        }
+        Myers {
+            peq,
+            len: pattern.len(),
+            n_blocks,
+        }
+    }
+
+    // The edit distance of the pattern to the best match ending after each character of the text
+    pub fn distances(&self, text: &[char]) -> Vec<usize> {
+        let mut pv = vec![u64::MAX; self.n_blocks];
+        let mut mv = vec![0; self.n_blocks];
+        let no_match = vec![0; self.n_blocks];
+        let last_bit = 1 << ((self.len + 63) % 64);
+        // Matches may start anywhere, so the top row is all zeros and nothing comes in at the top
+        let mut score = self.len;
+        let mut distances = Vec::with_capacity(text.len());
+        for c in text {
+            let eq = self.peq.get(c).unwrap_or(&no_match);
+            let mut h = 0;
+            for b in 0..self.n_blocks {
+                let high_bit = match b == self.n_blocks - 1 {
+                    true => last_bit,
+                    false => 1 << 63,
+                };
+                h = advance_block(&mut pv[b], &mut mv[b], eq[b], h, high_bit);
+            }
+            score = (score as i64 + h as i64) as usize;
+            distances.push(score);
+        }
+        distances
+    }
+
+    // Where matches with at most k errors end, as indices after the last character
+    pub fn match_ends(&self, text: &[char], k: usize) -> Vec<usize> {
+        self.distances(text)
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, d)| d <= k)
+            .map(|(i, _)| i + 1)
+            .collect()
+    }
+
+    pub fn matches_within(&self, text: &[char], k: usize) -> bool {
+        // Nothing to compare against, so the whole pattern is the errors
+        if self.len <= k {
+            return true;
+        }
+        self.distances(text).into_iter().any(|d| d <= k)
+    }
+}
+
+impl Index<Stored<HashMap<String, HashSet<usize>>>> {
+    // An edit changes at most two words of the phrase, or one word into two, so with k errors at
+    // least all but 2k of the different words of the phrase are in a matching article. Only the
+    // articles with that many of the words are searched, or every article if that is none.
+    fn approximate_candidates(&self, words: &[&str], k: usize) -> Vec<usize> {
+        let distinct: HashSet<&str> = words.iter().copied().collect();
+        let needed = match distinct.len().checked_sub(2 * k) {
+            Some(needed) if needed > 0 => needed,
+            _ => return (0..self.article_titles.len()).collect(),
+        };
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for word in distinct {
+            for &article in self.database.get(word).into_iter().flatten() {
+                *counts.entry(article).or_default() += 1;
+            }
+        }
+        let mut candidates: Vec<usize> = counts
+            .into_iter()
+            .filter(|&(_, count)| count >= needed)
+            .map(|(article, _)| article)
+            .collect();
+        candidates.sort_unstable();
+        candidates
+    }
+
+    pub fn approximate_search(&self, query: &str, k: usize) -> Vec<String> {
+        let words: Vec<&str> = query.split(' ').filter(|w| !w.is_empty()).collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+        let p: Vec<char> = words.join(" ").chars().collect();
+        let myers = Myers::new(&p);
+
+        let mut result: Vec<usize> = Vec::new();
+        for art_no in self.approximate_candidates(&words, k) {
+            // Read the article
+            let t: Vec<char> = self.article_text(art_no).chars().collect();
+            if myers.matches_within(&t, k) {
+                result.push(art_no)
+            }
+        }
+        // Result to article names
+        result
+            .iter()
+            .map(|a_no| self.article_titles[*a_no].to_owned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::Config;
+    use crate::index::docstore::DocumentStore;
+    use crate::index::{Query, Search, SearchType};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // The textbook dynamic program, with a free start anywhere in the text
+    fn naive_distances(pattern: &[char], text: &[char]) -> Vec<usize> {
+        let mut column: Vec<usize> = (0..=pattern.len()).collect();
+        let mut distances = Vec::new();
+        for t in text {
+            let mut next = vec![0; pattern.len() + 1];
+            for i in 1..=pattern.len() {
+                let change = column[i - 1] + (pattern[i - 1] != *t) as usize;
+                next[i] = change.min(column[i] + 1).min(next[i - 1] + 1);
+            }
+            distances.push(next[pattern.len()]);
+            column = next;
+        }
+        distances
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    #[test]
+    fn error_budgets() {
+        assert_eq!(error_budget("Myers"), Some(1));
+        assert_eq!(error_budget("Myers/3"), Some(3));
+        assert_eq!(error_budget("Myers/x"), None);
+        assert_eq!(error_budget("Myers3"), None);
+        assert_eq!(error_budget("KMP"), None);
+    }
+
+    #[test]
+    fn small_distances() {
+        let myers = Myers::new(&chars("survey"));
+        assert_eq!(
+            myers.distances(&chars("surgery")),
+            naive_distances(&chars("survey"), &chars("surgery"))
+        );
+        assert_eq!(myers.match_ends(&chars("a surgery"), 2), vec![7, 8, 9]);
+        assert_eq!(myers.match_ends(&chars("a survey"), 0), vec![8]);
+        assert!(!myers.matches_within(&chars("nothing like it"), 2));
+    }
+
+    #[test]
+    fn same_distances_as_dynamic_programming() {
+        let mut rng = StdRng::seed_from_u64(8008135);
+        for len in [1, 5, 63, 64, 65, 130, 200] {
+            for _ in 0..5 {
+                let pattern: Vec<char> = (0..len).map(|_| rng.gen_range('a'..='d')).collect();
+                let mut text: Vec<char> = (0..300).map(|_| rng.gen_range('a'..='e')).collect();
+                // A copy of the pattern with some errors, so there are close matches too
+                let start = rng.gen_range(0..100);
+                for (i, c) in pattern.iter().enumerate() {
+                    if start + i < text.len() && rng.gen_range(0..10) != 0 {
+                        text[start + i] = *c;
+                    }
+                }
+                assert_eq!(
+                    Myers::new(&pattern).distances(&text),
+                    naive_distances(&pattern, &text),
+                    "length {}",
+                    len
+                );
+            }
+        }
+    }
+
+    fn setup_test() -> Index<Stored<HashMap<String, HashSet<usize>>>> {
+        let articles = [
+            "in london the first international workingmen association was founded",
+            "in london the first intemational working men association was founded",
+            "the second international was founded in paris",
+        ];
+        let mut database: HashMap<String, HashSet<usize>> = HashMap::new();
+        let mut article_titles: Vec<String> = Vec::new();
+        for (art_no, contents) in articles.iter().enumerate() {
+            for word in contents.split(' ') {
+                database.entry(word.to_string()).or_default().insert(art_no);
+            }
+            article_titles.push(format!("article {}", art_no));
+        }
+        Index {
+            database: Stored::new(database, DocumentStore::from_articles(&articles)),
+            article_titles,
+        }
+    }
+
+    #[test]
+    fn finds_phrases_with_errors() {
+        let index = setup_test();
+        let query = "london the first international workingmen association was founded";
+        assert_eq!(index.approximate_search(query, 0), vec!["article 0"]);
+        // "rn" read as "m" is two errors, and the space in "working men" is one more
+        assert_eq!(index.approximate_search(query, 2), vec!["article 0"]);
+        assert_eq!(
+            index.approximate_search(query, 3),
+            vec!["article 0", "article 1"]
+        );
+        assert_eq!(
+            index.search(&Query {
+                search_string: "second international was fuonded in paris".to_string(),
+                search_type: SearchType::ExactSearch("Myers".to_string()),
+            }),
+            vec![] as Vec<String>
+        );
+        assert_eq!(
+            index.search(&Query {
+                search_string: "second international was fuonded in paris".to_string(),
+                search_type: SearchType::ExactSearch("Myers/2".to_string()),
+            }),
+            vec!["article 2"]
+        );
+    }
+
+    #[test]
+    fn candidates_need_all_but_two_words_per_error() {
+        let index = setup_test();
+        let words = ["the", "first", "international", "association"];
+        assert_eq!(index.approximate_candidates(&words, 0), vec![0]);
+        assert_eq!(index.approximate_candidates(&words, 1), vec![0, 1, 2]);
+        // Too many errors to rule out any article
+        assert_eq!(index.approximate_candidates(&words, 2).len(), 3);
+    }
+
+    #[test]
+    fn wiki100_kb_with_a_typo() {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "10".to_string(),
+        ]);
+        let index = Index::index10(&config).unwrap();
+        assert_eq!(
+            index.approximate_search("a political phiolsophy which considers", 2),
+            vec!["Anarchism"]
+        );
+        assert_eq!(
+            index.approximate_search("a political phiolsophy which considers", 1),
+            Vec::<String>::new()
+        );
+    }
+}
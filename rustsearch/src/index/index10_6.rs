@@ -0,0 +1,306 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+use crate::helpers::Config;
+
+use super::docstore::Stored;
+use super::index10_0::{kmp_allmatches_slice, kmp_table_slice};
+use super::*;
+
+// Exact phrase search that ignores case, and optionally accents. The phrase and the articles are
+// folded, and the phrase is searched for with KMP in the folded text. Every byte of the folded
+// text knows the character of the original text it came from, so matches are reported as byte
+// ranges of the original text.
+//
+// Case is folded by lowercasing, with 'ß' folded to "ss" and the final sigma to 'σ', as full
+// case folding does. Accents are stripped by decomposing characters and leaving out the
+// combining marks, so 'Ç' becomes 'c'. Letters without a decomposition, such as 'ø', are kept.
+
+// The articles with a match, in order, each with the byte ranges of its matches in the original
+// text of the article
+pub type FoldedMatches = Vec<(usize, Vec<(usize, usize)>)>;
+
+// The articles every folded word occurs in, in increasing order, with the words folded both
+// ways. The candidates for a phrase are looked up here, so no word is folded at query time.
+pub struct FoldedDatabase {
+    case_folded: HashMap<String, Vec<usize>>,
+    accents_folded: HashMap<String, Vec<usize>>,
+}
+
+impl FoldedDatabase {
+    pub fn new(database: &HashMap<String, HashSet<usize>>) -> Self {
+        let folded = |strip_accents: bool| {
+            let mut folded: HashMap<String, HashSet<usize>> = HashMap::new();
+            for (word, articles) in database {
+                folded
+                    .entry(fold(word, strip_accents).text)
+                    .or_default()
+                    .extend(articles);
+            }
+            folded
+                .into_iter()
+                .map(|(word, articles)| {
+                    let mut articles: Vec<usize> = articles.into_iter().collect();
+                    articles.sort_unstable();
+                    (word, articles)
+                })
+                .collect()
+        };
+        FoldedDatabase {
+            case_folded: folded(false),
+            accents_folded: folded(true),
+        }
+    }
+
+    fn postings(&self, folded_word: &str, strip_accents: bool) -> Option<&Vec<usize>> {
+        match strip_accents {
+            true => self.accents_folded.get(folded_word),
+            false => self.case_folded.get(folded_word),
+        }
+    }
+}
+
+// "Folded" ignores case, "Folded/accents" ignores accents too
+pub fn strips_accents(search_type: &str) -> Option<bool> {
+    match search_type {
+        "Folded" => Some(false),
+        "Folded/accents" => Some(true),
+        _ => None,
+    }
+}
+
+pub struct Folded {
+    pub text: String,
+    // For every byte of the folded text, the byte range of the character it came from
+    origins: Vec<(usize, usize)>,
+}
+
+impl Folded {
+    // The range of the original text that the folded bytes start..end came from
+    pub fn original_range(&self, start: usize, end: usize) -> (usize, usize) {
+        (self.origins[start].0, self.origins[end - 1].1)
+    }
+}
+
+fn push_lowercase(c: char, folded: &mut String) {
+    for lower in c.to_lowercase() {
+        match lower {
+            'ß' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            _ => folded.push(lower),
+        }
+    }
+}
+
+pub fn fold(text: &str, strip_accents: bool) -> Folded {
+    let mut folded = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+    for (start, c) in text.char_indices() {
+        let before = folded.len();
+        match strip_accents {
+            true => decompose_canonical(c, |d| {
+                if !is_combining_mark(d) {
+                    push_lowercase(d, &mut folded)
+                }
+            }),
+            false => push_lowercase(c, &mut folded),
+        }
+        let origin = (start, start + c.len_utf8());
+        origins.extend((before..folded.len()).map(|_| origin));
+    }
+    Folded {
+        text: folded,
+        origins,
+    }
+}
+
+impl Index<Stored<FoldedDatabase>> {
+    pub fn index10_6(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let index = Index::index10(config)?;
+        Ok(Index {
+            database: Stored::new(
+                FoldedDatabase::new(&index.database.postings),
+                index.database.documents,
+            ),
+            article_titles: index.article_titles,
+        })
+    }
+
+    // The articles with every word of the phrase, in increasing order
+    fn folded_candidates(&self, folded_words: &[&str], strip_accents: bool) -> Vec<usize> {
+        let mut postings: Vec<&Vec<usize>> = Vec::with_capacity(folded_words.len());
+        for word in folded_words {
+            match self.database.postings(word, strip_accents) {
+                Some(articles) => postings.push(articles),
+                None => return Vec::new(),
+            }
+        }
+        postings.sort_unstable_by_key(|articles| articles.len());
+        let (shortest, rest) = postings.split_first().unwrap();
+        shortest
+            .iter()
+            .copied()
+            .filter(|art| {
+                rest.iter()
+                    .all(|articles| articles.binary_search(art).is_ok())
+            })
+            .collect()
+    }
+
+    pub fn folded_matches(&self, query: &str, strip_accents: bool) -> FoldedMatches {
+        let folded_query = fold(query, strip_accents).text;
+        let words: Vec<&str> = folded_query.split(' ').filter(|w| !w.is_empty()).collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+        let p = folded_query.as_bytes();
+        let table = kmp_table_slice(p);
+
+        let mut result = FoldedMatches::new();
+        for art_no in self.folded_candidates(&words, strip_accents) {
+            let folded = fold(&self.article_text(art_no), strip_accents);
+            let spans: Vec<(usize, usize)> =
+                kmp_allmatches_slice(folded.text.as_bytes(), p, &table)
+                    .into_iter()
+                    .map(|start| folded.original_range(start, start + p.len()))
+                    .collect();
+            if !spans.is_empty() {
+                result.push((art_no, spans));
+            }
+        }
+        result
+    }
+
+    pub fn folded_search(&self, query: &str, strip_accents: bool) -> Vec<String> {
+        self.folded_matches(query, strip_accents)
+            .into_iter()
+            .map(|(a_no, _)| self.article_titles[a_no].to_owned())
+            .collect()
+    }
+}
+
+impl Search for Index<Stored<FoldedDatabase>> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::ExactSearch(x) if strips_accents(x).is_some() => {
+                self.folded_search(&query.search_string, strips_accents(x).unwrap())
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::docstore::DocumentStore;
+
+    #[test]
+    fn folding_keeps_the_original_offsets() {
+        let folded = fold("Große Ça", false);
+        assert_eq!(folded.text, "grosse ça");
+        // "ss" both come from 'ß', bytes 3..5 of the original
+        assert_eq!(folded.original_range(3, 5), (3, 5));
+        assert_eq!(folded.original_range(0, 4), (0, 5));
+        assert_eq!(folded.original_range(7, 9), (7, 9));
+
+        let folded = fold("Große Ça ÖDIPUS øre ΟΔΥΣΣΕΥΣ", true);
+        assert_eq!(folded.text, "grosse ca odipus øre οδυσσευσ");
+        assert_eq!(folded.original_range(7, 9), (7, 10));
+        assert_eq!(folded.original_range(10, 12), (11, 14));
+    }
+
+    fn setup_test() -> Index<Stored<FoldedDatabase>> {
+        let articles = [
+            "Anarchism is a political philosophy and the word anarchism",
+            "Ça dorada and ÇA DORADA",
+            "ca dorada",
+        ];
+        let mut database: HashMap<String, HashSet<usize>> = HashMap::new();
+        let mut article_titles: Vec<String> = Vec::new();
+        for (art_no, contents) in articles.iter().enumerate() {
+            for word in contents.split(' ') {
+                database.entry(word.to_string()).or_default().insert(art_no);
+            }
+            article_titles.push(format!("article {}", art_no));
+        }
+        Index {
+            database: Stored::new(
+                FoldedDatabase::new(&database),
+                DocumentStore::from_articles(&articles),
+            ),
+            article_titles,
+        }
+    }
+
+    #[test]
+    fn matches_in_the_original_text() {
+        let index = setup_test();
+        assert_eq!(
+            index.folded_matches("anarchism", false),
+            vec![(0, vec![(0, 9), (49, 58)])]
+        );
+        assert_eq!(
+            index.folded_matches("ça dorada", false),
+            vec![(1, vec![(0, 10), (15, 25)])]
+        );
+        assert_eq!(
+            index.folded_matches("ca dorada", true),
+            vec![(1, vec![(0, 10), (15, 25)]), (2, vec![(0, 9)])]
+        );
+        assert_eq!(
+            index.folded_matches("ca dorada", false),
+            vec![(2, vec![(0, 9)])]
+        );
+        assert_eq!(index.folded_matches(" ", false), vec![]);
+        assert_eq!(index.folded_matches("dorada nowhere", true), vec![]);
+        // Every word of the phrase has to be in the article, not only the first one
+        assert_eq!(
+            index.folded_matches("dorada and", true),
+            vec![(1, vec![(4, 14)])]
+        );
+        assert_eq!(index.folded_matches("anarchism anarchism", false), vec![]);
+
+        let query = Query {
+            search_string: "POLITICAL philosophy".to_string(),
+            search_type: SearchType::ExactSearch("Folded".to_string()),
+        };
+        assert_eq!(index.search(&query), vec!["article 0"]);
+        let query = Query {
+            search_string: "ÇA DORADA".to_string(),
+            search_type: SearchType::ExactSearch("Folded/accents".to_string()),
+        };
+        assert_eq!(index.search(&query), vec!["article 1", "article 2"]);
+    }
+
+    #[test]
+    fn wiki100_kb_without_case_and_accents() {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "10_6".to_string(),
+        ]);
+        assert_eq!(
+            Index::index10(&config)
+                .unwrap()
+                .kmp_search(&"anarchism is a political philosophy".to_string()),
+            Vec::<String>::new()
+        );
+        let index = Index::index10_6(&config).unwrap();
+        assert_eq!(
+            index.folded_search("anarchism is a political philosophy", false),
+            vec!["Anarchism"]
+        );
+        assert_eq!(
+            index.folded_search("confederacion nacional del trabajo", false),
+            Vec::<String>::new()
+        );
+        let matches = index.folded_matches("confederacion nacional del trabajo", true);
+        assert_eq!(matches.len(), 1);
+        let text = index.article_text(matches[0].0);
+        let (start, end) = matches[0].1[0];
+        assert_eq!(&text[start..end], "Confederación Nacional del Trabajo");
+    }
+}
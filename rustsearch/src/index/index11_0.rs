@@ -4,15 +4,15 @@ use std::error::Error;
 use crate::helpers::*;
 use crate::index::Index;
 
+use super::docstore::{DocumentStore, Stored};
 use super::*;
 
-impl Index<HashMap<(String, String, String), Vec<usize>>> {
+impl Index<Stored<HashMap<(String, String, String), Vec<usize>>>> {
     pub fn index11(config: &Config) -> Result<Self, Box<dyn Error>> {
-        // Read all articles, run them through the regex and write each individual article to a file named in the format 00xxx.txt
-        write_article_files(config);
-
         // Setup
         let mut database: HashMap<(String, String, String), Vec<usize>> = HashMap::new();
+        // The exact search verifies its candidates against the text of the articles
+        let mut documents = DocumentStore::default();
 
         let articles_iter = read_and_clean_file_to_iter(config)?;
 
@@ -23,6 +23,7 @@ impl Index<HashMap<(String, String, String), Vec<usize>>> {
             let mut contents_iter = contents.iter();
             if title != "" {
                 article_titles.push(title.to_string());
+                documents.push(contents.join(" ").as_bytes());
 
                 let mut prv1 = match contents_iter.next() {Some(value) => value , None => continue};
                 let mut prv2 = match contents_iter.next() {Some(value) => value , None => continue};
@@ -42,7 +43,7 @@ impl Index<HashMap<(String, String, String), Vec<usize>>> {
         }
 
         Ok(Index {
-            database,
+            database: Stored::new(database, documents),
             article_titles,
         })
     }
@@ -97,7 +98,7 @@ impl Index<HashMap<(String, String, String), Vec<usize>>> {
     }
 }
 
-impl Search for Index<HashMap<(String, String, String), Vec<usize>>> {
+impl Search for Index<Stored<HashMap<(String, String, String), Vec<usize>>>> {
     fn search(&self, query: &Query) -> ArticleTitles {
         match &query.search_type {
             SearchType::FuzzySearch => self.fuzzy_triples_search(&query.search_string),
@@ -113,7 +114,7 @@ impl Search for Index<HashMap<(String, String, String), Vec<usize>>> {
 mod tests {
     use super::*;
 
-    fn setup_real() -> Index<HashMap<(String, String, String), Vec<usize>>> {
+    fn setup_real() -> Index<Stored<HashMap<(String, String, String), Vec<usize>>>> {
         let config = Config::build(&[
             "".to_string(),
             "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
@@ -122,7 +123,7 @@ mod tests {
         Index::index11(&config).unwrap()
     }
 
-    fn setup_test() -> Index<HashMap<(String, String, String), Vec<usize>>> {
+    fn setup_test() -> Index<Stored<HashMap<(String, String, String), Vec<usize>>>> {
         let mut database: HashMap<(String, String, String), Vec<usize>> = HashMap::new();
         database.insert(
             (
@@ -161,13 +162,13 @@ mod tests {
             article_titles.push(format!("article {}", i).to_string());
         }
         Index {
-            database,
+            database: Stored::new(database, DocumentStore::default()),
             article_titles,
         }
     }
 
     fn search_match(
-        index: Index<HashMap<(String, String, String), Vec<usize>>>,
+        index: Index<Stored<HashMap<(String, String, String), Vec<usize>>>>,
         query: Query,
         expected: Vec<String>,
     ) {
@@ -1,13 +1,10 @@
 #![allow(non_snake_case)]
 use crate::index::index10_1::{boyer_moore, boyer_moore_preprocess};
-use std::{
-    collections::{HashMap, HashSet},
-    fs,
-};
+use std::collections::{HashMap, HashSet};
 
-use super::Index;
+use super::{docstore::Stored, Index, Occurrences};
 
-impl Index<HashMap<(String, String, String), Vec<usize>>> {
+impl Index<Stored<HashMap<(String, String, String), Vec<usize>>>> {
     fn article_intersection_from_query(&self, query: &String) -> Option<HashSet<&usize>> {
         // Split sentence into words
         // Get article set for each word, and find intersection
@@ -62,25 +59,16 @@ impl Index<HashMap<(String, String, String), Vec<usize>>> {
 
         // With the intersection, we can now go through each article that "pass the test" of having the correct triples,
         // and actually linear search through them to find the correct answers
-        let p: Vec<char> = query.chars().collect();
+        // Bytes rather than chars, as a UTF-8 pattern only matches UTF-8 text at char boundaries
+        let p = query.as_bytes();
 
         let mut result: Vec<usize> = Vec::new();
-        let (L_prime, l_prime, R,_) = boyer_moore_preprocess(&p);
+        let (L_prime, l_prime, R,_) = boyer_moore_preprocess(p);
 
         for art_no in art_intersect {
-            // Read the file
-            let t: Vec<char> =
-                fs::read_to_string(format!("data/individual_articles/{:08}.txt", art_no))
-                    .expect(
-                        format!(
-                            "Article number {} not found in data/individual_articles/",
-                            art_no
-                        )
-                        .as_str(),
-                    )
-                    .chars()
-                    .collect();
-            match boyer_moore(&p, &t, (&L_prime, &l_prime, &R)) {
+            // Read the article
+            let t = self.article_bytes(*art_no);
+            match boyer_moore(p, &t, (&L_prime, &l_prime, &R)) {
                 x if x.len() == 0 => (),   // Empty vector
                 _ => result.push(*art_no), // There was at least one occurence
             }
@@ -91,18 +79,28 @@ impl Index<HashMap<(String, String, String), Vec<usize>>> {
             .map(|a_no| self.article_titles[*a_no].to_owned())
             .collect()
     }
+
+    // Every occurrence of the phrase, as byte offsets in the articles
+    pub fn exact_triples_occurrences(&self, query: &String) -> Occurrences {
+        let mut art_intersect: Vec<usize> = match self.article_intersection_from_query(query) {
+            None => return vec![],
+            Some(x) => x.into_iter().copied().collect(),
+        };
+        art_intersect.sort_unstable();
+        self.boyer_moore_occurrences_in(query.as_bytes(), art_intersect)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         helpers::Config,
-        index::{Query, Search, SearchType},
+        index::{docstore::DocumentStore, Query, Search, SearchType},
     };
 
     use super::*;
 
-    fn setup_real() -> Index<HashMap<(String, String, String), Vec<usize>>> {
+    fn setup_real() -> Index<Stored<HashMap<(String, String, String), Vec<usize>>>> {
         let config = Config::build(&[
             "".to_string(),
             "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
@@ -111,7 +109,7 @@ mod tests {
         Index::index11(&config).unwrap()
     }
 
-    fn setup_test() -> Index<HashMap<(String, String, String), Vec<usize>>> {
+    fn setup_test() -> Index<Stored<HashMap<(String, String, String), Vec<usize>>>> {
         let mut database: HashMap<(String, String, String), Vec<usize>> = HashMap::new();
         database.insert(
             (
@@ -150,40 +148,26 @@ mod tests {
             article_titles.push(format!("article {}", i).to_string());
         }
 
-        // Write the actual files
-        fs::write(format!("data/individual_articles/{:08}.txt", 0),
+        // The text of the articles
+        let documents = DocumentStore::from_articles(&[
             "word1 word2 word3 . word2 word3 word4 . word3 word4 word5",
-        ).unwrap();
-        fs::write(format!("data/individual_articles/{:08}.txt", 1),
             "word2 word3 word4 . word4 word5 word6",
-        ).unwrap();
-        fs::write(format!("data/individual_articles/{:08}.txt", 2),
             "word4 word5 word6 . word2 word3 word4 . word3 word4 word5",
-        ).unwrap();
-        fs::write(format!("data/individual_articles/{:08}.txt", 3),
             "word4 word5 word6 . word2 word3 word4",
-        ).unwrap();
-        fs::write(format!("data/individual_articles/{:08}.txt", 4),
             "word2 word3 word4 word5",
-        ).unwrap();
-        fs::write(format!("data/individual_articles/{:08}.txt", 5),
             "word2 word3 word4",
-        ).unwrap();
-        fs::write(format!("data/individual_articles/{:08}.txt", 6),
-        "word2 word3 word4 word5",
-        ).unwrap();
-        fs::write(format!("data/individual_articles/{:08}.txt", 7),
-        "word2 word3 word4",
-        ).unwrap();
-        
+            "word2 word3 word4 word5",
+            "word2 word3 word4",
+        ]);
+
         Index {
-            database,
+            database: Stored::new(database, documents),
             article_titles,
         }
     }
 
     fn search_match(
-        index: Index<HashMap<(String, String, String), Vec<usize>>>,
+        index: Index<Stored<HashMap<(String, String, String), Vec<usize>>>>,
         query: Query,
         expected: Vec<String>,
     ) {
@@ -279,6 +263,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn occurrences_of_three_words() {
+        let index = setup_test();
+        let occurrences = index.exact_triples_occurrences(&"word3 word4 word5".to_string());
+        assert_eq!(
+            occurrences,
+            vec![(0, vec![40]), (2, vec![40]), (4, vec![6]), (6, vec![6])]
+        );
+        assert_eq!(
+            index.exact_triples_occurrences(&"word2 word3 word4".to_string())[0],
+            (0, vec![20])
+        );
+        assert_eq!(
+            index.exact_triples_occurrences(&"hej med".to_string()),
+            vec![]
+        );
+    }
+
     #[test]
     fn fuzzy_search_gives_the_wrong_answer_real() {
         let index = setup_real();
@@ -0,0 +1,508 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::helpers::*;
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::*;
+
+// Where each word occurs, for the NEAR/k and ONEAR/k operators. A word has the articles it
+// occurs in and, for each of them, its positions in the article, counting the words from 0. The
+// terms of the title are counted after the words of the body.
+//
+// A boolean query is evaluated to the matching articles along with the spans of words that made
+// them match. A word matches at each of its positions, and a quoted phrase wherever its words
+// are right after each other, with the span of the whole phrase. NEAR/k joins a span of its left side
+// with a span of its right side when at most k words apart, i.e. NEAR/1 is next to each other.
+// And and Or keep the spans of both sides, while an inverted query has no spans, so it never
+// matches as a side of NEAR.
+
+#[derive(Default)]
+pub struct Positions {
+    pub articles: Vec<usize>,
+    pub positions: Vec<Vec<u32>>,
+}
+
+// The words from start to end, both included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+// The matching articles in increasing order, each with its sorted spans
+pub type Matches = Vec<(usize, Vec<Span>)>;
+
+fn merge_spans(mut left: Vec<Span>, right: Vec<Span>) -> Vec<Span> {
+    left.extend(right);
+    left.sort_unstable();
+    left.dedup();
+    left
+}
+
+pub fn and(left: Matches, right: Matches) -> Matches {
+    let mut result = Matches::new();
+    let mut right = right.into_iter().peekable();
+    for (article, spans) in left {
+        while right.next_if(|(a, _)| *a < article).is_some() {}
+        if let Some((_, right_spans)) = right.next_if(|(a, _)| *a == article) {
+            result.push((article, merge_spans(spans, right_spans)));
+        }
+    }
+    result
+}
+
+pub fn or(left: Matches, right: Matches) -> Matches {
+    let mut result = Matches::with_capacity(left.len().max(right.len()));
+    let mut right = right.into_iter().peekable();
+    for (article, spans) in left {
+        while let Some(smaller) = right.next_if(|(a, _)| *a < article) {
+            result.push(smaller);
+        }
+        match right.next_if(|(a, _)| *a == article) {
+            Some((_, right_spans)) => result.push((article, merge_spans(spans, right_spans))),
+            None => result.push((article, spans)),
+        }
+    }
+    result.extend(right);
+    result
+}
+
+// Adds the spans made of a span of first and a span of second starting 1 to distance words after
+// it ends. first is gone through by the ends of its spans, and second is sorted by their starts,
+// so the spans of second that can follow are a window that only moves forward.
+fn spans_followed_by(first: &[Span], second: &[Span], distance: u32, spans: &mut Vec<Span>) {
+    let mut by_end: Vec<&Span> = first.iter().collect();
+    by_end.sort_unstable_by_key(|span| span.end);
+    let (mut lo, mut hi) = (0, 0);
+    for f in by_end {
+        while lo < second.len() && second[lo].start <= f.end {
+            lo += 1;
+        }
+        while hi < second.len() && second[hi].start <= f.end.saturating_add(distance) {
+            hi += 1;
+        }
+        spans.extend(second[lo..hi].iter().map(|s| Span {
+            start: f.start,
+            end: s.end,
+        }));
+    }
+}
+
+// The spans made of a left span and a right span at most distance words apart
+pub fn near_spans(left: &[Span], right: &[Span], distance: usize, ordered: bool) -> Vec<Span> {
+    let distance = u32::try_from(distance).unwrap_or(u32::MAX);
+    let mut spans = Vec::new();
+    spans_followed_by(left, right, distance, &mut spans);
+    if !ordered {
+        spans_followed_by(right, left, distance, &mut spans);
+    }
+    spans.sort_unstable();
+    spans.dedup();
+    spans
+}
+
+pub fn near(left: Matches, right: Matches, distance: usize, ordered: bool) -> Matches {
+    let mut result = Matches::new();
+    let mut right = right.into_iter().peekable();
+    for (article, spans) in left {
+        while right.next_if(|(a, _)| *a < article).is_some() {}
+        if let Some((_, right_spans)) = right.next_if(|(a, _)| *a == article) {
+            let spans = near_spans(&spans, &right_spans, distance, ordered);
+            if !spans.is_empty() {
+                result.push((article, spans));
+            }
+        }
+    }
+    result
+}
+
+impl Index<HashMap<String, Positions>> {
+    pub fn index11_2(config: &Config) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_articles(read_and_clean_file_to_iter(config)?))
+    }
+
+    pub fn from_articles(articles: Vec<(String, Vec<String>)>) -> Self {
+        let mut database: HashMap<String, Positions> = HashMap::new();
+        let mut article_titles: Vec<String> = Vec::new();
+
+        for (title, contents) in articles {
+            if !title.is_empty() {
+                let article = article_titles.len();
+                let terms = article_terms(&title, &contents);
+                article_titles.push(title);
+                for (position, word) in terms.into_iter().enumerate() {
+                    let p = database.entry(word).or_default();
+                    if p.articles.last() != Some(&article) {
+                        p.articles.push(article);
+                        p.positions.push(Vec::new());
+                    }
+                    p.positions.last_mut().unwrap().push(position as u32);
+                }
+            }
+        }
+
+        Index {
+            database,
+            article_titles,
+        }
+    }
+
+    // The words of a phrase are found at the positions after the first word
+    fn phrase_matches(&self, words: &[&str]) -> Matches {
+        let postings: Vec<&Positions> = match words.iter().map(|w| self.database.get(*w)).collect()
+        {
+            Some(postings) => postings,
+            None => return Vec::new(),
+        };
+        let last = words.len() as u32 - 1;
+        let mut result = Matches::new();
+        for (i, &article) in postings[0].articles.iter().enumerate() {
+            let rest: Vec<&Vec<u32>> = match postings[1..]
+                .iter()
+                .map(|p| Some(&p.positions[p.articles.binary_search(&article).ok()?]))
+                .collect()
+            {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let spans: Vec<Span> = postings[0].positions[i]
+                .iter()
+                .filter(|&&start| {
+                    rest.iter()
+                        .zip(start + 1..)
+                        .all(|(positions, pos)| positions.binary_search(&pos).is_ok())
+                })
+                .map(|&start| Span {
+                    start,
+                    end: start + last,
+                })
+                .collect();
+            if !spans.is_empty() {
+                result.push((article, spans));
+            }
+        }
+        result
+    }
+
+    fn invert(&self, matches: Matches) -> Matches {
+        let mut matches = matches.into_iter().map(|(a, _)| a).peekable();
+        (0..self.article_titles.len())
+            .filter(|&article| matches.next_if_eq(&article).is_none())
+            .map(|article| (article, Vec::new()))
+            .collect()
+    }
+
+    pub fn evaluate_syntax_tree_positional(&self, node: &AstNode) -> Matches {
+        match node {
+            AstNode::Invert(child) => self.invert(self.evaluate_syntax_tree_positional(child)),
+            AstNode::Binary(op, left_child, right_child) => {
+                let left = self.evaluate_syntax_tree_positional(left_child);
+                let right = self.evaluate_syntax_tree_positional(right_child);
+                match *op {
+                    BinaryOp::And => and(left, right),
+                    BinaryOp::Or => or(left, right),
+                    BinaryOp::Near { distance, ordered } => near(left, right, distance, ordered),
+                }
+            }
+            AstNode::Name(name) => {
+                let words: Vec<&str> = name.split(' ').collect();
+                self.phrase_matches(&words)
+            }
+        }
+    }
+
+    // The titles of the matching articles, with the spans that matched in each
+    pub fn near_search(&self, exp: &str) -> Vec<(String, Vec<Span>)> {
+        match Expr::from_string_with_positions(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => self
+                .evaluate_syntax_tree_positional(&node)
+                .into_iter()
+                .map(|(article, spans)| (self.article_titles[article].to_string(), spans))
+                .collect(),
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn single_search(&self, word: &str) -> ArticleTitles {
+        match self.database.get(word) {
+            Some(p) => p
+                .articles
+                .iter()
+                .map(|&a| self.article_titles[a].to_string())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Search for Index<HashMap<String, Positions>> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch => self.single_search(&query.search_string),
+            SearchType::BooleanSearch(_) => self
+                .near_search(&query.search_string)
+                .into_iter()
+                .map(|(title, _)| title)
+                .collect(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::index::gen_query::gen_a_lot_of_runs_bool;
+
+    const FILE: &str = "data/WestburyLab.wikicorp.201004_100KB.txt";
+
+    fn config() -> Config {
+        Config {
+            file_path: FILE.to_string(),
+            indexno: "11_2".to_string(),
+        }
+    }
+
+    fn setup_test() -> Index<HashMap<String, Positions>> {
+        let articles = [
+            ("article 0", "the quick brown fox jumps over the lazy dog"),
+            ("article 1", "the dog chases the fox"),
+            ("article 2", "a fox"),
+            ("article 3", "no animals here"),
+        ];
+        Index::<HashMap<String, Positions>>::from_articles(
+            articles
+                .iter()
+                .map(|(title, contents)| {
+                    (
+                        title.to_string(),
+                        contents.split(' ').map(|w| w.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn span(start: u32, end: u32) -> Span {
+        Span { start, end }
+    }
+
+    fn found(index: &Index<HashMap<String, Positions>>, exp: &str) -> Vec<(String, Vec<Span>)> {
+        index.near_search(exp)
+    }
+
+    #[test]
+    fn near_spans_match_every_pair() {
+        let mut rng = StdRng::seed_from_u64(8008135);
+        let mut random_spans = |n: usize| -> Vec<Span> {
+            let mut spans: Vec<Span> = (0..n)
+                .map(|_| {
+                    let start = rng.gen_range(0..40);
+                    span(start, start + rng.gen_range(0..4))
+                })
+                .collect();
+            spans.sort_unstable();
+            spans.dedup();
+            spans
+        };
+        for _ in 0..200 {
+            let (left, right) = (random_spans(8), random_spans(8));
+            for distance in [0, 1, 3, usize::MAX] {
+                for ordered in [false, true] {
+                    let mut expected = Vec::new();
+                    for l in &left {
+                        for r in &right {
+                            if l.end < r.start && (r.start - l.end) as usize <= distance {
+                                expected.push(span(l.start, r.end));
+                            } else if !ordered
+                                && r.end < l.start
+                                && (l.start - r.end) as usize <= distance
+                            {
+                                expected.push(span(r.start, l.end));
+                            }
+                        }
+                    }
+                    expected.sort_unstable();
+                    expected.dedup();
+                    assert_eq!(near_spans(&left, &right, distance, ordered), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn positions_are_recorded() {
+        let index = setup_test();
+        let the = &index.database["the"];
+        assert_eq!(the.articles, vec![0, 1]);
+        assert_eq!(the.positions, vec![vec![0, 6], vec![0, 3]]);
+    }
+
+    #[test]
+    fn near_in_either_order() {
+        let index = setup_test();
+        assert_eq!(
+            found(&index, "fox near/3 dog"),
+            vec![("article 1".to_string(), vec![span(1, 4)]),]
+        );
+        assert_eq!(
+            found(&index, "fox near/5 dog"),
+            vec![
+                ("article 0".to_string(), vec![span(3, 8)]),
+                ("article 1".to_string(), vec![span(1, 4)]),
+            ]
+        );
+        // Next to each other
+        assert_eq!(
+            found(&index, "brown NEAR/1 fox"),
+            vec![("article 0".to_string(), vec![span(2, 3)])]
+        );
+        assert!(found(&index, "quick NEAR/1 fox").is_empty());
+    }
+
+    #[test]
+    fn ordered_near() {
+        let index = setup_test();
+        assert_eq!(
+            found(&index, "fox onear/5 dog"),
+            vec![("article 0".to_string(), vec![span(3, 8)])]
+        );
+        assert_eq!(
+            found(&index, "dog onear/5 fox"),
+            vec![("article 1".to_string(), vec![span(1, 4)])]
+        );
+        // A word is never near itself
+        assert!(found(&index, "fox near/3 fox").is_empty());
+        assert_eq!(
+            found(&index, "the onear/6 the"),
+            vec![
+                ("article 0".to_string(), vec![span(0, 6)]),
+                ("article 1".to_string(), vec![span(0, 3)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn near_with_other_operators() {
+        let index = setup_test();
+        // Each side of near can be any query with spans
+        assert_eq!(
+            found(&index, "(quick | chases) near/2 fox"),
+            vec![
+                ("article 0".to_string(), vec![span(1, 3)]),
+                ("article 1".to_string(), vec![span(2, 4)]),
+            ]
+        );
+        assert_eq!(
+            found(&index, "(brown near/1 fox) onear/2 over"),
+            vec![("article 0".to_string(), vec![span(2, 5)])]
+        );
+        // The spans of both sides of an and are kept
+        assert_eq!(
+            found(&index, "a & fox"),
+            vec![("article 2".to_string(), vec![span(0, 0), span(1, 1)])]
+        );
+        assert!(found(&index, "!dog near/10 fox").is_empty());
+        assert_eq!(
+            found(&index, "!(fox near/3 dog)"),
+            vec![
+                ("article 0".to_string(), vec![]),
+                ("article 2".to_string(), vec![]),
+                ("article 3".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn phrases_are_spans() {
+        let index = setup_test();
+        assert_eq!(
+            found(&index, "\"the fox\" | \"the lazy dog\""),
+            vec![
+                ("article 0".to_string(), vec![span(6, 8)]),
+                ("article 1".to_string(), vec![span(3, 4)]),
+            ]
+        );
+        assert_eq!(
+            found(&index, "\"a fox\" | (\"quick brown\" onear/2 jumps)"),
+            vec![
+                ("article 0".to_string(), vec![span(1, 4)]),
+                ("article 2".to_string(), vec![span(0, 1)]),
+            ]
+        );
+        assert!(found(&index, "\"fox the\"").is_empty());
+        assert_eq!(
+            found(&index, "Title:2 & fox"),
+            vec![("article 2".to_string(), vec![span(1, 1), span(3, 3)])]
+        );
+        assert!(found(&index, "\"the cat\"").is_empty());
+        assert_eq!(found(&index, "!\"the fox\"").len(), 3);
+    }
+
+    #[test]
+    fn boolean_queries_match_index8() {
+        let index = Index::index11_2(&config()).unwrap();
+        let index8 = Index::index8(&config()).unwrap();
+        for depth_vec in gen_a_lot_of_runs_bool(FILE.to_string(), 10) {
+            for q in depth_vec {
+                let query = Query {
+                    search_string: q.clone(),
+                    search_type: SearchType::BooleanSearch("Naive".to_string()),
+                };
+                assert_eq!(index.search(&query), index8.search(&query), "{}", q);
+            }
+        }
+    }
+
+    #[test]
+    fn near_matches_a_scan_of_the_articles() {
+        let articles: Vec<(String, Vec<String>)> = read_and_clean_file_to_iter(&config())
+            .unwrap()
+            .into_iter()
+            .filter(|(title, _)| !title.is_empty())
+            .collect();
+        let index = Index::index11_2(&config()).unwrap();
+        let pairs = [
+            ("the", "of"),
+            ("of", "the"),
+            ("anarchism", "is"),
+            ("a", "and"),
+        ];
+        for (a, b) in pairs {
+            for (distance, ordered) in [(1, false), (1, true), (4, false), (4, true)] {
+                let op = if ordered { "onear" } else { "near" };
+                let matches = index.evaluate_syntax_tree_positional(&AstNode::Binary(
+                    BinaryOp::Near { distance, ordered },
+                    Box::new(AstNode::Name(a.to_string())),
+                    Box::new(AstNode::Name(b.to_string())),
+                ));
+                let expected: Vec<usize> = articles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, words))| {
+                        (0..words.len()).any(|i| {
+                            words[i] == a
+                                && (i + 1..words.len().min(i + distance + 1))
+                                    .chain(match ordered {
+                                        true => 0..0,
+                                        false => i.saturating_sub(distance)..i,
+                                    })
+                                    .any(|j| words[j] == b)
+                        })
+                    })
+                    .map(|(article, _)| article)
+                    .collect();
+                let articles_found: Vec<usize> = matches.iter().map(|(a, _)| *a).collect();
+                assert_eq!(articles_found, expected, "{} {}/{} {}", a, op, distance, b);
+                for (_, spans) in &matches {
+                    assert!(spans.iter().all(|s| s.end - s.start <= distance as u32));
+                }
+            }
+        }
+    }
+}
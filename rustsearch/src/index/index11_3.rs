@@ -0,0 +1,402 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::ops::Bound;
+
+use crate::helpers::*;
+use crate::index::index10_1::{boyer_moore_preprocess, boyer_moore_truefalse};
+use crate::index::merge::{and, invert, or};
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::docstore::{DocumentStore, Stored};
+use super::*;
+
+// index11 with grams of any n instead of triples. Words are interned, so a gram is the ids of its
+// words rather than n strings.
+//
+// At every position of an article the n words starting there are a gram, and near the end of the
+// article, where there are fewer than n words left, the words that are left are. So every run of
+// at most n words in an article is the start of one of its grams, and grams are kept sorted to
+// find the grams starting with a run. Queries with more than n words are split into overlapping
+// grams of n words, as index11 does with triples.
+//
+// Boolean expressions may have quoted phrases as well as words, like "mutual aid" & !"state
+// socialism". Every phrase is looked up as an exact search, and the sorted article lists of the
+// words and phrases are merged as in index8.
+
+pub type TermId = u32;
+
+pub const DEFAULT_N: usize = 3;
+
+pub struct NgramDatabase {
+    n: usize,
+    term_ids: HashMap<String, TermId>,
+    // The articles each word occurs in, by the id of the word
+    word_postings: Vec<Vec<usize>>,
+    grams: BTreeMap<Box<[TermId]>, Vec<usize>>,
+}
+
+fn intersect(a: Vec<usize>, b: &[usize]) -> Vec<usize> {
+    let b: HashSet<&usize> = HashSet::from_iter(b);
+    a.into_iter().filter(|art| b.contains(art)).collect()
+}
+
+impl NgramDatabase {
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "grams have at least one word");
+        NgramDatabase {
+            n,
+            term_ids: HashMap::new(),
+            word_postings: Vec::new(),
+            grams: BTreeMap::new(),
+        }
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    pub fn n_terms(&self) -> usize {
+        self.word_postings.len()
+    }
+
+    fn intern(&mut self, word: &str) -> TermId {
+        match self.term_ids.get(word) {
+            Some(&id) => id,
+            None => {
+                let id = self.word_postings.len() as TermId;
+                self.term_ids.insert(word.to_string(), id);
+                self.word_postings.push(Vec::new());
+                id
+            }
+        }
+    }
+
+    // Articles have to be added in increasing order
+    pub fn add_article(&mut self, article: usize, contents: &[String]) {
+        let ids: Vec<TermId> = contents.iter().map(|w| self.intern(w)).collect();
+        for &id in &ids {
+            let v = &mut self.word_postings[id as usize];
+            if v.last() != Some(&article) {
+                v.push(article)
+            }
+        }
+        for start in 0..ids.len() {
+            let gram = &ids[start..(start + self.n).min(ids.len())];
+            let v = self.grams.entry(gram.into()).or_default();
+            if v.last() != Some(&article) {
+                v.push(article)
+            }
+        }
+    }
+
+    // None if a word isn't in any article
+    pub fn term_ids(&self, words: &[&str]) -> Option<Vec<TermId>> {
+        words
+            .iter()
+            .map(|w| self.term_ids.get(*w).copied())
+            .collect()
+    }
+
+    // The articles where the words occur right after each other, for at most n words
+    pub fn gram_postings(&self, ids: &[TermId]) -> Vec<usize> {
+        match ids.len() {
+            0 => Vec::new(),
+            1 => self.word_postings[ids[0] as usize].clone(),
+            len if len == self.n => self.grams.get(ids).cloned().unwrap_or_default(),
+            _ => {
+                let mut articles: Vec<usize> = self
+                    .grams
+                    .range::<[TermId], _>((Bound::Included(ids), Bound::Unbounded))
+                    .take_while(|(gram, _)| gram.starts_with(ids))
+                    .flat_map(|(_, articles)| articles.iter().copied())
+                    .collect();
+                articles.sort_unstable();
+                articles.dedup();
+                articles
+            }
+        }
+    }
+
+    // The articles with every gram of the words. For at most n words, those are exactly the
+    // articles with the words after each other.
+    pub fn articles_with_grams(&self, words: &[&str]) -> Vec<usize> {
+        let ids = match self.term_ids(words) {
+            Some(ids) if !ids.is_empty() => ids,
+            _ => return Vec::new(),
+        };
+        if ids.len() <= self.n {
+            return self.gram_postings(&ids);
+        }
+        let mut grams = ids.windows(self.n);
+        let first = self.gram_postings(grams.next().unwrap());
+        grams.fold(first, |acc, gram| match acc.is_empty() {
+            true => acc,
+            false => intersect(acc, &self.gram_postings(gram)),
+        })
+    }
+}
+
+impl Index<Stored<NgramDatabase>> {
+    pub fn index11_3(config: &Config) -> Result<Self, Box<dyn Error>> {
+        Self::ngram_index(config, DEFAULT_N)
+    }
+
+    pub fn ngram_index(config: &Config, n: usize) -> Result<Self, Box<dyn Error>> {
+        let mut database = NgramDatabase::new(n);
+        // The exact search verifies its candidates against the text of the articles
+        let mut documents = DocumentStore::default();
+        let mut article_titles: Vec<String> = Vec::new();
+        for (title, contents) in read_and_clean_file_to_iter(config)? {
+            if !title.is_empty() {
+                article_titles.push(title);
+                documents.push(contents.join(" ").as_bytes());
+                database.add_article(article_titles.len() - 1, &contents);
+            }
+        }
+
+        Ok(Index {
+            database: Stored::new(database, documents),
+            article_titles,
+        })
+    }
+
+    fn vec_to_articlelist(&self, articles: Vec<usize>) -> ArticleTitles {
+        articles
+            .into_iter()
+            .map(|a_no| self.article_titles[a_no].to_owned())
+            .collect()
+    }
+
+    pub fn fuzzy_ngram_search(&self, query: &str) -> ArticleTitles {
+        let words: Vec<&str> = query.split_ascii_whitespace().collect();
+        self.vec_to_articlelist(self.database.articles_with_grams(&words))
+    }
+
+    pub fn exact_ngram_search(&self, query: &str) -> ArticleTitles {
+        let words: Vec<&str> = query.split_ascii_whitespace().collect();
+        self.vec_to_articlelist(self.phrase_articles(&words))
+    }
+
+    // The articles with the words right after each other, in increasing order
+    fn phrase_articles(&self, words: &[&str]) -> Vec<usize> {
+        let candidates = self.database.articles_with_grams(words);
+        if words.len() <= self.database.n {
+            return candidates;
+        }
+
+        // The grams may be spread over the article, so the phrase is searched for in the
+        // candidates, as in index11
+        let phrase = words.join(" ");
+        let p = phrase.as_bytes();
+        let (big_l_prime, l_prime, r, _) = boyer_moore_preprocess(p);
+        let result: Vec<usize> = candidates
+            .into_iter()
+            .filter(|art_no| {
+                let t = self.article_bytes(*art_no);
+                boyer_moore_truefalse(p, &t, (&big_l_prime, &l_prime, &r))
+            })
+            .collect();
+        result
+    }
+
+    pub fn boolean_phrase_search(&self, exp: &str) -> ArticleTitles {
+        match Expr::from_string_with_phrases(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => {
+                self.vec_to_articlelist(self.evaluate_syntax_tree(node))
+            }
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn evaluate_syntax_tree(&self, node: AstNode) -> Vec<usize> {
+        match node {
+            AstNode::Invert(child) => {
+                invert(self.evaluate_syntax_tree(*child), self.article_titles.len())
+            }
+            AstNode::Binary(BinaryOp::And, left_child, right_child) => and(
+                self.evaluate_syntax_tree(*left_child).into_iter(),
+                self.evaluate_syntax_tree(*right_child).into_iter(),
+            ),
+            AstNode::Binary(BinaryOp::Or, left_child, right_child) => or(
+                self.evaluate_syntax_tree(*left_child).into_iter(),
+                self.evaluate_syntax_tree(*right_child).into_iter(),
+            ),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
+            // A word is a phrase of one word
+            AstNode::Name(name) => {
+                let words: Vec<&str> = name.split(' ').collect();
+                self.phrase_articles(&words)
+            }
+        }
+    }
+}
+
+impl Search for Index<Stored<NgramDatabase>> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch | SearchType::FuzzySearch => {
+                self.fuzzy_ngram_search(&query.search_string)
+            }
+            SearchType::ExactSearch(x) if x == "NgramBoyerMoore" => {
+                self.exact_ngram_search(&query.search_string)
+            }
+            SearchType::BooleanSearch(x) if x == "Phrases" => {
+                self.boolean_phrase_search(&query.search_string)
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    fn setup_test(n: usize) -> Index<Stored<NgramDatabase>> {
+        let articles = [
+            "word1 word2 word3 word4",
+            "word2 word3 word4 word5",
+            "word4 word5",
+            "word3",
+            "word1 word3 word2",
+        ];
+        let mut database = NgramDatabase::new(n);
+        for (i, contents) in articles.iter().enumerate() {
+            database.add_article(i, &words(contents));
+        }
+        Index {
+            database: Stored::new(database, DocumentStore::from_articles(&articles)),
+            article_titles: (0..articles.len())
+                .map(|i| format!("article {}", i))
+                .collect(),
+        }
+    }
+
+    fn search(index: &Index<Stored<NgramDatabase>>, query: &str) -> Vec<usize> {
+        let words: Vec<&str> = query.split(' ').collect();
+        index.database.articles_with_grams(&words)
+    }
+
+    #[test]
+    fn words_are_interned() {
+        let index = setup_test(3);
+        assert_eq!(index.database.n_terms(), 5);
+        assert_eq!(
+            index.database.term_ids(&["word3", "word1"]),
+            Some(vec![2, 0])
+        );
+        assert_eq!(index.database.term_ids(&["word3", "word9"]), None);
+    }
+
+    #[test]
+    fn queries_shorter_than_a_gram() {
+        for n in 2..=4 {
+            let index = setup_test(n);
+            assert_eq!(search(&index, "word3"), vec![0, 1, 3, 4], "n = {}", n);
+            assert_eq!(search(&index, "word3 word4"), vec![0, 1], "n = {}", n);
+            // At the end of an article, and articles shorter than a gram
+            assert_eq!(search(&index, "word4 word5"), vec![1, 2], "n = {}", n);
+            assert_eq!(search(&index, "word3 word2"), vec![4], "n = {}", n);
+            assert_eq!(search(&index, "word5 word4"), Vec::<usize>::new());
+        }
+    }
+
+    #[test]
+    fn queries_longer_than_a_gram() {
+        let index = setup_test(2);
+        assert_eq!(search(&index, "word2 word3 word4"), vec![0, 1]);
+        assert_eq!(
+            search(&index, "word1 word2 word3 word4 word5"),
+            Vec::<usize>::new()
+        );
+        let index = setup_test(3);
+        assert_eq!(search(&index, "word2 word3 word4 word5"), vec![1]);
+        assert_eq!(
+            search(&index, "word9 word3 word4 word5"),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn the_empty_query() {
+        let index = setup_test(3);
+        assert_eq!(index.fuzzy_ngram_search(""), Vec::<String>::new());
+        assert_eq!(index.fuzzy_ngram_search("  "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn boolean_expressions_of_phrases() {
+        let index = setup_test(2);
+        let search = |exp: &str| {
+            index.evaluate_syntax_tree(match Expr::from_string_with_phrases(exp) {
+                Ok(Expr(ExprData::HasNodes(node))) => node,
+                _ => panic!("{}", exp),
+            })
+        };
+        assert_eq!(search("\"word2 word3 word4\" & !word5"), vec![0]);
+        assert_eq!(search("\"word1 word3\" | \"word4 word5\""), vec![1, 2, 4]);
+        // Longer than a gram, so the phrase is searched for in the candidates
+        assert_eq!(search("\"word1 word2 word3\" or word9"), vec![0]);
+        assert_eq!(search("!\"word3 word4\""), vec![2, 3, 4]);
+        assert_eq!(
+            search("(word1, \"word3   word2\") | \"word9 word1\""),
+            vec![4]
+        );
+        assert_eq!(
+            index.boolean_phrase_search("\"word1 word2"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn same_results_as_index11_on_triples() {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "11_3".to_string(),
+        ]);
+        let index11 = Index::index11(&config).unwrap();
+        let index = Index::index11_3(&config).unwrap();
+        for query in [
+            "a political philosophy",
+            "Anarchism is a political philosophy",
+            "of the",
+            "autism",
+            "the state undesirable unnecessary and harmful",
+        ] {
+            let mut expected = index11.fuzzy_triples_search(&query.to_string());
+            expected.sort();
+            let mut result = index.fuzzy_ngram_search(query);
+            result.sort();
+            if query.split(' ').count() >= 3 {
+                assert_eq!(result, expected, "{}", query);
+            } else {
+                // index11 finds nothing for fewer than three words
+                assert!(!result.is_empty() && expected.is_empty(), "{}", query);
+            }
+        }
+
+        let bigrams = Index::ngram_index(&config, 2).unwrap();
+        let query = "Anarchism is a political philosophy";
+        assert_eq!(
+            bigrams.exact_ngram_search(query),
+            index.exact_ngram_search(query)
+        );
+        assert_eq!(index.exact_ngram_search(query), vec!["Anarchism"]);
+
+        let query = Query {
+            search_string:
+                "(\"a political philosophy\" and !\"the state undesirable\") or \"of autism\""
+                    .to_string(),
+            search_type: SearchType::BooleanSearch("Phrases".to_string()),
+        };
+        assert_eq!(index.search(&query), vec!["Autism"]);
+    }
+}
@@ -0,0 +1,569 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::helpers::*;
+use crate::index::merge::{and, invert, or};
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::*;
+
+// Layout of a mapped index file. All numbers are little endian.
+//
+//   header            MAGIC, then n_titles, n_terms, n_postings, n_title_bytes, n_term_bytes as u64
+//   title offsets     (n_titles + 1) x u64, byte offsets into the title bytes
+//   term offsets      (n_terms + 1) x u64, byte offsets into the term bytes
+//   postings offsets  (n_terms + 1) x u64, offsets into the postings, counted in article numbers
+//   postings          n_postings x u32, the sorted article numbers of every term, one term after another
+//   title bytes       the article titles, concatenated
+//   term bytes        the terms in sorted order, concatenated
+//
+// Everything is read straight from the mapped bytes. Opening a file checks that every offset and
+// article number points into the data, so a broken file is an error rather than a panic later on.
+//
+// The last byte of MAGIC is the version of the format. It goes up whenever a file would hold
+// something else for the same corpus, as when the titles were added to the indexed terms, so the
+// files written before are rebuilt.
+pub const MAGIC: &[u8; 8] = b"RSIDX\0\0\x02";
+const HEADER_LEN: usize = 8 + 5 * 8;
+
+pub struct MappedIndex {
+    mmap: Mmap,
+    n_titles: usize,
+    n_terms: usize,
+    title_offsets: usize,
+    term_offsets: usize,
+    postings_offsets: usize,
+    postings: usize,
+    title_bytes: usize,
+    term_bytes: usize,
+}
+
+fn invalid_data(msg: &str) -> Box<dyn Error> {
+    Box::new(io::Error::new(ErrorKind::InvalidData, msg))
+}
+
+// Where the mapped index of a corpus is kept: in the mapped_indices directory next to the corpus,
+// so it is not listed among the corpus files, and doesn't depend on the working directory
+pub fn mapped_index_path(file_path: &str) -> Result<String, Box<dyn Error>> {
+    let corpus = fs::canonicalize(file_path)?;
+    let (dir, file_name) = match (corpus.parent(), corpus.file_name()) {
+        (Some(dir), Some(file_name)) => (dir, file_name),
+        _ => return Err(invalid_data("The corpus is not a file")),
+    };
+    let mut index_name = file_name.to_owned();
+    index_name.push(".idx");
+    dir.join("mapped_indices")
+        .join(index_name)
+        .into_os_string()
+        .into_string()
+        .map_err(|_| invalid_data("The path of the mapped index is not valid UTF-8"))
+}
+
+impl Index<HashMap<String, Vec<usize>>> {
+    pub fn write_mapped_index(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut terms: Vec<(&String, &Vec<usize>)> = self.database.iter().collect();
+        terms.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        let n_postings: usize = terms.iter().map(|(_, articles)| articles.len()).sum();
+        let n_title_bytes: usize = self.article_titles.iter().map(|t| t.len()).sum();
+        let n_term_bytes: usize = terms.iter().map(|(term, _)| term.len()).sum();
+        if self.article_titles.len() > u32::MAX as usize {
+            return Err(invalid_data("Too many articles for a mapped index"));
+        }
+
+        // Write next to the old file and rename it into place, so processes that have the old file
+        // mapped keep seeing a complete index
+        let tmp_path = format!("{}.tmp", path);
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+
+        out.write_all(MAGIC)?;
+        for n in [
+            self.article_titles.len(),
+            terms.len(),
+            n_postings,
+            n_title_bytes,
+            n_term_bytes,
+        ] {
+            out.write_all(&(n as u64).to_le_bytes())?;
+        }
+
+        let mut offset: u64 = 0;
+        out.write_all(&offset.to_le_bytes())?;
+        for title in &self.article_titles {
+            offset += title.len() as u64;
+            out.write_all(&offset.to_le_bytes())?;
+        }
+        offset = 0;
+        out.write_all(&offset.to_le_bytes())?;
+        for (term, _) in &terms {
+            offset += term.len() as u64;
+            out.write_all(&offset.to_le_bytes())?;
+        }
+        offset = 0;
+        out.write_all(&offset.to_le_bytes())?;
+        for (_, articles) in &terms {
+            offset += articles.len() as u64;
+            out.write_all(&offset.to_le_bytes())?;
+        }
+
+        for (_, articles) in &terms {
+            for &article in articles.iter() {
+                out.write_all(&(article as u32).to_le_bytes())?;
+            }
+        }
+        for title in &self.article_titles {
+            out.write_all(title.as_bytes())?;
+        }
+        for (term, _) in &terms {
+            out.write_all(term.as_bytes())?;
+        }
+
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+impl MappedIndex {
+    // Opens the mapped index of the corpus. It is built first if it is missing or older than the
+    // corpus, and rebuilt if it can't be opened, as when it has an older version of the format.
+    pub fn index12_0(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let path = mapped_index_path(&config.file_path)?;
+        let up_to_date = match (fs::metadata(&path), fs::metadata(&config.file_path)) {
+            (Ok(index_meta), Ok(corpus_meta)) => {
+                index_meta.modified()? >= corpus_meta.modified()?
+            }
+            _ => false,
+        };
+        if up_to_date {
+            if let Ok(index) = MappedIndex::open(&path) {
+                return Ok(index);
+            }
+        }
+        Index::index8(config)?.write_mapped_index(&path)?;
+        MappedIndex::open(&path)
+    }
+
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path)?;
+        // Safe as long as the file is not changed in place. Index files are only ever replaced
+        // through a rename, which leaves existing mappings untouched.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        if mmap.len() < HEADER_LEN || mmap[0..7] != MAGIC[0..7] {
+            return Err(invalid_data("Not a mapped index file"));
+        }
+        if mmap[7] != MAGIC[7] {
+            return Err(invalid_data("Mapped index file has another version of the format"));
+        }
+        let header =
+            |i: usize| u64::from_le_bytes(mmap[8 + 8 * i..16 + 8 * i].try_into().unwrap()) as usize;
+        let (n_titles, n_terms, n_postings, n_title_bytes, n_term_bytes) =
+            (header(0), header(1), header(2), header(3), header(4));
+        // No count can be larger than the file, which also keeps the sums below from overflowing
+        if (0..5).any(|i| header(i) > mmap.len()) {
+            return Err(invalid_data("Mapped index file has the wrong length"));
+        }
+
+        let title_offsets = HEADER_LEN;
+        let term_offsets = title_offsets + 8 * (n_titles + 1);
+        let postings_offsets = term_offsets + 8 * (n_terms + 1);
+        let postings = postings_offsets + 8 * (n_terms + 1);
+        let title_bytes = postings + 4 * n_postings;
+        let term_bytes = title_bytes + n_title_bytes;
+        if term_bytes + n_term_bytes != mmap.len() {
+            return Err(invalid_data("Mapped index file has the wrong length"));
+        }
+
+        let index = MappedIndex {
+            mmap,
+            n_titles,
+            n_terms,
+            title_offsets,
+            term_offsets,
+            postings_offsets,
+            postings,
+            title_bytes,
+            term_bytes,
+        };
+        if index.read_u64(title_offsets + 8 * n_titles) != n_title_bytes
+            || index.read_u64(term_offsets + 8 * n_terms) != n_term_bytes
+            || index.read_u64(postings_offsets + 8 * n_terms) != n_postings
+        {
+            return Err(invalid_data("Mapped index file has inconsistent offsets"));
+        }
+        if !index.offsets_are_valid(title_offsets, n_titles)
+            || !index.offsets_are_valid(term_offsets, n_terms)
+            || !index.offsets_are_valid(postings_offsets, n_terms)
+        {
+            return Err(invalid_data("Mapped index file has inconsistent offsets"));
+        }
+        let mut articles = index.mmap[postings..title_bytes]
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize);
+        if articles.any(|article| article >= n_titles) {
+            return Err(invalid_data("Mapped index file has an article number out of range"));
+        }
+        Ok(index)
+    }
+
+    fn read_u64(&self, pos: usize) -> usize {
+        u64::from_le_bytes(self.mmap[pos..pos + 8].try_into().unwrap()) as usize
+    }
+
+    // The n + 1 offsets of the table must start at 0 and never decrease. The last one is checked
+    // against the length of the data, so every slice between two of them is within it.
+    fn offsets_are_valid(&self, table: usize, n: usize) -> bool {
+        let offsets = || (0..=n).map(|i| self.read_u64(table + 8 * i));
+        offsets().next() == Some(0) && offsets().zip(offsets().skip(1)).all(|(a, b)| a <= b)
+    }
+
+    pub fn n_titles(&self) -> usize {
+        self.n_titles
+    }
+
+    pub fn n_terms(&self) -> usize {
+        self.n_terms
+    }
+
+    pub fn title(&self, i: usize) -> String {
+        let start = self.read_u64(self.title_offsets + 8 * i);
+        let end = self.read_u64(self.title_offsets + 8 * (i + 1));
+        String::from_utf8_lossy(&self.mmap[self.title_bytes + start..self.title_bytes + end])
+            .into_owned()
+    }
+
+    pub fn term(&self, i: usize) -> &[u8] {
+        let start = self.read_u64(self.term_offsets + 8 * i);
+        let end = self.read_u64(self.term_offsets + 8 * (i + 1));
+        &self.mmap[self.term_bytes + start..self.term_bytes + end]
+    }
+
+    // Binary search in the sorted term dictionary
+    pub fn find_term(&self, word: &str) -> Option<usize> {
+        let (mut low, mut high) = (0, self.n_terms);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.term(mid).cmp(word.as_bytes()) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    // The articles of the term, decoded from the mapped bytes as they are read
+    pub fn postings(&self, term_no: usize) -> impl Iterator<Item = usize> + '_ {
+        let start = self.read_u64(self.postings_offsets + 8 * term_no);
+        let end = self.read_u64(self.postings_offsets + 8 * (term_no + 1));
+        self.mmap[self.postings + 4 * start..self.postings + 4 * end]
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    pub fn word_postings(&self, word: &str) -> Vec<usize> {
+        match self.find_term(word) {
+            Some(term_no) => self.postings(term_no).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn vec_to_articlelist(&self, vec: Vec<usize>) -> ArticleTitles {
+        vec.into_iter().map(|i| self.title(i)).collect()
+    }
+
+    pub fn single_search(&self, query: &str) -> ArticleTitles {
+        self.vec_to_articlelist(self.word_postings(query))
+    }
+
+    pub fn boolean_search(&self, exp: &str) -> ArticleTitles {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => {
+                self.vec_to_articlelist(self.evaluate_syntax_tree(node))
+            }
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn evaluate_syntax_tree(&self, node: AstNode) -> Vec<usize> {
+        match node {
+            AstNode::Invert(child) => self.invert(self.evaluate_syntax_tree(*child)),
+            AstNode::Binary(BinaryOp::And, left_child, right_child) => match *right_child {
+                // A word is intersected straight from the mapped postings
+                AstNode::Name(word) => match self.find_term(&word) {
+                    Some(term_no) => and(
+                        self.evaluate_syntax_tree(*left_child).into_iter(),
+                        self.postings(term_no),
+                    ),
+                    None => Vec::new(),
+                },
+                right_child => and(
+                    self.evaluate_syntax_tree(*left_child).into_iter(),
+                    self.evaluate_syntax_tree(right_child).into_iter(),
+                ),
+            },
+            AstNode::Binary(BinaryOp::Or, left_child, right_child) => or(
+                self.evaluate_syntax_tree(*left_child).into_iter(),
+                self.evaluate_syntax_tree(*right_child).into_iter(),
+            ),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
+            AstNode::Name(word) => self.word_postings(&word),
+        }
+    }
+
+    pub fn invert(&self, child: Vec<usize>) -> Vec<usize> {
+        invert(child, self.n_titles)
+    }
+}
+
+impl Search for MappedIndex {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch => self.single_search(&query.search_string),
+            SearchType::BooleanSearch(_) => self.boolean_search(&query.search_string),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::gen_query::gen_a_lot_of_runs_bool;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rustsearch_{}_{}.idx", name, std::process::id()))
+            .into_os_string()
+            .into_string()
+            .unwrap()
+    }
+
+    fn setup_index8() -> Index<HashMap<String, Vec<usize>>> {
+        Index::index8(&Config {
+            file_path: "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            indexno: "12_0".to_string(),
+        })
+        .unwrap()
+    }
+
+    fn setup_test() -> Index<HashMap<String, Vec<usize>>> {
+        let mut database: HashMap<String, Vec<usize>> = HashMap::new();
+        database.insert("word1".to_string(), vec![0]);
+        database.insert("word2".to_string(), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        database.insert("word3".to_string(), vec![0, 2, 4, 6]);
+        database.insert("word4".to_string(), vec![1, 2, 3]);
+        let mut article_titles: Vec<String> = Vec::new();
+        for i in 0..100 {
+            article_titles.push(format!("article {}", i).to_string());
+        }
+        Index {
+            database,
+            article_titles,
+        }
+    }
+
+    #[test]
+    fn written_index_can_be_opened() {
+        let path = temp_path("written");
+        setup_test().write_mapped_index(&path).unwrap();
+        let mapped = MappedIndex::open(&path).unwrap();
+
+        assert_eq!(mapped.n_titles(), 100);
+        assert_eq!(mapped.n_terms(), 4);
+        assert_eq!(mapped.term(0), b"word1");
+        assert_eq!(mapped.term(3), b"word4");
+        assert_eq!(mapped.title(42), "article 42");
+        assert_eq!(mapped.find_term("word3"), Some(2));
+        assert_eq!(mapped.find_term("word0"), None);
+        assert_eq!(mapped.find_term("word5"), None);
+        assert_eq!(mapped.word_postings("word3"), vec![0, 2, 4, 6]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn boolean_search_on_mapped_bytes() {
+        let path = temp_path("boolean");
+        setup_test().write_mapped_index(&path).unwrap();
+        let mapped = MappedIndex::open(&path).unwrap();
+
+        assert_eq!(
+            mapped.boolean_search("word1 | (word3 & word4)"),
+            vec!["article 0", "article 2"]
+        );
+        assert_eq!(
+            mapped.boolean_search("word2 & !word3"),
+            vec!["article 1", "article 3", "article 5", "article 7"]
+        );
+        assert_eq!(
+            mapped.boolean_search("word1 & nowhere"),
+            Vec::<String>::new()
+        );
+        assert_eq!(mapped.boolean_search("word1 | nowhere"), vec!["article 0"]);
+        assert_eq!(mapped.boolean_search(""), Vec::<String>::new());
+        assert_eq!(mapped.boolean_search("word1(("), Vec::<String>::new());
+        assert_eq!(mapped.boolean_search("!word2").len(), 92);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_same_file_can_be_mapped_twice() {
+        let path = temp_path("shared");
+        setup_test().write_mapped_index(&path).unwrap();
+        let first = MappedIndex::open(&path).unwrap();
+        let second = MappedIndex::open(&path).unwrap();
+        assert_eq!(first.single_search("word4"), second.single_search("word4"));
+
+        // Replacing the file leaves the existing mappings intact
+        let mut index = setup_test();
+        index.database.insert("word5".to_string(), vec![99]);
+        index.write_mapped_index(&path).unwrap();
+        assert_eq!(first.n_terms(), 4);
+        assert_eq!(MappedIndex::open(&path).unwrap().n_terms(), 5);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn broken_files_are_rejected() {
+        let path = temp_path("broken");
+        setup_test().write_mapped_index(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+
+        fs::write(&path, &bytes[..10]).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        fs::write(&path, &bad_magic).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+
+        // Claim one title more than the file holds
+        let mut bad_count = bytes;
+        bad_count[8] += 1;
+        fs::write(&path, &bad_count).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn broken_offsets_are_rejected() {
+        let path = temp_path("offsets");
+        setup_test().write_mapped_index(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let write_u64 = |bytes: &mut Vec<u8>, pos: usize, n: u64| {
+            bytes[pos..pos + 8].copy_from_slice(&n.to_le_bytes());
+        };
+        let (n_titles, n_terms) = (100, 4);
+        let term_offsets = HEADER_LEN + 8 * (n_titles + 1);
+        let postings_offsets = term_offsets + 8 * (n_terms + 1);
+        let postings = postings_offsets + 8 * (n_terms + 1);
+
+        // A title ending before it starts, and titles not starting at the start of their bytes
+        let mut broken = bytes.clone();
+        write_u64(&mut broken, HEADER_LEN + 8 * 5, 1);
+        fs::write(&path, &broken).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+        let mut broken = bytes.clone();
+        write_u64(&mut broken, HEADER_LEN, 1 << 40);
+        fs::write(&path, &broken).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+
+        let mut broken = bytes.clone();
+        write_u64(&mut broken, term_offsets + 8 * 2, 1 << 40);
+        fs::write(&path, &broken).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+
+        let mut broken = bytes.clone();
+        write_u64(&mut broken, postings_offsets + 8, 1 << 40);
+        fs::write(&path, &broken).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+
+        // An article that has no title
+        let mut broken = bytes.clone();
+        broken[postings..postings + 4].copy_from_slice(&100u32.to_le_bytes());
+        fs::write(&path, &broken).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+
+        fs::write(&path, &bytes).unwrap();
+        assert!(MappedIndex::open(&path).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mapped_and_index8_get_the_same_results() {
+        let file = "data/WestburyLab.wikicorp.201004_100KB.txt".to_string();
+        let path = temp_path("wiki100kb");
+        let index8 = setup_index8();
+        index8.write_mapped_index(&path).unwrap();
+        let mapped = MappedIndex::open(&path).unwrap();
+
+        assert_eq!(mapped.n_terms(), index8.database.len());
+        for (word, articles) in &index8.database {
+            assert_eq!(&mapped.word_postings(word), articles);
+        }
+        for depth_vec in gen_a_lot_of_runs_bool(file, 10) {
+            for query in &depth_vec {
+                assert_eq!(
+                    index8.boolean_search_naive(query),
+                    mapped.boolean_search(query)
+                );
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn files_of_other_versions_are_rebuilt() {
+        let dir = std::env::temp_dir().join(format!("rustsearch_versions_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let corpus = dir.join("corpus.txt").into_os_string().into_string().unwrap();
+        fs::copy("data/WestburyLab.wikicorp.201004_100KB.txt", &corpus).unwrap();
+        let config = Config {
+            file_path: corpus.clone(),
+            indexno: "12_0".to_string(),
+        };
+        let path = mapped_index_path(&corpus).unwrap();
+        MappedIndex::index12_0(&config).unwrap();
+
+        // A file written by the version before, which had no title terms
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[7] = 1;
+        fs::write(&path, &bytes).unwrap();
+        assert!(MappedIndex::open(&path).is_err());
+
+        let mapped = MappedIndex::index12_0(&config).unwrap();
+        assert_eq!(fs::read(&path).unwrap()[0..8], MAGIC[..]);
+        assert_eq!(mapped.single_search("title:Autism"), vec!["Autism"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mapped_index_path_is_next_to_the_corpus() {
+        let data = fs::canonicalize("data").unwrap();
+        assert_eq!(
+            mapped_index_path("data/WestburyLab.wikicorp.201004_100KB.txt").unwrap(),
+            data.join("mapped_indices/WestburyLab.wikicorp.201004_100KB.txt.idx")
+                .to_str()
+                .unwrap()
+        );
+        assert_eq!(
+            mapped_index_path("./data/../data/WestburyLab.wikicorp.201004_100KB.txt").unwrap(),
+            mapped_index_path("data/WestburyLab.wikicorp.201004_100KB.txt").unwrap()
+        );
+        assert!(mapped_index_path("data/nothing.txt").is_err());
+    }
+}
@@ -0,0 +1,1159 @@
+use std::cmp::min;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::ops::Bound;
+
+use regex_syntax::hir::{
+    Anchor, Class, Hir, HirKind, Literal, RepetitionKind, RepetitionRange,
+};
+use regex_syntax::utf8::Utf8Sequences;
+
+use crate::helpers::*;
+use crate::index::merge::{and, invert, or};
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::*;
+
+// A minimal acyclic finite state transducer mapping byte strings to u64 outputs. Common prefixes
+// and common suffixes of the keys are stored once. The output of a key is the sum of the outputs
+// on its path plus the final output of the last node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Transition {
+    byte: u8,
+    output: u64,
+    target: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    first_transition: u32,
+    n_transitions: u32,
+    is_final: bool,
+    final_output: u64,
+}
+
+// The keys an Fst enumerates, in order, with their outputs
+pub type KeyOutputs = Vec<(Vec<u8>, u64)>;
+
+pub struct Fst {
+    nodes: Vec<Node>,
+    transitions: Vec<Transition>,
+    root: u32,
+    len: usize,
+}
+
+// A node that may still get transitions. The target of its last transition is the next node on
+// the builder's stack until it is frozen.
+#[derive(Default)]
+struct UnfinishedNode {
+    is_final: bool,
+    final_output: u64,
+    transitions: Vec<Transition>,
+}
+
+// Builds an Fst from keys inserted in strictly increasing order
+pub struct FstBuilder {
+    nodes: Vec<Node>,
+    transitions: Vec<Transition>,
+    registry: HashMap<(bool, u64, Vec<Transition>), u32>,
+    stack: Vec<UnfinishedNode>,
+    last_key: Option<Vec<u8>>,
+    len: usize,
+}
+
+impl Default for FstBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FstBuilder {
+    pub fn new() -> FstBuilder {
+        FstBuilder {
+            nodes: Vec::new(),
+            transitions: Vec::new(),
+            registry: HashMap::new(),
+            stack: vec![UnfinishedNode::default()],
+            last_key: None,
+            len: 0,
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8], value: u64) -> Result<(), Box<dyn Error>> {
+        let prefix_len = match &self.last_key {
+            Some(last) if key <= &last[..] => {
+                return Err("Keys must be inserted in strictly increasing order".into())
+            }
+            Some(last) => last.iter().zip(key).take_while(|(a, b)| a == b).count(),
+            None => 0,
+        };
+        self.freeze_from(prefix_len + 1);
+
+        // Push outputs as close to the root as possible, so shared prefixes carry shared output
+        let mut value = value;
+        for i in 0..prefix_len {
+            let transition = self.stack[i].transitions.last_mut().unwrap();
+            let common = min(transition.output, value);
+            let rest = transition.output - common;
+            transition.output = common;
+            value -= common;
+            if rest > 0 {
+                let child = &mut self.stack[i + 1];
+                for t in child.transitions.iter_mut() {
+                    t.output += rest;
+                }
+                if child.is_final {
+                    child.final_output += rest;
+                }
+            }
+        }
+
+        if key.len() == prefix_len {
+            // Only the empty key, inserted first, ends at the root
+            self.stack[prefix_len].is_final = true;
+            self.stack[prefix_len].final_output = value;
+        } else {
+            for (i, &byte) in key.iter().enumerate().skip(prefix_len) {
+                self.stack[i].transitions.push(Transition {
+                    byte,
+                    output: if i == prefix_len { value } else { 0 },
+                    target: 0,
+                });
+                self.stack.push(UnfinishedNode::default());
+            }
+            self.stack.last_mut().unwrap().is_final = true;
+        }
+        self.last_key = Some(key.to_vec());
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Fst {
+        self.freeze_from(1);
+        let root = self.stack.pop().unwrap();
+        let root = self.freeze(root);
+        Fst {
+            nodes: self.nodes,
+            transitions: self.transitions,
+            root,
+            len: self.len,
+        }
+    }
+
+    // Freezes the nodes of the stack below the given depth, reusing equivalent frozen nodes
+    fn freeze_from(&mut self, depth: usize) {
+        while self.stack.len() > depth {
+            let node = self.stack.pop().unwrap();
+            let id = self.freeze(node);
+            self.stack
+                .last_mut()
+                .unwrap()
+                .transitions
+                .last_mut()
+                .unwrap()
+                .target = id;
+        }
+    }
+
+    fn freeze(&mut self, node: UnfinishedNode) -> u32 {
+        let key = (node.is_final, node.final_output, node.transitions);
+        if let Some(&id) = self.registry.get(&key) {
+            return id;
+        }
+        let id = self.nodes.len() as u32;
+        self.nodes.push(Node {
+            first_transition: self.transitions.len() as u32,
+            n_transitions: key.2.len() as u32,
+            is_final: key.0,
+            final_output: key.1,
+        });
+        self.transitions.extend_from_slice(&key.2);
+        self.registry.insert(key, id);
+        id
+    }
+}
+
+// Decides which keys to enumerate while walking the Fst. can_match lets whole subtrees be skipped.
+pub trait Automaton {
+    type State: Clone;
+    fn start(&self) -> Self::State;
+    fn is_match(&self, state: &Self::State) -> bool;
+    fn can_match(&self, _state: &Self::State) -> bool {
+        true
+    }
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State;
+}
+
+pub struct AlwaysMatch;
+
+impl Automaton for AlwaysMatch {
+    type State = ();
+    fn start(&self) {}
+    fn is_match(&self, _state: &()) -> bool {
+        true
+    }
+    fn accept(&self, _state: &(), _byte: u8) {}
+}
+
+// Matches every key starting with the prefix. The state is how much of the prefix has been read,
+// or None once a byte didn't match.
+pub struct Prefix<'a>(pub &'a [u8]);
+
+impl<'a> Automaton for Prefix<'a> {
+    type State = Option<usize>;
+    fn start(&self) -> Option<usize> {
+        Some(0)
+    }
+    fn is_match(&self, state: &Option<usize>) -> bool {
+        *state == Some(self.0.len())
+    }
+    fn can_match(&self, state: &Option<usize>) -> bool {
+        state.is_some()
+    }
+    fn accept(&self, state: &Option<usize>, byte: u8) -> Option<usize> {
+        match *state {
+            Some(i) if i == self.0.len() => Some(i),
+            Some(i) if self.0[i] == byte => Some(i + 1),
+            _ => None,
+        }
+    }
+}
+
+// Matches the keys between two bounds in lexicographic order
+pub struct Range {
+    lower: Bound<Vec<u8>>,
+    upper: Bound<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RangeState {
+    depth: usize,
+    // Whether the key read so far is a prefix of the lower/upper bound
+    on_lower: bool,
+    on_upper: bool,
+    dead: bool,
+}
+
+impl Range {
+    pub fn new(lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Range {
+        Range {
+            lower: Range::owned_bound(lower),
+            upper: Range::owned_bound(upper),
+        }
+    }
+
+    fn owned_bound(bound: Bound<&[u8]>) -> Bound<Vec<u8>> {
+        match bound {
+            Bound::Included(bytes) => Bound::Included(bytes.to_vec()),
+            Bound::Excluded(bytes) => Bound::Excluded(bytes.to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+
+    fn bound_bytes(bound: &Bound<Vec<u8>>) -> &[u8] {
+        match bound {
+            Bound::Included(bytes) | Bound::Excluded(bytes) => bytes,
+            Bound::Unbounded => &[],
+        }
+    }
+}
+
+impl Automaton for Range {
+    type State = RangeState;
+    fn start(&self) -> RangeState {
+        RangeState {
+            depth: 0,
+            on_lower: !matches!(self.lower, Bound::Unbounded),
+            on_upper: !matches!(self.upper, Bound::Unbounded),
+            dead: false,
+        }
+    }
+
+    fn is_match(&self, state: &RangeState) -> bool {
+        let above_lower = !state.on_lower
+            || (state.depth == Range::bound_bytes(&self.lower).len()
+                && matches!(self.lower, Bound::Included(_)));
+        // A proper prefix of the upper bound is smaller than it
+        let below_upper = !state.on_upper
+            || state.depth < Range::bound_bytes(&self.upper).len()
+            || matches!(self.upper, Bound::Included(_));
+        !state.dead && above_lower && below_upper
+    }
+
+    fn can_match(&self, state: &RangeState) -> bool {
+        !state.dead
+    }
+
+    fn accept(&self, state: &RangeState, byte: u8) -> RangeState {
+        let mut next = *state;
+        next.depth += 1;
+        if state.dead {
+            return next;
+        }
+        if state.on_lower {
+            let lower = Range::bound_bytes(&self.lower);
+            if state.depth < lower.len() {
+                next.dead |= byte < lower[state.depth];
+                next.on_lower = byte == lower[state.depth];
+            } else {
+                // Longer than the lower bound, which it starts with
+                next.on_lower = false;
+            }
+        }
+        if state.on_upper {
+            let upper = Range::bound_bytes(&self.upper);
+            if state.depth < upper.len() {
+                next.dead |= byte > upper[state.depth];
+                next.on_upper = byte == upper[state.depth];
+            } else {
+                // Longer than the upper bound, which it starts with
+                next.dead = true;
+            }
+        }
+        next
+    }
+}
+
+// Matches the keys the regex matches as a whole. The regex is compiled to an NFA over bytes, and
+// the state is the set of NFA states reachable after the key read so far, so a subtree is skipped
+// as soon as the set is empty.
+#[derive(Debug, Clone, Copy)]
+enum NfaState {
+    // A byte within the range, then the next state
+    Bytes(u8, u8, u32),
+    Split(u32, u32),
+    // Only passed at the start or at the end of the key
+    AtStart(u32),
+    AtEnd(u32),
+    Fail,
+    Match,
+}
+
+pub struct RegexAutomaton {
+    states: Vec<NfaState>,
+    start: u32,
+}
+
+// Bounded repetitions copy their body, so the NFA is limited in size like the regex crate's
+// programs are
+const MAX_NFA_STATES: usize = 1 << 20;
+
+impl RegexAutomaton {
+    pub fn new(pattern: &str) -> Result<Self, Box<dyn Error>> {
+        let hir = regex_syntax::Parser::new().parse(pattern)?;
+        let mut automaton = RegexAutomaton {
+            states: vec![NfaState::Match],
+            start: 0,
+        };
+        automaton.start = automaton.compile(&hir, 0)?;
+        Ok(automaton)
+    }
+
+    fn push(&mut self, state: NfaState) -> Result<u32, Box<dyn Error>> {
+        if self.states.len() >= MAX_NFA_STATES {
+            return Err("The regex is too large".into());
+        }
+        self.states.push(state);
+        Ok((self.states.len() - 1) as u32)
+    }
+
+    fn alternatives(&mut self, starts: Vec<u32>) -> Result<u32, Box<dyn Error>> {
+        let mut starts = starts.into_iter().rev();
+        let mut start = match starts.next() {
+            Some(start) => start,
+            None => return self.push(NfaState::Fail),
+        };
+        for other in starts {
+            start = self.push(NfaState::Split(other, start))?;
+        }
+        Ok(start)
+    }
+
+    // Adds the states matching hir, which go on to next, and returns the first of them
+    fn compile(&mut self, hir: &Hir, next: u32) -> Result<u32, Box<dyn Error>> {
+        match hir.kind() {
+            HirKind::Empty => Ok(next),
+            HirKind::Literal(Literal::Unicode(c)) => {
+                let mut buffer = [0; 4];
+                let mut start = next;
+                for &byte in c.encode_utf8(&mut buffer).as_bytes().iter().rev() {
+                    start = self.push(NfaState::Bytes(byte, byte, start))?;
+                }
+                Ok(start)
+            }
+            HirKind::Literal(Literal::Byte(byte)) => {
+                self.push(NfaState::Bytes(*byte, *byte, next))
+            }
+            HirKind::Class(Class::Unicode(class)) => {
+                let mut starts = Vec::new();
+                for range in class.iter() {
+                    for sequence in Utf8Sequences::new(range.start(), range.end()) {
+                        let mut start = next;
+                        for bytes in sequence.as_slice().iter().rev() {
+                            start = self.push(NfaState::Bytes(bytes.start, bytes.end, start))?;
+                        }
+                        starts.push(start);
+                    }
+                }
+                self.alternatives(starts)
+            }
+            HirKind::Class(Class::Bytes(class)) => {
+                let mut starts = Vec::new();
+                for range in class.iter() {
+                    starts.push(self.push(NfaState::Bytes(range.start(), range.end(), next))?);
+                }
+                self.alternatives(starts)
+            }
+            HirKind::Anchor(Anchor::StartLine | Anchor::StartText) => {
+                self.push(NfaState::AtStart(next))
+            }
+            HirKind::Anchor(Anchor::EndLine | Anchor::EndText) => self.push(NfaState::AtEnd(next)),
+            HirKind::WordBoundary(_) => Err("Word boundaries are not supported".into()),
+            HirKind::Group(group) => self.compile(&group.hir, next),
+            HirKind::Concat(parts) => {
+                let mut start = next;
+                for part in parts.iter().rev() {
+                    start = self.compile(part, start)?;
+                }
+                Ok(start)
+            }
+            HirKind::Alternation(alternatives) => {
+                let mut starts = Vec::new();
+                for alternative in alternatives {
+                    starts.push(self.compile(alternative, next)?);
+                }
+                self.alternatives(starts)
+            }
+            HirKind::Repetition(repetition) => {
+                let (min, max) = match &repetition.kind {
+                    RepetitionKind::ZeroOrOne => (0, Some(1)),
+                    RepetitionKind::ZeroOrMore => (0, None),
+                    RepetitionKind::OneOrMore => (1, None),
+                    RepetitionKind::Range(RepetitionRange::Exactly(m)) => (*m, Some(*m)),
+                    RepetitionKind::Range(RepetitionRange::AtLeast(m)) => (*m, None),
+                    RepetitionKind::Range(RepetitionRange::Bounded(m, n)) => (*m, Some(*n)),
+                };
+                let mut start = match max {
+                    // The body loops back to the split, which is filled in once the body is added
+                    None => {
+                        let split = self.push(NfaState::Split(next, next))?;
+                        let body = self.compile(&repetition.hir, split)?;
+                        self.states[split as usize] = NfaState::Split(body, next);
+                        split
+                    }
+                    Some(max) => {
+                        let mut start = next;
+                        for _ in min..max {
+                            let body = self.compile(&repetition.hir, start)?;
+                            start = self.push(NfaState::Split(body, start))?;
+                        }
+                        start
+                    }
+                };
+                for _ in 0..min {
+                    start = self.compile(&repetition.hir, start)?;
+                }
+                Ok(start)
+            }
+        }
+    }
+
+    // The states reachable from the given ones without reading a byte, sorted. AtEnd states are
+    // kept until the end is known, since the key may end there.
+    fn closure(
+        &self,
+        from: impl IntoIterator<Item = u32>,
+        at_start: bool,
+        at_end: bool,
+    ) -> Vec<u32> {
+        let mut seen = vec![false; self.states.len()];
+        let mut stack: Vec<u32> = from.into_iter().collect();
+        let mut states = Vec::new();
+        while let Some(state) = stack.pop() {
+            if std::mem::replace(&mut seen[state as usize], true) {
+                continue;
+            }
+            match self.states[state as usize] {
+                NfaState::Split(a, b) => stack.extend([b, a]),
+                NfaState::AtStart(next) if at_start => stack.push(next),
+                NfaState::AtEnd(next) if at_end => stack.push(next),
+                NfaState::AtEnd(_) | NfaState::Bytes(..) | NfaState::Match => states.push(state),
+                NfaState::AtStart(_) | NfaState::Fail => {}
+            }
+        }
+        states.sort_unstable();
+        states
+    }
+}
+
+impl Automaton for RegexAutomaton {
+    type State = Vec<u32>;
+    fn start(&self) -> Vec<u32> {
+        self.closure([self.start], true, false)
+    }
+    fn is_match(&self, state: &Vec<u32>) -> bool {
+        self.closure(state.iter().copied(), false, true)
+            .iter()
+            .any(|&s| matches!(self.states[s as usize], NfaState::Match))
+    }
+    fn can_match(&self, state: &Vec<u32>) -> bool {
+        !state.is_empty()
+    }
+    fn accept(&self, state: &Vec<u32>, byte: u8) -> Vec<u32> {
+        let next = state.iter().filter_map(|&s| match self.states[s as usize] {
+            NfaState::Bytes(lo, hi, next) if lo <= byte && byte <= hi => Some(next),
+            _ => None,
+        });
+        self.closure(next, false, false)
+    }
+}
+
+impl Fst {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn n_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn node_transitions(&self, node: u32) -> &[Transition] {
+        let node = &self.nodes[node as usize];
+        let start = node.first_transition as usize;
+        &self.transitions[start..start + node.n_transitions as usize]
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<u64> {
+        let mut node = self.root;
+        let mut output = 0;
+        for &byte in key {
+            let transitions = self.node_transitions(node);
+            // Transitions are sorted, since keys are inserted in order
+            let i = transitions.binary_search_by_key(&byte, |t| t.byte).ok()?;
+            output += transitions[i].output;
+            node = transitions[i].target;
+        }
+        let node = &self.nodes[node as usize];
+        match node.is_final {
+            true => Some(output + node.final_output),
+            false => None,
+        }
+    }
+
+    // All keys accepted by the automaton with their outputs, in lexicographic order
+    pub fn search<A: Automaton>(&self, automaton: &A) -> KeyOutputs {
+        let mut result = Vec::new();
+        let mut key = Vec::new();
+        self.search_node(
+            automaton,
+            self.root,
+            automaton.start(),
+            &mut key,
+            0,
+            &mut result,
+        );
+        result
+    }
+
+    fn search_node<A: Automaton>(
+        &self,
+        automaton: &A,
+        node: u32,
+        state: A::State,
+        key: &mut Vec<u8>,
+        output: u64,
+        result: &mut KeyOutputs,
+    ) {
+        let n = &self.nodes[node as usize];
+        if n.is_final && automaton.is_match(&state) {
+            result.push((key.clone(), output + n.final_output));
+        }
+        for t in self.node_transitions(node) {
+            let next_state = automaton.accept(&state, t.byte);
+            if automaton.can_match(&next_state) {
+                key.push(t.byte);
+                self.search_node(
+                    automaton,
+                    t.target,
+                    next_state,
+                    key,
+                    output + t.output,
+                    result,
+                );
+                key.pop();
+            }
+        }
+    }
+
+    pub fn stream(&self) -> KeyOutputs {
+        self.search(&AlwaysMatch)
+    }
+
+    pub fn prefix(&self, prefix: &[u8]) -> KeyOutputs {
+        self.search(&Prefix(prefix))
+    }
+
+    pub fn range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> KeyOutputs {
+        self.search(&Range::new(lower, upper))
+    }
+
+    // The regex has to match the whole key. The walk follows the regex, so only the keys it can
+    // still match are visited.
+    pub fn regex(&self, pattern: &str) -> Result<KeyOutputs, Box<dyn Error>> {
+        Ok(self.search(&RegexAutomaton::new(pattern)?))
+    }
+}
+
+// The Fst maps every term to its number in sorted order, which is where its postings start in
+// offsets. The postings are the sorted article lists of all terms, one after another.
+pub struct FstPostings {
+    terms: Fst,
+    offsets: Vec<usize>,
+    postings: Vec<usize>,
+}
+
+impl Index<FstPostings> {
+    pub fn index12_1(config: &Config) -> Result<Self, Box<dyn Error>> {
+        // A BTreeMap hands the terms to the builder in sorted order
+        let mut terms: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+        let articles_iter = read_and_clean_file_to_iter(config)?;
+        let mut article_titles: Vec<String> = Vec::new();
+
+        for (title, contents) in articles_iter {
+            if !title.is_empty() {
+                article_titles.push(title.to_string());
+                for word in article_terms(&title, &contents) {
+                    let v = terms.entry(word.to_string()).or_default();
+                    if v.last() != Some(&(article_titles.len() - 1)) {
+                        v.push(article_titles.len() - 1)
+                    }
+                }
+            }
+        }
+
+        Ok(Index {
+            database: FstPostings::from_sorted(terms)?,
+            article_titles,
+        })
+    }
+
+    pub fn vec_to_articlelist(&self, vec: Vec<usize>) -> ArticleTitles {
+        vec.into_iter()
+            .map(|i| self.article_titles[i].clone())
+            .collect()
+    }
+
+    pub fn single_search(&self, query: &str) -> ArticleTitles {
+        self.vec_to_articlelist(self.database.word_postings(query).to_vec())
+    }
+
+    pub fn boolean_search(&self, exp: &str) -> ArticleTitles {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => {
+                self.vec_to_articlelist(self.evaluate_syntax_tree(node))
+            }
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn evaluate_syntax_tree(&self, node: AstNode) -> Vec<usize> {
+        match node {
+            AstNode::Invert(child) => {
+                invert(self.evaluate_syntax_tree(*child), self.article_titles.len())
+            }
+            AstNode::Binary(BinaryOp::And, left_child, right_child) => and(
+                self.evaluate_syntax_tree(*left_child).into_iter(),
+                self.evaluate_syntax_tree(*right_child).into_iter(),
+            ),
+            AstNode::Binary(BinaryOp::Or, left_child, right_child) => or(
+                self.evaluate_syntax_tree(*left_child).into_iter(),
+                self.evaluate_syntax_tree(*right_child).into_iter(),
+            ),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
+            AstNode::Name(word) => self.database.word_postings(&word).to_vec(),
+        }
+    }
+
+    // Same syntax as index9: "wor*" finds the articles with a word starting with "wor",
+    // and a query without a * finds the word itself
+    pub fn prefix_search(&self, query: &str) -> ArticleTitles {
+        match query.split_once('*') {
+            Some((prefix, _)) => {
+                let terms = field_terms_of(query, self.database.terms.prefix(prefix.as_bytes()));
+                self.vec_to_articlelist(self.database.union_of_terms(&terms))
+            }
+            None => self.single_search(query),
+        }
+    }
+
+    // "lower..upper" or "lower..=upper", where either bound may be left out
+    pub fn range_search(&self, query: &str) -> ArticleTitles {
+        let (lower, upper) = match query.split_once("..") {
+            Some(bounds) => bounds,
+            None => return Vec::new(),
+        };
+        let lower = match lower {
+            "" => Bound::Unbounded,
+            lower => Bound::Included(lower.as_bytes()),
+        };
+        let upper = match upper.strip_prefix('=') {
+            Some("") => Bound::Unbounded,
+            Some(upper) => Bound::Included(upper.as_bytes()),
+            None if upper.is_empty() => Bound::Unbounded,
+            None => Bound::Excluded(upper.as_bytes()),
+        };
+        let terms = field_terms_of(query, self.database.terms.range(lower, upper));
+        self.vec_to_articlelist(self.database.union_of_terms(&terms))
+    }
+
+    pub fn regex_search(&self, pattern: &str) -> ArticleTitles {
+        match self.database.terms.regex(pattern) {
+            Ok(terms) => {
+                let terms = field_terms_of(pattern, terms);
+                self.vec_to_articlelist(self.database.union_of_terms(&terms))
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+// Words of fields other than the body are indexed as "field:word". Queries that don't mention a
+// field only find words of the body, like the boolean search does.
+fn field_terms_of(query: &str, terms: KeyOutputs) -> KeyOutputs {
+    match query.contains(':') {
+        true => terms,
+        false => terms
+            .into_iter()
+            .filter(|(term, _)| !term.contains(&b':'))
+            .collect(),
+    }
+}
+
+impl FstPostings {
+    pub fn from_sorted(terms: BTreeMap<String, Vec<usize>>) -> Result<Self, Box<dyn Error>> {
+        let mut builder = FstBuilder::new();
+        let mut offsets: Vec<usize> = vec![0];
+        let mut postings: Vec<usize> = Vec::new();
+        for (i, (term, articles)) in terms.into_iter().enumerate() {
+            builder.insert(term.as_bytes(), i as u64)?;
+            postings.extend(articles);
+            offsets.push(postings.len());
+        }
+        Ok(FstPostings {
+            terms: builder.finish(),
+            offsets,
+            postings,
+        })
+    }
+
+    pub fn terms(&self) -> &Fst {
+        &self.terms
+    }
+
+    pub fn term_postings(&self, term_no: u64) -> &[usize] {
+        let term_no = term_no as usize;
+        &self.postings[self.offsets[term_no]..self.offsets[term_no + 1]]
+    }
+
+    pub fn word_postings(&self, word: &str) -> &[usize] {
+        match self.terms.get(word.as_bytes()) {
+            Some(term_no) => self.term_postings(term_no),
+            None => &[],
+        }
+    }
+
+    pub fn union_of_terms(&self, terms: &[(Vec<u8>, u64)]) -> Vec<usize> {
+        let mut articles: Vec<usize> = terms
+            .iter()
+            .flat_map(|&(_, term_no)| self.term_postings(term_no).iter().copied())
+            .collect();
+        articles.sort_unstable();
+        articles.dedup();
+        articles
+    }
+}
+
+impl Search for Index<FstPostings> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch => self.single_search(&query.search_string),
+            SearchType::BooleanSearch(_) => self.boolean_search(&query.search_string),
+            SearchType::PrefixSearch => self.prefix_search(&query.search_string),
+            SearchType::TermSearch(x) if x == "Range" => self.range_search(&query.search_string),
+            SearchType::TermSearch(x) if x == "Regex" => self.regex_search(&query.search_string),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::gen_query::{gen_a_lot_of_runs_bool, gen_a_lot_of_runs_tries};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashSet;
+
+    fn setup_real() -> Index<FstPostings> {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "12_1".to_string(),
+        ]);
+        Index::index12_1(&config).unwrap()
+    }
+
+    fn setup_test() -> Index<FstPostings> {
+        let mut terms: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        terms.insert("word1".to_string(), vec![0]);
+        terms.insert("word2".to_string(), vec![0, 1, 2, 3]);
+        terms.insert("world".to_string(), vec![0, 2, 4, 6, 7]);
+        terms.insert("would".to_string(), vec![5, 6, 7, 99]);
+        terms.insert("boob".to_string(), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        terms.insert("booby".to_string(), vec![0, 5, 6, 7]);
+        terms.insert("booty".to_string(), vec![1, 2, 3]);
+        let mut article_titles: Vec<String> = Vec::new();
+        for i in 0..100 {
+            article_titles.push(format!("article {}", i).to_string());
+        }
+        Index {
+            database: FstPostings::from_sorted(terms).unwrap(),
+            article_titles,
+        }
+    }
+
+    fn build(keys: &[&str]) -> Fst {
+        let mut builder = FstBuilder::new();
+        for (i, key) in keys.iter().enumerate() {
+            builder.insert(key.as_bytes(), i as u64).unwrap();
+        }
+        builder.finish()
+    }
+
+    fn keys(result: KeyOutputs) -> Vec<String> {
+        result
+            .into_iter()
+            .map(|(key, _)| String::from_utf8(key).unwrap())
+            .collect()
+    }
+
+    fn search_match(result: ArticleTitles, titles: Vec<&str>) {
+        assert_eq!(
+            HashSet::<String>::from_iter(result),
+            HashSet::from_iter(titles.iter().map(|s| s.to_string()))
+        )
+    }
+
+    #[test]
+    fn get_finds_every_key_and_nothing_else() {
+        let fst = build(&[
+            "", "a", "ab", "abc", "b", "bcd", "tap", "taps", "top", "tops",
+        ]);
+        assert_eq!(fst.len(), 10);
+        for (i, key) in [
+            "", "a", "ab", "abc", "b", "bcd", "tap", "taps", "top", "tops",
+        ]
+        .iter()
+        .enumerate()
+        {
+            assert_eq!(fst.get(key.as_bytes()), Some(i as u64));
+        }
+        for key in ["abcd", "t", "ta", "to", "tapss", "c", "bc"] {
+            assert_eq!(fst.get(key.as_bytes()), None);
+        }
+    }
+
+    #[test]
+    fn common_suffixes_are_shared() {
+        // The "a" and "o" branches end in the same states and are stored once
+        let fst = build(&["tap", "taps", "top", "tops"]);
+        assert!(fst.n_nodes() <= 6);
+        assert_eq!(fst.get(b"tops"), Some(3));
+    }
+
+    #[test]
+    fn keys_must_be_increasing() {
+        let mut builder = FstBuilder::new();
+        builder.insert(b"b", 0).unwrap();
+        assert!(builder.insert(b"a", 1).is_err());
+        assert!(builder.insert(b"b", 1).is_err());
+        assert!(builder.insert(b"c", 1).is_ok());
+    }
+
+    #[test]
+    fn outputs_need_not_be_increasing() {
+        let mut builder = FstBuilder::new();
+        builder.insert(b"ab", 10).unwrap();
+        builder.insert(b"ac", 3).unwrap();
+        builder.insert(b"b", 7).unwrap();
+        let fst = builder.finish();
+        assert_eq!(fst.get(b"ab"), Some(10));
+        assert_eq!(fst.get(b"ac"), Some(3));
+        assert_eq!(fst.get(b"b"), Some(7));
+    }
+
+    #[test]
+    fn enumerations_are_in_order() {
+        let words = ["apple", "apply", "apt", "bat", "batch", "bath", "cat"];
+        let fst = build(&words);
+        assert_eq!(keys(fst.stream()), words);
+        assert_eq!(keys(fst.prefix(b"ap")), vec!["apple", "apply", "apt"]);
+        assert_eq!(keys(fst.prefix(b"bat")), vec!["bat", "batch", "bath"]);
+        assert_eq!(keys(fst.prefix(b"x")), Vec::<String>::new());
+        assert_eq!(
+            keys(fst.range(Bound::Included(b"apt"), Bound::Excluded(b"bath"))),
+            vec!["apt", "bat", "batch"]
+        );
+        assert_eq!(
+            keys(fst.range(Bound::Excluded(b"bat"), Bound::Included(b"bath"))),
+            vec!["batch", "bath"]
+        );
+        assert_eq!(
+            keys(fst.range(Bound::Unbounded, Bound::Excluded(b"b"))),
+            vec!["apple", "apply", "apt"]
+        );
+        assert_eq!(
+            keys(fst.range(Bound::Included(b"ba"), Bound::Unbounded)),
+            vec!["bat", "batch", "bath", "cat"]
+        );
+        assert_eq!(keys(fst.regex("ba.h").unwrap()), vec!["bath"]);
+        assert_eq!(
+            keys(fst.regex("appl(e|y)").unwrap()),
+            vec!["apple", "apply"]
+        );
+        assert_eq!(keys(fst.regex("a|c.*").unwrap()), vec!["cat"]);
+        assert!(fst.regex("(").is_err());
+    }
+
+    #[test]
+    fn regex_walk_matches_the_regex_crate() {
+        let terms = &setup_real().database.terms;
+        let all = terms.search(&AlwaysMatch);
+        for pattern in [
+            "a.*m",
+            "^the$",
+            "(an|re)+[a-z]{2,4}",
+            "[^a-z].*",
+            "bi-.*",
+            "\\w{3}ism",
+            "[ée].?",
+            "x?y*z+",
+            "title:.*",
+            "(?i)AUTISM",
+            "",
+        ] {
+            let re = regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap();
+            let expected: KeyOutputs = all
+                .iter()
+                .filter(|(key, _)| matches!(std::str::from_utf8(key), Ok(k) if re.is_match(k)))
+                .cloned()
+                .collect();
+            assert_eq!(terms.regex(pattern).unwrap(), expected, "{}", pattern);
+        }
+        assert!(terms.regex("\\bthe").is_err());
+    }
+
+    #[test]
+    fn enumerations_match_sorted_vocabulary() {
+        let index8 = Index::index8(&Config {
+            file_path: "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            indexno: "8".to_string(),
+        })
+        .unwrap();
+        let mut words: Vec<&String> = index8.database.keys().collect();
+        words.sort();
+        let fst = build(&words.iter().map(|w| w.as_str()).collect::<Vec<&str>>());
+        assert_eq!(fst.len(), words.len());
+        assert!(fst.n_nodes() < words.iter().map(|w| w.len()).sum());
+
+        let all = keys(fst.stream());
+        assert_eq!(
+            all,
+            words.iter().map(|w| w.to_string()).collect::<Vec<String>>()
+        );
+
+        let mut rng = StdRng::seed_from_u64(8008135);
+        for _ in 0..50 {
+            let a = words[rng.gen_range(0..words.len())];
+            let b = words[rng.gen_range(0..words.len())];
+            let (low, high) = if a <= b { (a, b) } else { (b, a) };
+            let expected: Vec<String> = all
+                .iter()
+                .filter(|w| low.as_str() <= w.as_str() && w.as_str() < high.as_str())
+                .cloned()
+                .collect();
+            assert_eq!(
+                keys(fst.range(
+                    Bound::Included(low.as_bytes()),
+                    Bound::Excluded(high.as_bytes())
+                )),
+                expected
+            );
+
+            let prefix = &a[..min(2, a.len())];
+            let expected: Vec<String> = all
+                .iter()
+                .filter(|w| w.starts_with(prefix))
+                .cloned()
+                .collect();
+            assert_eq!(keys(fst.prefix(prefix.as_bytes())), expected);
+        }
+    }
+
+    #[test]
+    fn find_a_word() {
+        let index = setup_test();
+        search_match(index.single_search("word1"), vec!["article 0"]);
+        search_match(index.single_search("nowhere"), vec![]);
+    }
+
+    #[test]
+    fn find_a_prefix() {
+        let index = setup_test();
+        search_match(
+            index.prefix_search("word*"),
+            vec!["article 0", "article 1", "article 2", "article 3"],
+        );
+        search_match(
+            index.prefix_search("boo*"),
+            vec![
+                "article 0",
+                "article 1",
+                "article 2",
+                "article 3",
+                "article 4",
+                "article 5",
+                "article 6",
+                "article 7",
+            ],
+        );
+        search_match(
+            index.prefix_search("booty"),
+            vec!["article 1", "article 2", "article 3"],
+        );
+        search_match(index.prefix_search("x*"), vec![]);
+    }
+
+    #[test]
+    fn find_a_range() {
+        let index = setup_test();
+        search_match(
+            index.range_search("booby..booty"),
+            vec!["article 0", "article 5", "article 6", "article 7"],
+        );
+        search_match(
+            index.range_search("world..=would"),
+            vec![
+                "article 0",
+                "article 2",
+                "article 4",
+                "article 5",
+                "article 6",
+                "article 7",
+                "article 99",
+            ],
+        );
+        search_match(index.range_search("..boob"), vec![]);
+        search_match(
+            index.range_search("wou.."),
+            vec!["article 5", "article 6", "article 7", "article 99"],
+        );
+        search_match(index.range_search("no range"), vec![]);
+    }
+
+    #[test]
+    fn find_a_regex() {
+        let index = setup_test();
+        search_match(
+            index.regex_search("word[0-9]"),
+            vec!["article 0", "article 1", "article 2", "article 3"],
+        );
+        search_match(
+            index.regex_search("boo.y"),
+            vec![
+                "article 0",
+                "article 1",
+                "article 2",
+                "article 3",
+                "article 5",
+                "article 6",
+                "article 7",
+            ],
+        );
+        search_match(index.regex_search("wor"), vec![]);
+        search_match(index.regex_search("(("), vec![]);
+    }
+
+    #[test]
+    fn fields_are_only_searched_when_named() {
+        let index = setup_real();
+        search_match(index.prefix_search("title:Au*"), vec!["Autism"]);
+        search_match(index.regex_search("title:A.*m"), vec!["Anarchism", "Autism"]);
+        search_match(index.boolean_search("title:Albedo"), vec!["Albedo"]);
+        // Without the ':', the "title:..." terms are left out
+        search_match(index.prefix_search("title*"), vec![]);
+    }
+
+    #[test]
+    fn boolean_search_with_iversions() {
+        let index = setup_real();
+        search_match(index.boolean_search("!the"), vec![]);
+        search_match(
+            index.boolean_search("!letter"),
+            vec!["Anarchism", "Albedo", "Autism"],
+        );
+        search_match(index.boolean_search("letter or !the"), vec!["A"]);
+        search_match(index.boolean_search(""), vec![]);
+    }
+
+    #[test]
+    fn fst_and_index8_get_the_same_results() {
+        let file = "data/WestburyLab.wikicorp.201004_100KB.txt".to_string();
+        let index8 = Index::index8(&Config {
+            file_path: file.clone(),
+            indexno: "8".to_string(),
+        })
+        .unwrap();
+        let index = setup_real();
+
+        for (word, articles) in &index8.database {
+            assert_eq!(index.database.word_postings(word), &articles[..]);
+        }
+        for depth_vec in gen_a_lot_of_runs_bool(file, 10) {
+            for query in &depth_vec {
+                assert_eq!(
+                    index8.boolean_search_naive(query),
+                    index.boolean_search(query)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fst_and_trie_get_the_same_prefix_results() {
+        let file = "data/WestburyLab.wikicorp.201004_100KB.txt".to_string();
+        let index9 = Index::index9_1(&Config {
+            file_path: file.clone(),
+            indexno: "9_1".to_string(),
+        })
+        .unwrap();
+        let index = setup_real();
+
+        for query in gen_a_lot_of_runs_tries(file, 100, true) {
+            assert_eq!(
+                index9.prefix_search(&query),
+                index.prefix_search(&query),
+                "{}",
+                query
+            );
+        }
+    }
+}
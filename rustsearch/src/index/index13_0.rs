@@ -0,0 +1,463 @@
+use std::error::Error;
+
+use crate::helpers::*;
+use crate::index::Index;
+
+use super::*;
+
+// A suffix array over the whole corpus, for exact search of any substring. The text is the
+// cleaned articles, with the words joined by spaces as in the document store, and a '\n'
+// after every article. '\n' is never part of the cleaned text, so no match crosses into the next
+// article unless the pattern has one itself.
+//
+// The suffixes are sorted, so the suffixes starting with a pattern are one range of the array,
+// found by two binary searches: one for its start, and one for its end. Both go through the same
+// intervals of the array, whatever the pattern, and the LCP of the suffixes at the ends of each
+// interval is kept for its middle (Manber and Myers). With it, no byte of the pattern is compared
+// twice after matching, and a search takes O(m + log n) for a pattern of length m.
+pub struct SuffixArray {
+    text: Vec<u8>,
+    suffixes: Vec<u32>,
+    // For the middle of every interval of the search, the length of the common prefix of its
+    // suffix and the suffix at the lower (llcp) and upper (rlcp) end of the interval. Positions in
+    // the search are ranks plus one, with 0 and n + 1 standing for the ends of the array.
+    llcp: Vec<u32>,
+    rlcp: Vec<u32>,
+    article_starts: Vec<usize>,
+}
+
+// Sorting by prefix doubling: after each round the suffixes are sorted by their first k bytes,
+// and rank holds their order, equal for equal prefixes. Sorting by the rank of the first k bytes
+// and then the rank of the next k bytes sorts by the first 2k bytes. Both sorts are counting
+// sorts, so each round is linear.
+pub fn suffix_array(text: &[u8]) -> Vec<usize> {
+    let n = text.len();
+    let mut suffixes: Vec<usize> = (0..n).collect();
+    suffixes.sort_unstable_by_key(|&i| text[i]);
+    let mut rank: Vec<usize> = text.iter().map(|&b| b as usize).collect();
+    let mut new_rank = vec![0; n];
+    let mut k = 1;
+    while k < n {
+        // In order of the next k bytes. Suffixes shorter than k + 1 have none, so they come first.
+        let by_second: Vec<usize> = (n - k..n)
+            .chain(suffixes.iter().filter(|&&i| i >= k).map(|&i| i - k))
+            .collect();
+        // A stable counting sort by the first k bytes keeps that order within a rank
+        let mut starts = vec![0; n.max(256) + 1];
+        for &i in &by_second {
+            starts[rank[i] + 1] += 1;
+        }
+        for r in 1..starts.len() {
+            starts[r] += starts[r - 1];
+        }
+        for &i in &by_second {
+            suffixes[starts[rank[i]]] = i;
+            starts[rank[i]] += 1;
+        }
+
+        let second = |i: usize| rank.get(i + k);
+        new_rank[suffixes[0]] = 0;
+        for w in suffixes.windows(2) {
+            let differs = rank[w[0]] != rank[w[1]] || second(w[0]) != second(w[1]);
+            new_rank[w[1]] = new_rank[w[0]] + differs as usize;
+        }
+        std::mem::swap(&mut rank, &mut new_rank);
+        if rank[suffixes[n - 1]] == n - 1 {
+            break;
+        }
+        k *= 2;
+    }
+    suffixes
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+// Kasai's algorithm: lcp[r] is the length of the common prefix of the suffixes at ranks r - 1 and
+// r. Going through the suffixes in text order, each one shares at most one byte less with the
+// suffix ranked before it than the one before did, so the comparisons add up to O(n).
+pub fn lcp_array(text: &[u8], suffixes: &[usize]) -> Vec<usize> {
+    let mut rank = vec![0; text.len()];
+    for (r, &i) in suffixes.iter().enumerate() {
+        rank[i] = r;
+    }
+    let mut lcp = vec![0; text.len()];
+    let mut common = 0;
+    for i in 0..text.len() {
+        if rank[i] == 0 {
+            common = 0;
+            continue;
+        }
+        let before = suffixes[rank[i] - 1];
+        common += common_prefix(&text[i + common..], &text[before + common..]);
+        lcp[rank[i]] = common;
+        common = common.saturating_sub(1);
+    }
+    lcp
+}
+
+// Fills llcp and rlcp for the middles of the intervals below (lo, hi), and returns the LCP of the
+// suffixes at lo and hi. The ends of the array stand for suffixes sharing nothing with any other.
+fn fill_interval_lcps(
+    lcp: &[usize],
+    (lo, hi): (usize, usize),
+    llcp: &mut [u32],
+    rlcp: &mut [u32],
+) -> usize {
+    if hi - lo == 1 {
+        return match lo {
+            0 => 0,
+            _ if hi == lcp.len() + 1 => 0,
+            _ => lcp[lo],
+        };
+    }
+    let mid = (lo + hi) / 2;
+    let to_lo = fill_interval_lcps(lcp, (lo, mid), llcp, rlcp);
+    let to_hi = fill_interval_lcps(lcp, (mid, hi), llcp, rlcp);
+    // LCPs are at most the length of the text, which fits a u32
+    llcp[mid] = to_lo as u32;
+    rlcp[mid] = to_hi as u32;
+    to_lo.min(to_hi)
+}
+
+impl SuffixArray {
+    // Offsets are kept as u32, so the text of all the articles can't be longer than u32::MAX
+    pub fn new(articles: &[Vec<String>]) -> Result<Self, Box<dyn Error>> {
+        let mut text: Vec<u8> = Vec::new();
+        let mut article_starts = Vec::with_capacity(articles.len());
+        for contents in articles {
+            article_starts.push(text.len());
+            text.extend_from_slice(contents.join(" ").as_bytes());
+            text.push(b'\n');
+        }
+        if u32::try_from(text.len()).is_err() {
+            return Err("The articles are too long for a suffix array".into());
+        }
+        let suffixes = suffix_array(&text);
+        let lcp = lcp_array(&text, &suffixes);
+        let mut llcp = vec![0; text.len() + 2];
+        let mut rlcp = vec![0; text.len() + 2];
+        fill_interval_lcps(&lcp, (0, text.len() + 1), &mut llcp, &mut rlcp);
+        Ok(SuffixArray {
+            // Every suffix starts within the text, so its start fits
+            suffixes: suffixes
+                .into_iter()
+                .map(|i| u32::try_from(i).unwrap())
+                .collect(),
+            llcp,
+            rlcp,
+            text,
+            article_starts,
+        })
+    }
+
+    // The suffix at a position of the search, which is its rank plus one
+    fn suffix(&self, position: usize) -> &[u8] {
+        &self.text[self.suffixes[position - 1] as usize..]
+    }
+
+    // The rank of the first suffix that is larger than the pattern, where a suffix starting with
+    // the pattern counts as larger, or as smaller if after_matches is set.
+    //
+    // The search keeps lo below and hi above the pattern, with l and r the lengths of the common
+    // prefixes of the pattern and their suffixes. If l >= r, the suffix in the middle shares
+    // at least min(llcp, l) bytes with the pattern. When llcp and l differ, that decides which side
+    // of the pattern the middle is on without looking at it, and otherwise the comparison starts
+    // at l. The other way round if r > l.
+    fn boundary(&self, pattern: &[u8], after_matches: bool) -> usize {
+        let (mut lo, mut hi) = (0, self.suffixes.len() + 1);
+        let (mut l, mut r) = (0, 0);
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            let (llcp, rlcp) = (self.llcp[mid] as usize, self.rlcp[mid] as usize);
+            // Below the pattern, or above it, and how much it shares with the pattern
+            let (below, common) = if l >= r && llcp != l {
+                (llcp > l, llcp.min(l))
+            } else if r > l && rlcp != r {
+                (rlcp < r, rlcp.min(r))
+            } else {
+                let suffix = self.suffix(mid);
+                let skip = l.max(r);
+                let common = skip + common_prefix(&suffix[skip..], &pattern[skip..]);
+                let below = match common == pattern.len() {
+                    true => after_matches,
+                    false => common == suffix.len() || suffix[common] < pattern[common],
+                };
+                (below, common)
+            };
+            match below {
+                true => (lo, l) = (mid, common),
+                false => (hi, r) = (mid, common),
+            }
+        }
+        hi - 1
+    }
+
+    // The suffixes starting with the pattern, as a range of the array
+    pub fn find_range(&self, pattern: &[u8]) -> std::ops::Range<usize> {
+        if pattern.is_empty() {
+            return 0..0;
+        }
+        self.boundary(pattern, false)..self.boundary(pattern, true)
+    }
+
+    pub fn count(&self, pattern: &str) -> usize {
+        self.find_range(pattern.as_bytes()).len()
+    }
+
+    pub fn occurrences(&self, pattern: &str) -> Occurrences {
+        let mut offsets: Vec<usize> = self.suffixes[self.find_range(pattern.as_bytes())]
+            .iter()
+            .map(|&i| i as usize)
+            .collect();
+        offsets.sort_unstable();
+
+        let mut result = Occurrences::new();
+        for offset in offsets {
+            let article = self.article_starts.partition_point(|&s| s <= offset) - 1;
+            let start = self.article_starts[article];
+            let end = match self.article_starts.get(article + 1) {
+                Some(&next) => next - 1,
+                None => self.text.len() - 1,
+            };
+            if offset + pattern.len() > end {
+                continue;
+            }
+            match result.last_mut() {
+                Some((a, article_offsets)) if *a == article => article_offsets.push(offset - start),
+                _ => result.push((article, vec![offset - start])),
+            }
+        }
+        result
+    }
+}
+
+impl Index<SuffixArray> {
+    pub fn index13_0(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let (article_titles, articles): (Vec<String>, Vec<Vec<String>>) =
+            read_and_clean_file_to_iter(config)?
+                .into_iter()
+                .filter(|(title, _)| !title.is_empty())
+                .unzip();
+        Ok(Index {
+            database: SuffixArray::new(&articles)?,
+            article_titles,
+        })
+    }
+
+    pub fn occurrences(&self, pattern: &str) -> Occurrences {
+        self.database.occurrences(pattern)
+    }
+
+    // The occurrences with offsets in characters of the article text instead of bytes
+    pub fn char_occurrences(&self, occurrences: Occurrences) -> Occurrences {
+        occurrences
+            .into_iter()
+            .map(|(article, offsets)| {
+                let text = &self.database.text[self.database.article_starts[article]..];
+                (article, char_offsets(text, &offsets))
+            })
+            .collect()
+    }
+
+    pub fn substring_search(&self, pattern: &str) -> ArticleTitles {
+        self.occurrences(pattern)
+            .into_iter()
+            .map(|(article, _)| self.article_titles[article].clone())
+            .collect()
+    }
+
+    // The occurrences of the pattern that are whole words
+    pub fn single_search(&self, word: &str) -> ArticleTitles {
+        let text = &self.database.text;
+        let is_boundary = |i: usize| matches!(text.get(i), None | Some(b' ') | Some(b'\n'));
+        self.occurrences(word)
+            .into_iter()
+            .filter(|(article, offsets)| {
+                let start = self.database.article_starts[*article];
+                offsets.iter().any(|&offset| {
+                    (offset == 0 || is_boundary(start + offset - 1))
+                        && is_boundary(start + offset + word.len())
+                })
+            })
+            .map(|(article, _)| self.article_titles[article].clone())
+            .collect()
+    }
+}
+
+impl Search for Index<SuffixArray> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch => self.single_search(&query.search_string),
+            SearchType::ExactSearch(x) if x == "SuffixArray" => {
+                self.substring_search(&query.search_string)
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn setup_real() -> Index<SuffixArray> {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "13".to_string(),
+        ]);
+        Index::index13_0(&config).unwrap()
+    }
+
+    fn words(text: &str) -> Vec<String> {
+        text.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn suffix_array_matches_sorting() {
+        for text in ["banana", "mississippi", "aaaaaaaa", "abcabcabcab", "a", ""] {
+            let text = text.as_bytes();
+            let mut expected: Vec<usize> = (0..text.len()).collect();
+            expected.sort_by_key(|&i| &text[i..]);
+            let suffixes = suffix_array(text);
+            assert_eq!(suffixes, expected);
+        }
+    }
+
+    #[test]
+    fn ranges_and_lcps_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(13);
+        for len in [0, 1, 2, 7, 64, 300] {
+            for alphabet in [b"a".as_slice(), b"ab", b"acgt"] {
+                let text: Vec<u8> = (0..len)
+                    .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                    .collect();
+                let suffixes = suffix_array(&text);
+                let lcp = lcp_array(&text, &suffixes);
+                for r in 1..text.len() {
+                    let expected = common_prefix(&text[suffixes[r - 1]..], &text[suffixes[r]..]);
+                    assert_eq!(lcp[r], expected);
+                }
+
+                let index =
+                    SuffixArray::new(&[vec![String::from_utf8(text.clone()).unwrap()]]).unwrap();
+                for _ in 0..50 {
+                    let pattern: Vec<u8> = (0..rng.gen_range(1..6))
+                        .map(|_| b"abcgt\n"[rng.gen_range(0..6)])
+                        .collect();
+                    let range = index.find_range(&pattern);
+                    let mut expected: Vec<u32> = (0..index.text.len() as u32)
+                        .filter(|&i| index.text[i as usize..].starts_with(&pattern))
+                        .collect();
+                    let mut found = index.suffixes[range.clone()].to_vec();
+                    expected.sort_unstable();
+                    found.sort_unstable();
+                    assert_eq!(found, expected, "{:?}", pattern);
+                    // The range starts where the pattern would be inserted
+                    let start = index
+                        .suffixes
+                        .partition_point(|&i| &index.text[i as usize..] < pattern.as_slice());
+                    assert_eq!(range.start, start);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn occurrences_within_articles() {
+        let index = SuffixArray::new(&[
+            words("the banana band"),
+            words("an anagram"),
+            words("bandana"),
+        ])
+        .unwrap();
+        assert_eq!(
+            index.occurrences("an"),
+            vec![(0, vec![5, 7, 12]), (1, vec![0, 3]), (2, vec![1, 4])]
+        );
+        assert_eq!(index.occurrences("nd"), vec![(0, vec![13]), (2, vec![2])]);
+        assert_eq!(index.count("ana"), 4);
+        // Occurrences spanning two articles don't count
+        assert_eq!(index.occurrences("band\nan"), vec![]);
+        assert_eq!(index.occurrences("dana\n"), vec![]);
+        assert_eq!(index.occurrences("bandanas"), vec![]);
+        assert_eq!(index.occurrences(""), vec![]);
+    }
+
+    #[test]
+    fn occurrences_in_characters() {
+        let index = Index {
+            database: SuffixArray::new(&[words("Ça dorada"), words("ça ça")]).unwrap(),
+            article_titles: vec!["article 0".to_string(), "article 1".to_string()],
+        };
+        let occurrences = index.occurrences("a");
+        assert_eq!(occurrences, vec![(0, vec![2, 7, 9]), (1, vec![2, 6])]);
+        assert_eq!(
+            index.char_occurrences(occurrences),
+            vec![(0, vec![1, 6, 8]), (1, vec![1, 4])]
+        );
+    }
+
+    #[test]
+    fn substrings_in_wiki100_kb() {
+        let index = setup_real();
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "13".to_string(),
+        ]);
+        let articles: Vec<String> = read_and_clean_file_to_iter(&config)
+            .unwrap()
+            .into_iter()
+            .filter(|(title, _)| !title.is_empty())
+            .map(|(_, contents)| contents.join(" "))
+            .collect();
+
+        // Starting and ending in the middle of words
+        for pattern in [
+            "utis",
+            "narchis",
+            "e the a",
+            "m of ",
+            "bi-hemispherical",
+            "zzzz",
+            "A",
+        ] {
+            let expected: Occurrences = articles
+                .iter()
+                .enumerate()
+                .map(|(a, text)| {
+                    let offsets = (0..text.len())
+                        .filter(|&i| text.as_bytes()[i..].starts_with(pattern.as_bytes()))
+                        .collect::<Vec<usize>>();
+                    (a, offsets)
+                })
+                .filter(|(_, offsets)| !offsets.is_empty())
+                .collect();
+            assert_eq!(index.occurrences(pattern), expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn search_through_the_trait() {
+        let index = setup_real();
+        let query = |search_string: &str, search_type: SearchType| Query {
+            search_string: search_string.to_string(),
+            search_type,
+        };
+        let exact = || SearchType::ExactSearch("SuffixArray".to_string());
+        assert_eq!(index.search(&query("autis", exact())), vec!["Autism"]);
+        assert_eq!(
+            index.search(&query("autis", SearchType::SingleWordSearch)),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            index.search(&query("autism", SearchType::SingleWordSearch)),
+            vec!["Autism"]
+        );
+    }
+}
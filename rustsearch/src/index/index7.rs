@@ -29,7 +29,7 @@ impl Index<HashMap<String, Vec<u64>>> {
                     }
                     v_len += 1;
                 }
-                for word in contents {
+                for word in article_terms(&title, &contents) {
                     let v = database.entry(word.to_string()).or_default();
                     while v.len() < v_len {
                         v.push(0)
@@ -94,6 +94,9 @@ impl Index<HashMap<String, Vec<u64>>> {
                 .zip(self.evaluate_syntax_tree(*right_child).iter())
                 .map(|(l, r)| l | r)
                 .collect(),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
             AstNode::Name(word) => self
                 .database
                 .get(&word)
@@ -109,8 +112,11 @@ impl Index<HashMap<String, Vec<u64>>> {
 
 impl Search for Index<HashMap<String, Vec<u64>>> {
     fn search(&self, query: &Query) -> ArticleTitles {
-        match query.search_type {
+        match &query.search_type {
             SearchType::SingleWordSearch => self.single_search(&query.search_string),
+            SearchType::BooleanSearch(x) if x == "Simd" => {
+                self.boolean_search_simd(&query.search_string)
+            }
             SearchType::BooleanSearch(_) => self.boolean_search(&query.search_string),
             _ => unimplemented!(),
         }
@@ -0,0 +1,465 @@
+use std::cmp::max;
+use std::collections::HashMap;
+
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::ArticleTitles;
+
+// Number of 64-bit words evaluated at a time. Every intermediate result of a chunk is kept in a
+// buffer of this size, so the whole tree is evaluated without leaving the L1 cache.
+pub const CHUNK_WORDS: usize = 256;
+
+// Where the articles of a word come from
+pub enum Leaf<'a> {
+    Bits(&'a [u64]),
+    Articles(&'a [usize]),
+    Empty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Instruction {
+    Push(usize),
+    Not,
+    And,
+    Or,
+}
+
+// A boolean syntax tree compiled to postfix order, so it can be run once for every chunk
+pub struct BitsetProgram<'a> {
+    leaves: Vec<Leaf<'a>>,
+    instructions: Vec<Instruction>,
+    max_depth: usize,
+}
+
+impl<'a> BitsetProgram<'a> {
+    pub fn compile<F>(node: &AstNode, lookup: &F) -> BitsetProgram<'a>
+    where
+        F: Fn(&str) -> Leaf<'a>,
+    {
+        let mut program = BitsetProgram {
+            leaves: Vec::new(),
+            instructions: Vec::new(),
+            max_depth: 0,
+        };
+        program.max_depth = program.compile_node(node, lookup);
+        program
+    }
+
+    // Returns the stack depth needed to evaluate the node
+    fn compile_node<F>(&mut self, node: &AstNode, lookup: &F) -> usize
+    where
+        F: Fn(&str) -> Leaf<'a>,
+    {
+        match node {
+            AstNode::Invert(child) => {
+                let depth = self.compile_node(child, lookup);
+                self.instructions.push(Instruction::Not);
+                depth
+            }
+            AstNode::Binary(op, left_child, right_child) => {
+                let left_depth = self.compile_node(left_child, lookup);
+                let right_depth = self.compile_node(right_child, lookup);
+                self.instructions.push(match op {
+                    BinaryOp::And => Instruction::And,
+                    BinaryOp::Or => Instruction::Or,
+                    BinaryOp::Near { .. } => unreachable!("{}", NEAR_NEEDS_POSITIONS),
+                });
+                max(left_depth, right_depth + 1)
+            }
+            AstNode::Name(word) => {
+                self.leaves.push(lookup(word));
+                self.instructions
+                    .push(Instruction::Push(self.leaves.len() - 1));
+                1
+            }
+        }
+    }
+
+    pub fn evaluate(&self, n_words: usize) -> Vec<u64> {
+        let mut result: Vec<u64> = vec![0; n_words];
+        let mut stack: Vec<Vec<u64>> = vec![vec![0; CHUNK_WORDS]; self.max_depth];
+        // How far each article list leaf has been read
+        let mut cursors: Vec<usize> = vec![0; self.leaves.len()];
+
+        for (chunk_no, output) in result.chunks_mut(CHUNK_WORDS).enumerate() {
+            let first_word = chunk_no * CHUNK_WORDS;
+            let len = output.len();
+            let mut top = 0;
+            for instruction in &self.instructions {
+                match *instruction {
+                    Instruction::Push(leaf) => {
+                        self.load_leaf(
+                            leaf,
+                            first_word,
+                            &mut stack[top][..len],
+                            &mut cursors[leaf],
+                        );
+                        top += 1;
+                    }
+                    Instruction::Not => not_assign(&mut stack[top - 1][..len]),
+                    Instruction::And => {
+                        let (lower, upper) = stack.split_at_mut(top - 1);
+                        and_assign(&mut lower[top - 2][..len], &upper[0][..len]);
+                        top -= 1;
+                    }
+                    Instruction::Or => {
+                        let (lower, upper) = stack.split_at_mut(top - 1);
+                        or_assign(&mut lower[top - 2][..len], &upper[0][..len]);
+                        top -= 1;
+                    }
+                }
+            }
+            output.copy_from_slice(&stack[0][..len]);
+        }
+        result
+    }
+
+    fn load_leaf(&self, leaf: usize, first_word: usize, buffer: &mut [u64], cursor: &mut usize) {
+        match &self.leaves[leaf] {
+            Leaf::Bits(bits) => {
+                let start = first_word.min(bits.len());
+                let end = (first_word + buffer.len()).min(bits.len());
+                buffer[..end - start].copy_from_slice(&bits[start..end]);
+                buffer[end - start..].fill(0);
+            }
+            Leaf::Articles(articles) => {
+                buffer.fill(0);
+                let end_article = (first_word + buffer.len()) * 64;
+                while *cursor < articles.len() && articles[*cursor] < end_article {
+                    let bit = articles[*cursor] - first_word * 64;
+                    buffer[bit / 64] |= 1 << (bit % 64);
+                    *cursor += 1;
+                }
+            }
+            Leaf::Empty => buffer.fill(0),
+        }
+    }
+}
+
+pub fn and_assign(dst: &mut [u64], src: &[u64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safe because the CPU supports AVX2
+            return unsafe { avx2::and_assign(dst, src) };
+        }
+    }
+    scalar::and_assign(dst, src)
+}
+
+pub fn or_assign(dst: &mut [u64], src: &[u64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safe because the CPU supports AVX2
+            return unsafe { avx2::or_assign(dst, src) };
+        }
+    }
+    scalar::or_assign(dst, src)
+}
+
+pub fn not_assign(dst: &mut [u64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // Safe because the CPU supports AVX2
+            return unsafe { avx2::not_assign(dst) };
+        }
+    }
+    scalar::not_assign(dst)
+}
+
+// Works on four words at a time, which the compiler turns into vector instructions on most targets
+pub mod scalar {
+    pub fn and_assign(dst: &mut [u64], src: &[u64]) {
+        let mut dst_lanes = dst.chunks_exact_mut(4);
+        let mut src_lanes = src.chunks_exact(4);
+        for (d, s) in (&mut dst_lanes).zip(&mut src_lanes) {
+            d[0] &= s[0];
+            d[1] &= s[1];
+            d[2] &= s[2];
+            d[3] &= s[3];
+        }
+        for (d, s) in dst_lanes
+            .into_remainder()
+            .iter_mut()
+            .zip(src_lanes.remainder())
+        {
+            *d &= s;
+        }
+    }
+
+    pub fn or_assign(dst: &mut [u64], src: &[u64]) {
+        let mut dst_lanes = dst.chunks_exact_mut(4);
+        let mut src_lanes = src.chunks_exact(4);
+        for (d, s) in (&mut dst_lanes).zip(&mut src_lanes) {
+            d[0] |= s[0];
+            d[1] |= s[1];
+            d[2] |= s[2];
+            d[3] |= s[3];
+        }
+        for (d, s) in dst_lanes
+            .into_remainder()
+            .iter_mut()
+            .zip(src_lanes.remainder())
+        {
+            *d |= s;
+        }
+    }
+
+    pub fn not_assign(dst: &mut [u64]) {
+        for d in dst.iter_mut() {
+            *d = !*d;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn and_assign(dst: &mut [u64], src: &[u64]) {
+        let n = dst.len().min(src.len());
+        let mut i = 0;
+        while i + 4 <= n {
+            let d = _mm256_loadu_si256(dst.as_ptr().add(i) as *const __m256i);
+            let s = _mm256_loadu_si256(src.as_ptr().add(i) as *const __m256i);
+            _mm256_storeu_si256(
+                dst.as_mut_ptr().add(i) as *mut __m256i,
+                _mm256_and_si256(d, s),
+            );
+            i += 4;
+        }
+        super::scalar::and_assign(&mut dst[i..n], &src[i..n]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn or_assign(dst: &mut [u64], src: &[u64]) {
+        let n = dst.len().min(src.len());
+        let mut i = 0;
+        while i + 4 <= n {
+            let d = _mm256_loadu_si256(dst.as_ptr().add(i) as *const __m256i);
+            let s = _mm256_loadu_si256(src.as_ptr().add(i) as *const __m256i);
+            _mm256_storeu_si256(
+                dst.as_mut_ptr().add(i) as *mut __m256i,
+                _mm256_or_si256(d, s),
+            );
+            i += 4;
+        }
+        super::scalar::or_assign(&mut dst[i..n], &src[i..n]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn not_assign(dst: &mut [u64]) {
+        let ones = _mm256_set1_epi64x(-1);
+        let n = dst.len();
+        let mut i = 0;
+        while i + 4 <= n {
+            let d = _mm256_loadu_si256(dst.as_ptr().add(i) as *const __m256i);
+            _mm256_storeu_si256(
+                dst.as_mut_ptr().add(i) as *mut __m256i,
+                _mm256_xor_si256(d, ones),
+            );
+            i += 4;
+        }
+        super::scalar::not_assign(&mut dst[i..n]);
+    }
+}
+
+impl Index<HashMap<String, Vec<u64>>> {
+    pub fn boolean_search_simd(&self, exp: &str) -> ArticleTitles {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => {
+                self.bitvec_to_articlelist(self.evaluate_syntax_tree_simd(&node))
+            }
+            _ => vec![], // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn evaluate_syntax_tree_simd(&self, node: &AstNode) -> Vec<u64> {
+        let program = BitsetProgram::compile(node, &|word: &str| match self.database.get(word) {
+            Some(bits) => Leaf::Bits(bits),
+            None => Leaf::Empty,
+        });
+        program.evaluate(self.article_titles.len().div_ceil(64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::Config;
+    use crate::index::gen_query::gen_a_lot_of_runs_bool;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashSet;
+
+    fn setup_test() -> Index<HashMap<String, Vec<u64>>> {
+        let mut database: HashMap<String, Vec<u64>> = HashMap::new();
+        database.insert("word1".to_string(), vec![0b0000_0001, 0]);
+        database.insert("word2".to_string(), vec![0b1111_1111, 0]);
+        database.insert("word3".to_string(), vec![0b0101_0101, 0]);
+        database.insert("word4".to_string(), vec![0b0000_1110, 1]);
+        let mut article_titles: Vec<String> = Vec::new();
+        for i in 0..100 {
+            article_titles.push(format!("article {}", i).to_string());
+        }
+        Index {
+            database,
+            article_titles,
+        }
+    }
+
+    fn search_match(index: &Index<HashMap<String, Vec<u64>>>, query: &str, titles: Vec<&str>) {
+        dbg!(&query.to_string());
+        let index_result: HashSet<String> = HashSet::from_iter(index.boolean_search_simd(query));
+        assert_eq!(
+            index_result,
+            HashSet::from_iter(titles.iter().map(|s| s.to_string()))
+        )
+    }
+
+    fn random_words(rng: &mut StdRng, n: usize) -> Vec<u64> {
+        (0..n).map(|_| rng.gen()).collect()
+    }
+
+    #[test]
+    fn kernels_match_scalar_fallback() {
+        let mut rng = StdRng::seed_from_u64(8008135);
+        // Lengths that are not multiples of the vector width
+        for n in [0, 1, 3, 4, 7, 64, 257] {
+            let a = random_words(&mut rng, n);
+            let b = random_words(&mut rng, n);
+
+            let (mut fast, mut slow) = (a.clone(), a.clone());
+            and_assign(&mut fast, &b);
+            scalar::and_assign(&mut slow, &b);
+            assert_eq!(fast, slow);
+            assert_eq!(
+                fast,
+                a.iter().zip(&b).map(|(x, y)| x & y).collect::<Vec<u64>>()
+            );
+
+            let (mut fast, mut slow) = (a.clone(), a.clone());
+            or_assign(&mut fast, &b);
+            scalar::or_assign(&mut slow, &b);
+            assert_eq!(fast, slow);
+            assert_eq!(
+                fast,
+                a.iter().zip(&b).map(|(x, y)| x | y).collect::<Vec<u64>>()
+            );
+
+            let (mut fast, mut slow) = (a.clone(), a.clone());
+            not_assign(&mut fast);
+            scalar::not_assign(&mut slow);
+            assert_eq!(fast, slow);
+            assert_eq!(fast, a.iter().map(|x| !x).collect::<Vec<u64>>());
+        }
+    }
+
+    #[test]
+    fn program_spans_several_chunks() {
+        // Bitvectors long enough for the evaluation to run over several chunks
+        let mut rng = StdRng::seed_from_u64(8008135);
+        let n_words = 3 * CHUNK_WORDS + 17;
+        let mut database: HashMap<String, Vec<u64>> = HashMap::new();
+        for word in ["a", "b", "c"] {
+            database.insert(word.to_string(), random_words(&mut rng, n_words));
+        }
+        let index = Index {
+            database,
+            article_titles: (0..n_words * 64).map(|i| i.to_string()).collect(),
+        };
+
+        for query in ["a & !b", "!(a | b) & c", "a | (b & !c) | nowhere", "!a"] {
+            let node = match Expr::from_string(query).unwrap() {
+                Expr(ExprData::HasNodes(node)) => node,
+                _ => panic!(),
+            };
+            assert_eq!(
+                index.evaluate_syntax_tree_simd(&node),
+                index.evaluate_syntax_tree(node)
+            );
+        }
+    }
+
+    #[test]
+    fn article_leaves_are_converted_per_chunk() {
+        let articles: Vec<usize> = vec![0, 5, 64 * CHUNK_WORDS - 1, 64 * CHUNK_WORDS, 100000];
+        let node = AstNode::Name("x".to_string());
+        let program = BitsetProgram::compile(&node, &|_: &str| Leaf::Articles(&articles));
+        let bits = program.evaluate(100000 / 64 + 1);
+
+        let mut expected = vec![0u64; 100000 / 64 + 1];
+        for a in &articles {
+            expected[a / 64] |= 1 << (a % 64);
+        }
+        assert_eq!(bits, expected);
+    }
+
+    #[test]
+    fn find_a_word() {
+        let index = setup_test();
+        search_match(&index, "  word1 ", vec!["article 0"]);
+        search_match(
+            &index,
+            "word4",
+            vec!["article 1", "article 2", "article 3", "article 64"],
+        );
+    }
+
+    #[test]
+    fn or_and_and() {
+        let index = setup_test();
+        search_match(
+            &index,
+            "word1 | (word3 & word4)",
+            vec!["article 0", "article 2"],
+        );
+    }
+
+    #[test]
+    fn and_with_word_not_in_database() {
+        let index = setup_test();
+        search_match(&index, "word1 & nowhere", vec![]);
+        search_match(&index, "word1 | nowhere", vec!["article 0"]);
+    }
+
+    #[test]
+    fn the_empty_query() {
+        let index = setup_test();
+        search_match(&index, "", vec![]);
+        search_match(&index, "word1((", vec![]);
+    }
+
+    #[test]
+    fn empty_index() {
+        let index: Index<HashMap<String, Vec<u64>>> = Index {
+            database: HashMap::new(),
+            article_titles: Vec::new(),
+        };
+        assert_eq!(index.boolean_search_simd("word1"), Vec::<String>::new());
+        assert_eq!(index.boolean_search_simd("!word1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn index7_simd_and_index7_get_the_same_results() {
+        let file = "data/WestburyLab.wikicorp.201004_100KB.txt".to_string();
+        let index7 = Index::index7(&Config {
+            file_path: file.clone(),
+            indexno: "7_1".to_string(),
+        })
+        .unwrap();
+
+        for depth_vec in gen_a_lot_of_runs_bool(file, 10) {
+            for query in &depth_vec {
+                assert_eq!(
+                    index7.boolean_search(&query.to_string()),
+                    index7.boolean_search_simd(query)
+                );
+            }
+        }
+    }
+}
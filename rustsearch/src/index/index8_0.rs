@@ -17,7 +17,7 @@ impl Index<HashMap<String, Vec<usize>>> {
         for (title, contents) in articles_iter {
             if title != "" {
                 article_titles.push(title.to_string());
-                for word in contents {
+                for word in article_terms(&title, &contents) {
                     let v = database.entry(word.to_string()).or_default();
                     if (v.len() == 0) || (v[v.len() - 1] != article_titles.len() - 1) {
                         v.push(article_titles.len() - 1)
@@ -61,6 +61,9 @@ impl Index<HashMap<String, Vec<usize>>> {
                 self.evaluate_syntax_tree_naive(*left_child),
                 self.evaluate_syntax_tree_naive(*right_child),
             ),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
             AstNode::Name(word) => self.database.get(&word).unwrap_or(&vec![]).to_vec(),
         }
     }
@@ -112,6 +115,22 @@ impl Index<HashMap<String, Vec<usize>>> {
         result
     }
 
+    // Articles in the left list that are not in the right list, i.e. "left & !right"
+    pub fn difference(&self, left_child: Vec<usize>, right_child: Vec<usize>) -> Vec<usize> {
+        let mut result: Vec<usize> = Vec::new();
+        let mut r = 0;
+
+        for a in left_child {
+            while r < right_child.len() && right_child[r] < a {
+                r += 1;
+            }
+            if r == right_child.len() || right_child[r] != a {
+                result.push(a);
+            }
+        }
+        result
+    }
+
     pub fn invert(&self, child: Vec<usize>) -> Vec<usize> {
         let mut result: Vec<usize> = Vec::new();
         let mut p: usize = 0;
@@ -155,6 +174,12 @@ impl Search for Index<HashMap<String, Vec<usize>>> {
             SearchType::BooleanSearch(x) if x == "Bitvecs" => {
                 self.boolean_search_articles_to_bitvecs(&query.search_string)
             }
+            SearchType::BooleanSearch(x) if x == "Difference" => {
+                self.boolean_search_difference(&query.search_string)
+            }
+            SearchType::BooleanSearch(x) if x == "Simd" => {
+                self.boolean_search_simd(&query.search_string)
+            }
             _ => unimplemented!(),
         }
     }
@@ -222,6 +247,13 @@ mod tests {
         )
     }
 
+    #[test]
+    fn near_needs_positions() {
+        let index = setup_test();
+        search_match(&index, "word3 near/2 word4", vec![]);
+        search_match(&index, "!(word4 onear/1 word3)", vec![]);
+    }
+
     #[test]
     fn boolean_search_for_words_in_wiki100_kb() {
         let index = setup_real();
@@ -236,6 +268,19 @@ mod tests {
                                                                   // search_match(&index, "\"&amp;#65;\"", vec!["A"]); // A word that has special characters
     }
 
+    #[test]
+    fn field_qualified_words_in_wiki100_kb() {
+        let index = setup_real();
+
+        search_match(&index, "title:Autism", vec!["Autism"]);
+        search_match(&index, "TITLE:Anarchism | title:Albedo", vec!["Anarchism", "Albedo"]);
+        search_match(&index, "title:A & !title:Albedo", vec!["A"]);
+        // Unqualified words, and words qualified with the body, only search the body
+        search_match(&index, "body:autism", vec!["Autism"]);
+        search_match(&index, "title:autism", vec![]);
+        search_match(&index, "Albedo & !title:Albedo", vec![]);
+    }
+
     #[test]
     fn find_a_word() {
         let index = setup_test();
@@ -404,18 +449,34 @@ mod tests {
                         search_type: SearchType::BooleanSearch("Bitvecs".to_string()),
                     };
 
+                    let query7 = Query {
+                        search_string: word.clone(),
+                        search_type: SearchType::BooleanSearch("Difference".to_string()),
+                    };
+
+                    let query8 = Query {
+                        search_string: word.clone(),
+                        search_type: SearchType::BooleanSearch("Simd".to_string()),
+                    };
+
                     let article_list7_0 = index7.search(&query1);
                     let article_list8_0 = index8.search(&query2);
                     let article_list8_1: Vec<String> = index8.search(&query3);
                     let article_list8_2 = index8.search(&query4);
                     let article_list8_3 = index8.search(&query5);
                     let article_list8_4 = index8.search(&query6);
+                    let article_list8_5 = index8.search(&query7);
+                    let article_list7_1 = index7.search(&query8);
+                    let article_list8_8 = index8.search(&query8);
 
                     assert_eq!(article_list7_0, article_list8_0);
                     assert_eq!(article_list7_0, article_list8_1);
                     assert_eq!(article_list7_0, article_list8_2);
                     assert_eq!(article_list7_0, article_list8_3);
                     assert_eq!(article_list7_0, article_list8_4);
+                    assert_eq!(article_list7_0, article_list8_5);
+                    assert_eq!(article_list7_0, article_list7_1);
+                    assert_eq!(article_list7_0, article_list8_8);
                 }
             }
         }
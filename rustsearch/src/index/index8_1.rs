@@ -46,6 +46,9 @@ impl Index<HashMap<String, Vec<usize>>> {
                     ),
                 }
             }
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
             AstNode::Name(word) => self.database.get(&word).unwrap_or(&vec![]).to_vec(),
         }
     }
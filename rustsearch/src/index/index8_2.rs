@@ -40,6 +40,9 @@ impl Index<HashMap<String, Vec<usize>>> {
                 self.evaluate_syntax_tree_binary_search(*left_child),
                 self.evaluate_syntax_tree_binary_search(*right_child),
             ),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
             AstNode::Name(word) => self.database.get(&word).unwrap_or(&vec![]).to_vec(),
         }
     }
@@ -40,6 +40,9 @@ impl Index<HashMap<String, Vec<usize>>> {
                 )
                 .map(|(l, r)| l | r)
                 .collect(),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
             AstNode::Name(word) => {
                 self.to_bitvec(self.database.get(&word).unwrap_or(&vec![]).to_vec())
             }
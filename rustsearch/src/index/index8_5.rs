@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::ArticleTitles;
+
+// The result of evaluating a subtree. Negations are kept as the complement of an article list,
+// so "!the" costs nothing until it is combined with something else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArticleSet {
+    Articles(Vec<usize>),
+    ComplementOf(Vec<usize>),
+}
+
+impl Index<HashMap<String, Vec<usize>>> {
+    pub fn boolean_search_difference(&self, exp: &str) -> ArticleTitles {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => {
+                self.articleset_to_articlelist(self.evaluate_syntax_tree_difference(node))
+            }
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn evaluate_syntax_tree_difference(&self, node: AstNode) -> ArticleSet {
+        match node {
+            AstNode::Invert(child) => match self.evaluate_syntax_tree_difference(*child) {
+                ArticleSet::Articles(articles) => ArticleSet::ComplementOf(articles),
+                ArticleSet::ComplementOf(articles) => ArticleSet::Articles(articles),
+            },
+            AstNode::Binary(BinaryOp::And, left_child, right_child) => {
+                match (
+                    self.evaluate_syntax_tree_difference(*left_child),
+                    self.evaluate_syntax_tree_difference(*right_child),
+                ) {
+                    (ArticleSet::Articles(left), ArticleSet::Articles(right)) => {
+                        ArticleSet::Articles(self.and(left, right))
+                    }
+                    // a & !b = a - b
+                    (ArticleSet::Articles(left), ArticleSet::ComplementOf(right))
+                    | (ArticleSet::ComplementOf(right), ArticleSet::Articles(left)) => {
+                        ArticleSet::Articles(self.difference(left, right))
+                    }
+                    // !a & !b = !(a | b)
+                    (ArticleSet::ComplementOf(left), ArticleSet::ComplementOf(right)) => {
+                        ArticleSet::ComplementOf(self.or(left, right))
+                    }
+                }
+            }
+            AstNode::Binary(BinaryOp::Or, left_child, right_child) => {
+                match (
+                    self.evaluate_syntax_tree_difference(*left_child),
+                    self.evaluate_syntax_tree_difference(*right_child),
+                ) {
+                    (ArticleSet::Articles(left), ArticleSet::Articles(right)) => {
+                        ArticleSet::Articles(self.or(left, right))
+                    }
+                    // a | !b = !(b - a)
+                    (ArticleSet::Articles(left), ArticleSet::ComplementOf(right))
+                    | (ArticleSet::ComplementOf(right), ArticleSet::Articles(left)) => {
+                        ArticleSet::ComplementOf(self.difference(right, left))
+                    }
+                    // !a | !b = !(a & b)
+                    (ArticleSet::ComplementOf(left), ArticleSet::ComplementOf(right)) => {
+                        ArticleSet::ComplementOf(self.and(left, right))
+                    }
+                }
+            }
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
+            AstNode::Name(word) => {
+                ArticleSet::Articles(self.database.get(&word).unwrap_or(&vec![]).to_vec())
+            }
+        }
+    }
+
+    pub fn articleset_to_articlelist(&self, set: ArticleSet) -> ArticleTitles {
+        match set {
+            ArticleSet::Articles(articles) => self.vec_to_articlelist(articles),
+            ArticleSet::ComplementOf(articles) => {
+                // Walk the titles directly instead of building the complement list first
+                let mut output: Vec<String> = Vec::new();
+                let mut p = 0;
+                for (i, title) in self.article_titles.iter().enumerate() {
+                    if p < articles.len() && articles[p] == i {
+                        p += 1;
+                    } else {
+                        output.push(title.clone());
+                    }
+                }
+                output
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn setup_real() -> Index<HashMap<String, Vec<usize>>> {
+        let config = crate::helpers::Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "8_5".to_string(),
+        ]);
+        Index::index8(&config).unwrap()
+    }
+
+    fn setup_test() -> Index<HashMap<String, Vec<usize>>> {
+        let mut database: HashMap<String, Vec<usize>> = HashMap::new();
+        database.insert("word1".to_string(), vec![0]);
+        database.insert("word2".to_string(), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        database.insert("word3".to_string(), vec![0, 2, 4, 6]);
+        database.insert("word4".to_string(), vec![1, 2, 3]);
+        let mut article_titles: Vec<String> = Vec::new();
+        for i in 0..100 {
+            article_titles.push(format!("article {}", i).to_string());
+        }
+        Index {
+            database,
+            article_titles,
+        }
+    }
+
+    fn evaluate(index: &Index<HashMap<String, Vec<usize>>>, query: &str) -> ArticleSet {
+        match Expr::from_string(query).unwrap() {
+            Expr(ExprData::HasNodes(node)) => index.evaluate_syntax_tree_difference(node),
+            _ => panic!("Query should not be empty"),
+        }
+    }
+
+    fn search_match(index: &Index<HashMap<String, Vec<usize>>>, query: &str, titles: Vec<&str>) {
+        dbg!(&query.to_string());
+        let index_result: HashSet<String> =
+            HashSet::from_iter(index.boolean_search_difference(&query.to_string()));
+        assert_eq!(
+            index_result,
+            HashSet::from_iter(titles.iter().map(|s| s.to_string()))
+        )
+    }
+
+    #[test]
+    fn difference_of_article_lists() {
+        let index = setup_test();
+        assert_eq!(
+            index.difference(vec![0, 1, 2, 3, 4, 5, 6, 7], vec![0, 2, 4, 6]),
+            vec![1, 3, 5, 7]
+        );
+        assert_eq!(index.difference(vec![1, 2, 3], vec![]), vec![1, 2, 3]);
+        assert_eq!(index.difference(vec![], vec![1, 2, 3]), vec![]);
+        assert_eq!(
+            index.difference(vec![1, 2, 3], vec![0, 4, 99]),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn top_level_negation_stays_lazy() {
+        let index = setup_test();
+        assert_eq!(
+            evaluate(&index, "!word3"),
+            ArticleSet::ComplementOf(vec![0, 2, 4, 6])
+        );
+        assert_eq!(
+            evaluate(&index, "!word3 & !word4"),
+            ArticleSet::ComplementOf(vec![0, 1, 2, 3, 4, 6])
+        );
+        assert_eq!(
+            evaluate(&index, "!(word3 | word4)"),
+            ArticleSet::ComplementOf(vec![0, 1, 2, 3, 4, 6])
+        );
+        assert_eq!(
+            evaluate(&index, "!word3 | !word4"),
+            ArticleSet::ComplementOf(vec![2])
+        );
+    }
+
+    #[test]
+    fn and_not_becomes_difference() {
+        let index = setup_test();
+        assert_eq!(
+            evaluate(&index, "word2 & !word3"),
+            ArticleSet::Articles(vec![1, 3, 5, 7])
+        );
+        assert_eq!(
+            evaluate(&index, "!word3 & word2"),
+            ArticleSet::Articles(vec![1, 3, 5, 7])
+        );
+        // At any depth, including under double negation
+        assert_eq!(
+            evaluate(&index, "word1 | (word2 & !(word3 | word4))"),
+            ArticleSet::Articles(vec![0, 5, 7])
+        );
+        assert_eq!(
+            evaluate(&index, "word2 & !(word3 & !word1)"),
+            ArticleSet::Articles(vec![0, 1, 3, 5, 7])
+        );
+    }
+
+    #[test]
+    fn or_with_negation_stays_complement() {
+        let index = setup_test();
+        assert_eq!(
+            evaluate(&index, "word4 | !word3"),
+            ArticleSet::ComplementOf(vec![0, 4, 6])
+        );
+    }
+
+    #[test]
+    fn complement_to_articlelist() {
+        let index = setup_test();
+        let titles = index
+            .articleset_to_articlelist(ArticleSet::ComplementOf((1..100).collect::<Vec<usize>>()));
+        assert_eq!(titles, vec!["article 0".to_string()]);
+        assert_eq!(
+            index
+                .articleset_to_articlelist(ArticleSet::ComplementOf(vec![]))
+                .len(),
+            100
+        );
+    }
+
+    #[test]
+    fn boolean_search_for_words_in_wiki100_kb() {
+        let index = setup_real();
+
+        search_match(
+            &index,
+            "the | autism",
+            vec!["Anarchism", "Autism", "A", "Albedo"],
+        );
+        search_match(&index, "autism", vec!["Autism"]); // A word that should only be in one article
+        search_match(&index, "bi-hemispherical", vec!["Albedo"]); // Check for no splitting of 'bi-hemispherical'
+    }
+
+    #[test]
+    fn find_a_word() {
+        let index = setup_test();
+        search_match(&index, "  word1 ", vec!["article 0"]);
+    }
+
+    #[test]
+    fn ands_two_words() {
+        let index = setup_test();
+        search_match(&index, "word1 & word3", vec!["article 0"]);
+    }
+
+    #[test]
+    fn or_two_words() {
+        let index = setup_test();
+        search_match(
+            &index,
+            "word1 | word4",
+            vec!["article 0", "article 1", "article 2", "article 3"],
+        );
+    }
+
+    #[test]
+    fn or_and_and() {
+        let index = setup_test();
+        search_match(
+            &index,
+            "word1 | (word3 & word4)",
+            vec!["article 0", "article 2"],
+        );
+    }
+
+    #[test]
+    fn or_with_word_not_in_database() {
+        let index = setup_test();
+        search_match(&index, "word1 | nowhere", vec!["article 0"]);
+    }
+
+    #[test]
+    fn and_with_word_not_in_database() {
+        let index = setup_test();
+        search_match(&index, "word1 & nowhere", vec![]);
+    }
+
+    #[test]
+    fn word_not_in_database() {
+        let index = setup_test();
+        search_match(&index, "nowhere", vec![]);
+    }
+
+    #[test]
+    fn the_empty_query() {
+        let index = setup_test();
+        search_match(&index, "", vec![]);
+    }
+
+    #[test]
+    fn erroneous_query_finds_nothing() {
+        let index = setup_test();
+        search_match(&index, "word1((", vec![]);
+    }
+
+    #[test]
+    fn boolean_search_with_iversions() {
+        let index = setup_real();
+
+        search_match(&index, "!the", vec![]);
+        search_match(&index, "!letter", vec!["Anarchism", "Albedo", "Autism"]);
+
+        search_match(&index, "letter & !the", vec![]);
+        search_match(
+            &index,
+            "!letter & the",
+            vec!["Anarchism", "Albedo", "Autism"],
+        );
+        search_match(&index, "!letter & political", vec!["Anarchism"]);
+        search_match(&index, "!letter & !political", vec!["Albedo", "Autism"]);
+        search_match(&index, "!(letter or political)", vec!["Albedo", "Autism"]);
+
+        search_match(&index, "letter or !the", vec!["A"]);
+        search_match(
+            &index,
+            "!letter or the",
+            vec!["A", "Anarchism", "Albedo", "Autism"],
+        );
+        search_match(
+            &index,
+            "!letter or political",
+            vec!["Anarchism", "Albedo", "Autism"],
+        );
+        search_match(
+            &index,
+            "!letter or !political",
+            vec!["A", "Anarchism", "Albedo", "Autism"],
+        );
+        search_match(
+            &index,
+            "!(letter and political)",
+            vec!["A", "Anarchism", "Albedo", "Autism"],
+        );
+    }
+}
@@ -0,0 +1,526 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::helpers::*;
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::*;
+
+// Number of articles covered by each skip pointer
+pub const SKIP_LENGTH: usize = 64;
+
+pub struct SkipList {
+    pub articles: Vec<usize>,
+    // The largest article number in each block of SKIP_LENGTH articles
+    pub skips: Vec<usize>,
+}
+
+impl SkipList {
+    pub fn new() -> SkipList {
+        SkipList {
+            articles: Vec::new(),
+            skips: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, article_number: usize) {
+        if self.articles.len() / SKIP_LENGTH == self.skips.len() {
+            self.skips.push(article_number);
+        } else {
+            let last = self.skips.len() - 1;
+            self.skips[last] = article_number;
+        }
+        self.articles.push(article_number);
+    }
+
+    pub fn len(&self) -> usize {
+        self.articles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.articles.is_empty()
+    }
+
+    pub fn last(&self) -> Option<usize> {
+        self.articles.last().copied()
+    }
+
+    // The first position at or after `from` holding an article >= target, or len() if there is none.
+    // Whole blocks are skipped using the skip pointers, and the remaining block is galloped through.
+    pub fn advance(&self, from: usize, target: usize) -> usize {
+        if from >= self.articles.len() {
+            return self.articles.len();
+        }
+        if self.articles[from] >= target {
+            return from;
+        }
+        let block = from / SKIP_LENGTH;
+        if self.skips[block] >= target {
+            return gallop(&self.articles, from, target);
+        }
+        let block = gallop(&self.skips, block + 1, target);
+        if block == self.skips.len() {
+            return self.articles.len();
+        }
+        gallop(&self.articles, block * SKIP_LENGTH, target)
+    }
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        SkipList::new()
+    }
+}
+
+impl From<Vec<usize>> for SkipList {
+    fn from(articles: Vec<usize>) -> Self {
+        let skips = articles
+            .chunks(SKIP_LENGTH)
+            .map(|block| block[block.len() - 1])
+            .collect();
+        SkipList { articles, skips }
+    }
+}
+
+// Exponential search: the first position at or after `from` holding a value >= target,
+// or list.len() if there is none
+pub fn gallop(list: &[usize], from: usize, target: usize) -> usize {
+    if from >= list.len() {
+        return list.len();
+    }
+    let mut bound = 1;
+    while from + bound < list.len() && list[from + bound] < target {
+        bound *= 2;
+    }
+    // list[from + bound / 2] < target (or bound == 1), and list[from + bound] >= target if it exists
+    let low = from + bound / 2;
+    let high = min(from + bound + 1, list.len());
+    low + list[low..high].partition_point(|&a| a < target)
+}
+
+// Intersects all lists at once. All cursors are advanced towards the same candidate article,
+// and whenever a list overshoots the candidate, the overshooting article becomes the new candidate.
+pub fn intersect_all(mut lists: Vec<&SkipList>) -> Vec<usize> {
+    let mut result: Vec<usize> = Vec::new();
+    if lists.is_empty() || lists.iter().any(|l| l.is_empty()) {
+        return result;
+    }
+    // Drive the search from the shortest list
+    lists.sort_by_key(|l| l.len());
+
+    let mut cursors = vec![0; lists.len()];
+    let mut candidate = lists[0].articles[0];
+    'search: loop {
+        for i in 0..lists.len() {
+            cursors[i] = lists[i].advance(cursors[i], candidate);
+            if cursors[i] == lists[i].len() {
+                break 'search;
+            }
+            let article = lists[i].articles[cursors[i]];
+            if article > candidate {
+                candidate = article;
+                continue 'search;
+            }
+        }
+        // Every cursor points at the candidate
+        result.push(candidate);
+        cursors[0] += 1;
+        if cursors[0] == lists[0].len() {
+            break;
+        }
+        candidate = lists[0].articles[cursors[0]];
+    }
+    result
+}
+
+// Flattens a chain of ands, e.g. "a & (b & !c)" gives [a, b, !c]
+fn collect_and_operands(node: AstNode, operands: &mut Vec<AstNode>) {
+    match node {
+        AstNode::Binary(BinaryOp::And, left_child, right_child) => {
+            collect_and_operands(*left_child, operands);
+            collect_and_operands(*right_child, operands);
+        }
+        other => operands.push(other),
+    }
+}
+
+impl Index<HashMap<String, SkipList>> {
+    pub fn index8_6(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let mut database: HashMap<String, SkipList> = HashMap::new();
+
+        let articles_iter = read_and_clean_file_to_iter(config)?;
+        let mut article_titles: Vec<String> = Vec::new();
+
+        for (title, contents) in articles_iter {
+            if !title.is_empty() {
+                article_titles.push(title.to_string());
+                let article_number = article_titles.len() - 1;
+                for word in article_terms(&title, &contents) {
+                    let v = database.entry(word.to_string()).or_default();
+                    if v.last() != Some(article_number) {
+                        v.push(article_number)
+                    }
+                }
+            }
+        }
+
+        Ok(Index {
+            database,
+            article_titles,
+        })
+    }
+
+    pub fn vec_to_articlelist(&self, vec: Vec<usize>) -> ArticleTitles {
+        vec.into_iter()
+            .map(|i| self.article_titles[i].clone())
+            .collect()
+    }
+
+    pub fn single_search(&self, word: &str) -> ArticleTitles {
+        match self.database.get(word) {
+            Some(list) => self.vec_to_articlelist(list.articles.clone()),
+            None => vec![],
+        }
+    }
+
+    pub fn boolean_search_skip_pointers(&self, exp: &str) -> ArticleTitles {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => {
+                self.vec_to_articlelist(self.evaluate_syntax_tree_skip_pointers(node))
+            }
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn evaluate_syntax_tree_skip_pointers(&self, node: AstNode) -> Vec<usize> {
+        match node {
+            AstNode::Invert(child) => self.invert(self.evaluate_syntax_tree_skip_pointers(*child)),
+            and_node @ AstNode::Binary(BinaryOp::And, _, _) => {
+                let mut operands = Vec::new();
+                collect_and_operands(and_node, &mut operands);
+
+                let mut stored: Vec<&SkipList> = Vec::new();
+                let mut evaluated: Vec<SkipList> = Vec::new();
+                let mut negated: Vec<Vec<usize>> = Vec::new();
+                for operand in operands {
+                    match operand {
+                        AstNode::Name(word) => match self.database.get(&word) {
+                            Some(list) => stored.push(list),
+                            None => return vec![], // The intersection is empty
+                        },
+                        AstNode::Invert(child) => {
+                            negated.push(self.evaluate_syntax_tree_skip_pointers(*child))
+                        }
+                        other => evaluated.push(SkipList::from(
+                            self.evaluate_syntax_tree_skip_pointers(other),
+                        )),
+                    }
+                }
+                stored.extend(evaluated.iter());
+
+                let mut result = if stored.is_empty() {
+                    // Only negations, so at least one of them has to be materialized
+                    self.invert(negated.pop().unwrap())
+                } else {
+                    intersect_all(stored)
+                };
+                for excluded in negated {
+                    result = self.difference(result, &excluded);
+                }
+                result
+            }
+            AstNode::Binary(BinaryOp::Or, left_child, right_child) => self.or(
+                self.evaluate_syntax_tree_skip_pointers(*left_child),
+                self.evaluate_syntax_tree_skip_pointers(*right_child),
+            ),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
+            AstNode::Name(word) => match self.database.get(&word) {
+                Some(list) => list.articles.clone(),
+                None => vec![],
+            },
+        }
+    }
+
+    // Same merge as in index8_0
+    pub fn or(&self, left_child: Vec<usize>, right_child: Vec<usize>) -> Vec<usize> {
+        let mut result: Vec<usize> = Vec::with_capacity(left_child.len() + right_child.len());
+        let mut l = 0;
+        let mut r = 0;
+
+        while l < left_child.len() && r < right_child.len() {
+            if left_child[l] > right_child[r] {
+                result.push(right_child[r]);
+                r += 1;
+            } else if left_child[l] < right_child[r] {
+                result.push(left_child[l]);
+                l += 1;
+            } else {
+                result.push(left_child[l]);
+                l += 1;
+                r += 1;
+            }
+        }
+        result.extend_from_slice(&left_child[l..]);
+        result.extend_from_slice(&right_child[r..]);
+        result
+    }
+
+    // Articles in the left list that are not in the right list, galloping through the right list
+    pub fn difference(&self, left_child: Vec<usize>, right_child: &[usize]) -> Vec<usize> {
+        let mut r = 0;
+        left_child
+            .into_iter()
+            .filter(|&a| {
+                r = gallop(right_child, r, a);
+                r == right_child.len() || right_child[r] != a
+            })
+            .collect()
+    }
+
+    pub fn invert(&self, child: Vec<usize>) -> Vec<usize> {
+        let mut result: Vec<usize> = Vec::new();
+        let mut p: usize = 0;
+
+        for i in 0..self.article_titles.len() {
+            if (p >= child.len()) || (i < child[p]) {
+                result.push(i)
+            } else {
+                p += 1;
+            }
+        }
+        result
+    }
+}
+
+impl Search for Index<HashMap<String, SkipList>> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch => self.single_search(&query.search_string),
+            SearchType::BooleanSearch(x) if x == "SkipPointers" => {
+                self.boolean_search_skip_pointers(&query.search_string)
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::gen_query::gen_a_lot_of_runs_bool;
+    use std::collections::HashSet;
+
+    fn setup_real() -> Index<HashMap<String, SkipList>> {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "8_6".to_string(),
+        ]);
+        Index::index8_6(&config).unwrap()
+    }
+
+    fn setup_test() -> Index<HashMap<String, SkipList>> {
+        let mut database: HashMap<String, SkipList> = HashMap::new();
+        database.insert("word1".to_string(), SkipList::from(vec![0]));
+        database.insert(
+            "word2".to_string(),
+            SkipList::from(vec![0, 1, 2, 3, 4, 5, 6, 7]),
+        );
+        database.insert("word3".to_string(), SkipList::from(vec![0, 2, 4, 6]));
+        database.insert("word4".to_string(), SkipList::from(vec![1, 2, 3]));
+        // Long enough to span several skip blocks
+        database.insert(
+            "even".to_string(),
+            SkipList::from((0..1000).step_by(2).collect::<Vec<usize>>()),
+        );
+        database.insert(
+            "odd".to_string(),
+            SkipList::from((1..1000).step_by(2).collect::<Vec<usize>>()),
+        );
+        database.insert(
+            "tens".to_string(),
+            SkipList::from((0..1000).step_by(10).collect::<Vec<usize>>()),
+        );
+        let mut article_titles: Vec<String> = Vec::new();
+        for i in 0..1000 {
+            article_titles.push(format!("article {}", i).to_string());
+        }
+        Index {
+            database,
+            article_titles,
+        }
+    }
+
+    fn search_match(index: &Index<HashMap<String, SkipList>>, query: &str, titles: Vec<&str>) {
+        dbg!(&query.to_string());
+        let index_result: HashSet<String> =
+            HashSet::from_iter(index.boolean_search_skip_pointers(query));
+        assert_eq!(
+            index_result,
+            HashSet::from_iter(titles.iter().map(|s| s.to_string()))
+        )
+    }
+
+    #[test]
+    fn skips_are_block_maxima() {
+        let list = SkipList::from((0..200).collect::<Vec<usize>>());
+        assert_eq!(list.skips, vec![63, 127, 191, 199]);
+
+        let mut pushed = SkipList::new();
+        for a in 0..200 {
+            pushed.push(a);
+        }
+        assert_eq!(pushed.skips, list.skips);
+    }
+
+    #[test]
+    fn gallop_finds_first_larger_or_equal() {
+        let list: Vec<usize> = (0..100).map(|x| x * 3).collect();
+        assert_eq!(gallop(&list, 0, 0), 0);
+        assert_eq!(gallop(&list, 0, 1), 1);
+        assert_eq!(gallop(&list, 0, 3), 1);
+        assert_eq!(gallop(&list, 5, 3), 5);
+        assert_eq!(gallop(&list, 0, 150), 50);
+        assert_eq!(gallop(&list, 10, 298), 100);
+        assert_eq!(gallop(&list, 0, 297), 99);
+        assert_eq!(gallop(&list, 100, 0), 100);
+    }
+
+    #[test]
+    fn advance_skips_whole_blocks() {
+        let list = SkipList::from((0..1000).step_by(2).collect::<Vec<usize>>());
+        assert_eq!(list.advance(0, 0), 0);
+        assert_eq!(list.advance(0, 501), 251);
+        assert_eq!(list.advance(10, 4), 10);
+        assert_eq!(list.advance(63, 998), 499);
+        assert_eq!(list.advance(0, 999), 500);
+    }
+
+    #[test]
+    fn intersect_all_matches_pairwise_intersection() {
+        let index = setup_test();
+        let even = index.database.get("even").unwrap();
+        let tens = index.database.get("tens").unwrap();
+        let odd = index.database.get("odd").unwrap();
+        let word3 = index.database.get("word3").unwrap();
+
+        assert_eq!(
+            intersect_all(vec![even, tens]),
+            (0..1000).step_by(10).collect::<Vec<usize>>()
+        );
+        assert_eq!(intersect_all(vec![even, odd]), Vec::<usize>::new());
+        assert_eq!(intersect_all(vec![even, tens, word3]), vec![0]);
+        assert_eq!(intersect_all(vec![]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn and_chains_with_long_lists() {
+        let index = setup_test();
+        search_match(&index, "even & tens & word2", vec!["article 0"]);
+        search_match(&index, "odd & word4", vec!["article 1", "article 3"]);
+        search_match(
+            &index,
+            "word2 & !even & !word4",
+            vec!["article 5", "article 7"],
+        );
+        search_match(
+            &index,
+            "!odd & !tens & word2",
+            vec!["article 2", "article 4", "article 6"],
+        );
+    }
+
+    #[test]
+    fn boolean_search_for_words_in_wiki100_kb() {
+        let index = setup_real();
+
+        search_match(
+            &index,
+            "the | autism",
+            vec!["Anarchism", "Autism", "A", "Albedo"],
+        );
+        search_match(&index, "autism", vec!["Autism"]); // A word that should only be in one article
+        search_match(&index, "bi-hemispherical", vec!["Albedo"]); // Check for no splitting of 'bi-hemispherical'
+    }
+
+    #[test]
+    fn find_a_word() {
+        let index = setup_test();
+        search_match(&index, "  word1 ", vec!["article 0"]);
+    }
+
+    #[test]
+    fn ands_two_words() {
+        let index = setup_test();
+        search_match(&index, "word1 & word3", vec!["article 0"]);
+    }
+
+    #[test]
+    fn or_and_and() {
+        let index = setup_test();
+        search_match(
+            &index,
+            "word1 | (word3 & word4)",
+            vec!["article 0", "article 2"],
+        );
+    }
+
+    #[test]
+    fn and_with_word_not_in_database() {
+        let index = setup_test();
+        search_match(&index, "word1 & nowhere", vec![]);
+    }
+
+    #[test]
+    fn the_empty_query() {
+        let index = setup_test();
+        search_match(&index, "", vec![]);
+    }
+
+    #[test]
+    fn erroneous_query_finds_nothing() {
+        let index = setup_test();
+        search_match(&index, "word1((", vec![]);
+    }
+
+    #[test]
+    fn boolean_search_with_iversions() {
+        let index = setup_real();
+
+        search_match(&index, "!the", vec![]);
+        search_match(&index, "!letter", vec!["Anarchism", "Albedo", "Autism"]);
+        search_match(&index, "letter & !the", vec![]);
+        search_match(&index, "!letter & political", vec!["Anarchism"]);
+        search_match(&index, "!letter & !political", vec!["Albedo", "Autism"]);
+        search_match(&index, "letter or !the", vec!["A"]);
+        search_match(
+            &index,
+            "!(letter and political)",
+            vec!["A", "Anarchism", "Albedo", "Autism"],
+        );
+    }
+
+    #[test]
+    fn index8_6_and_index8_get_the_same_results() {
+        let file = "data/WestburyLab.wikicorp.201004_100KB.txt".to_string();
+        let config = Config {
+            file_path: file.clone(),
+            indexno: "8_6".to_string(),
+        };
+        let index8 = Index::index8(&config).unwrap();
+        let index8_6 = Index::index8_6(&config).unwrap();
+
+        for depth_vec in gen_a_lot_of_runs_bool(file, 10) {
+            for query in &depth_vec {
+                assert_eq!(
+                    index8.boolean_search_naive(query),
+                    index8_6.boolean_search_skip_pointers(query)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,789 @@
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::helpers::*;
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::*;
+
+// Largest number of articles kept in an array container before switching to a bitmap
+const ARRAY_MAX: usize = 4096;
+// A bitmap container has one bit for each of the 2^16 low bits of an article number
+const BITMAP_WORDS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Container {
+    // Sorted low bits, for sparse chunks
+    Array(Vec<u16>),
+    // 65536 bits, for dense chunks
+    Bitmap(Vec<u64>),
+    // Sorted, inclusive intervals (start, end) of consecutive low bits
+    Run(Vec<(u16, u16)>),
+}
+
+impl Container {
+    // Picks the smallest of the three representations for the given sorted low bits
+    pub fn from_sorted(values: Vec<u16>) -> Container {
+        let runs = runs_from_sorted(&values);
+        if 4 * runs.len() < min(2 * values.len(), 2 * BITMAP_WORDS * 4) {
+            Container::Run(runs)
+        } else if values.len() <= ARRAY_MAX {
+            Container::Array(values)
+        } else {
+            let mut bits = vec![0; BITMAP_WORDS];
+            for v in values {
+                bits[v as usize / 64] |= 1 << (v % 64);
+            }
+            Container::Bitmap(bits)
+        }
+    }
+
+    fn from_runs(runs: Vec<(u16, u16)>) -> Container {
+        let cardinality: usize = runs.iter().map(|&(s, e)| (e - s) as usize + 1).sum();
+        if 4 * runs.len() < min(2 * cardinality, 2 * BITMAP_WORDS * 4) {
+            Container::Run(runs)
+        } else {
+            Container::from_sorted(
+                runs.into_iter()
+                    .flat_map(|(s, e)| s..=e)
+                    .collect::<Vec<u16>>(),
+            )
+        }
+    }
+
+    fn from_bitmap(bits: Vec<u64>) -> Container {
+        let cardinality: usize = bits.iter().map(|w| w.count_ones() as usize).sum();
+        // A run starts at every set bit whose preceding bit is unset
+        let mut n_runs = 0;
+        let mut carry = 0;
+        for w in &bits {
+            n_runs += (w & !((w << 1) | carry)).count_ones() as usize;
+            carry = w >> 63;
+        }
+        if 4 * n_runs < min(2 * cardinality, 2 * BITMAP_WORDS * 4) || cardinality <= ARRAY_MAX {
+            Container::from_sorted(Container::Bitmap(bits).values())
+        } else {
+            Container::Bitmap(bits)
+        }
+    }
+
+    pub fn cardinality(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap(bits) => bits.iter().map(|w| w.count_ones() as usize).sum(),
+            Container::Run(runs) => runs.iter().map(|&(s, e)| (e - s) as usize + 1).sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Container::Array(values) => values.is_empty(),
+            Container::Bitmap(bits) => bits.iter().all(|&w| w == 0),
+            Container::Run(runs) => runs.is_empty(),
+        }
+    }
+
+    pub fn contains(&self, x: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&x).is_ok(),
+            Container::Bitmap(bits) => bits[x as usize / 64] & (1 << (x % 64)) != 0,
+            Container::Run(runs) => {
+                let i = runs.partition_point(|&(start, _)| start <= x);
+                i > 0 && runs[i - 1].1 >= x
+            }
+        }
+    }
+
+    pub fn values(&self) -> Vec<u16> {
+        match self {
+            Container::Array(values) => values.clone(),
+            Container::Bitmap(bits) => {
+                let mut values = Vec::new();
+                for (i, &w) in bits.iter().enumerate() {
+                    let mut w = w;
+                    while w != 0 {
+                        values.push((i * 64) as u16 + w.trailing_zeros() as u16);
+                        w &= w - 1;
+                    }
+                }
+                values
+            }
+            Container::Run(runs) => runs.iter().flat_map(|&(s, e)| s..=e).collect(),
+        }
+    }
+
+    fn to_bitmap(&self) -> Vec<u64> {
+        match self {
+            Container::Bitmap(bits) => bits.clone(),
+            Container::Array(values) => {
+                let mut bits = vec![0; BITMAP_WORDS];
+                for &v in values {
+                    bits[v as usize / 64] |= 1 << (v % 64);
+                }
+                bits
+            }
+            Container::Run(runs) => {
+                let mut bits = vec![0; BITMAP_WORDS];
+                for &(s, e) in runs {
+                    set_range(&mut bits, s as usize, e as usize);
+                }
+                bits
+            }
+        }
+    }
+
+    pub fn and(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(a), Container::Array(b)) => {
+                let mut result = Vec::new();
+                let (mut i, mut j) = (0, 0);
+                while i < a.len() && j < b.len() {
+                    if a[i] < b[j] {
+                        i += 1;
+                    } else if a[i] > b[j] {
+                        j += 1;
+                    } else {
+                        result.push(a[i]);
+                        i += 1;
+                        j += 1;
+                    }
+                }
+                Container::Array(result)
+            }
+            (Container::Array(a), x) | (x, Container::Array(a)) => {
+                Container::Array(a.iter().copied().filter(|&v| x.contains(v)).collect())
+            }
+            (Container::Run(a), Container::Run(b)) => {
+                let mut result = Vec::new();
+                let (mut i, mut j) = (0, 0);
+                while i < a.len() && j < b.len() {
+                    let start = max(a[i].0, b[j].0);
+                    let end = min(a[i].1, b[j].1);
+                    if start <= end {
+                        result.push((start, end));
+                    }
+                    if a[i].1 < b[j].1 {
+                        i += 1;
+                    } else {
+                        j += 1;
+                    }
+                }
+                Container::from_runs(result)
+            }
+            (a, b) => Container::from_bitmap(
+                a.to_bitmap()
+                    .iter()
+                    .zip(b.to_bitmap().iter())
+                    .map(|(l, r)| l & r)
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn or(&self, other: &Container) -> Container {
+        match (self, other) {
+            (Container::Array(a), Container::Array(b)) => {
+                let mut result = Vec::with_capacity(a.len() + b.len());
+                let (mut i, mut j) = (0, 0);
+                while i < a.len() && j < b.len() {
+                    if a[i] < b[j] {
+                        result.push(a[i]);
+                        i += 1;
+                    } else if a[i] > b[j] {
+                        result.push(b[j]);
+                        j += 1;
+                    } else {
+                        result.push(a[i]);
+                        i += 1;
+                        j += 1;
+                    }
+                }
+                result.extend_from_slice(&a[i..]);
+                result.extend_from_slice(&b[j..]);
+                Container::from_sorted(result)
+            }
+            (Container::Run(a), Container::Run(b)) => {
+                let mut all: Vec<(u16, u16)> = a.iter().chain(b.iter()).copied().collect();
+                all.sort();
+                let mut result: Vec<(u16, u16)> = Vec::new();
+                for (s, e) in all {
+                    match result.last_mut() {
+                        // Overlapping or adjacent intervals are merged
+                        Some(last) if s as usize <= last.1 as usize + 1 => last.1 = max(last.1, e),
+                        _ => result.push((s, e)),
+                    }
+                }
+                Container::from_runs(result)
+            }
+            (a, b) => Container::from_bitmap(
+                a.to_bitmap()
+                    .iter()
+                    .zip(b.to_bitmap().iter())
+                    .map(|(l, r)| l | r)
+                    .collect(),
+            ),
+        }
+    }
+
+    // The low bits in self that are not in other
+    pub fn andnot(&self, other: &Container) -> Container {
+        match self {
+            Container::Array(a) => {
+                Container::Array(a.iter().copied().filter(|&v| !other.contains(v)).collect())
+            }
+            a => Container::from_bitmap(
+                a.to_bitmap()
+                    .iter()
+                    .zip(other.to_bitmap().iter())
+                    .map(|(l, r)| l & !r)
+                    .collect(),
+            ),
+        }
+    }
+
+    // The complement within the first `len` low bits (1 <= len <= 65536)
+    pub fn not(&self, len: usize) -> Container {
+        match self {
+            Container::Run(runs) => {
+                let mut result = Vec::new();
+                let mut next = 0;
+                for &(s, e) in runs {
+                    if s as usize >= len {
+                        break;
+                    }
+                    if (s as usize) > next {
+                        result.push((next as u16, s - 1));
+                    }
+                    next = e as usize + 1;
+                }
+                if next < len {
+                    result.push((next as u16, (len - 1) as u16));
+                }
+                Container::from_runs(result)
+            }
+            other => {
+                let mut universe = vec![0; BITMAP_WORDS];
+                set_range(&mut universe, 0, len - 1);
+                Container::from_bitmap(
+                    other
+                        .to_bitmap()
+                        .iter()
+                        .zip(universe.iter())
+                        .map(|(b, u)| !b & u)
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+fn runs_from_sorted(values: &[u16]) -> Vec<(u16, u16)> {
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for &v in values {
+        match runs.last_mut() {
+            Some(last) if last.1 as usize + 1 == v as usize => last.1 = v,
+            _ => runs.push((v, v)),
+        }
+    }
+    runs
+}
+
+// Sets the bits start..=end
+fn set_range(bits: &mut [u64], start: usize, end: usize) {
+    for (i, word) in bits.iter_mut().enumerate().take(end / 64 + 1).skip(start / 64) {
+        let low = if i == start / 64 { start % 64 } else { 0 };
+        let high = if i == end / 64 { end % 64 } else { 63 };
+        let mask = if high - low == 63 {
+            u64::MAX
+        } else {
+            ((1u64 << (high - low + 1)) - 1) << low
+        };
+        *word |= mask;
+    }
+}
+
+// Article numbers split into chunks of 2^16, keyed by the high bits and sorted by key.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RoaringBitmap {
+    pub containers: Vec<(u16, Container)>,
+}
+
+impl RoaringBitmap {
+    pub fn new() -> RoaringBitmap {
+        RoaringBitmap {
+            containers: Vec::new(),
+        }
+    }
+
+    pub fn from_sorted(articles: &[usize]) -> RoaringBitmap {
+        let mut containers = Vec::new();
+        let mut start = 0;
+        while start < articles.len() {
+            let key = articles[start] >> 16;
+            let end = start + articles[start..].partition_point(|&a| a >> 16 == key);
+            let values = articles[start..end]
+                .iter()
+                .map(|&a| (a & 0xFFFF) as u16)
+                .collect();
+            containers.push((key as u16, Container::from_sorted(values)));
+            start = end;
+        }
+        RoaringBitmap { containers }
+    }
+
+    pub fn cardinality(&self) -> usize {
+        self.containers.iter().map(|(_, c)| c.cardinality()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty()
+    }
+
+    pub fn contains(&self, article_number: usize) -> bool {
+        let key = (article_number >> 16) as u16;
+        match self.containers.binary_search_by_key(&key, |(k, _)| *k) {
+            Ok(i) => self.containers[i]
+                .1
+                .contains((article_number & 0xFFFF) as u16),
+            Err(_) => false,
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<usize> {
+        self.containers
+            .iter()
+            .flat_map(|(key, c)| {
+                c.values()
+                    .into_iter()
+                    .map(move |v| ((*key as usize) << 16) | v as usize)
+            })
+            .collect()
+    }
+
+    fn push_nonempty(&mut self, key: u16, container: Container) {
+        if !container.is_empty() {
+            self.containers.push((key, container))
+        }
+    }
+
+    pub fn and(&self, other: &RoaringBitmap) -> RoaringBitmap {
+        let mut result = RoaringBitmap::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.containers.len() && j < other.containers.len() {
+            let (key_a, a) = &self.containers[i];
+            let (key_b, b) = &other.containers[j];
+            if key_a < key_b {
+                i += 1;
+            } else if key_a > key_b {
+                j += 1;
+            } else {
+                result.push_nonempty(*key_a, a.and(b));
+                i += 1;
+                j += 1;
+            }
+        }
+        result
+    }
+
+    pub fn or(&self, other: &RoaringBitmap) -> RoaringBitmap {
+        let mut result = RoaringBitmap::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.containers.len() || j < other.containers.len() {
+            if j == other.containers.len()
+                || (i < self.containers.len() && self.containers[i].0 < other.containers[j].0)
+            {
+                result.containers.push(self.containers[i].clone());
+                i += 1;
+            } else if i == self.containers.len() || self.containers[i].0 > other.containers[j].0 {
+                result.containers.push(other.containers[j].clone());
+                j += 1;
+            } else {
+                let key = self.containers[i].0;
+                result.push_nonempty(key, self.containers[i].1.or(&other.containers[j].1));
+                i += 1;
+                j += 1;
+            }
+        }
+        result
+    }
+
+    // The articles in self that are not in other, i.e. "self & !other"
+    pub fn andnot(&self, other: &RoaringBitmap) -> RoaringBitmap {
+        let mut result = RoaringBitmap::new();
+        let mut j = 0;
+        for (key, a) in &self.containers {
+            while j < other.containers.len() && other.containers[j].0 < *key {
+                j += 1;
+            }
+            if j < other.containers.len() && other.containers[j].0 == *key {
+                result.push_nonempty(*key, a.andnot(&other.containers[j].1));
+            } else {
+                result.containers.push((*key, a.clone()));
+            }
+        }
+        result
+    }
+
+    // The complement within the articles 0..n_titles
+    pub fn not(&self, n_titles: usize) -> RoaringBitmap {
+        let mut result = RoaringBitmap::new();
+        if n_titles == 0 {
+            return result;
+        }
+        let mut j = 0;
+        for key in 0..=((n_titles - 1) >> 16) {
+            let len = min(1 << 16, n_titles - (key << 16));
+            while j < self.containers.len() && (self.containers[j].0 as usize) < key {
+                j += 1;
+            }
+            let container = if j < self.containers.len() && self.containers[j].0 as usize == key {
+                self.containers[j].1.not(len)
+            } else {
+                Container::Run(vec![(0, (len - 1) as u16)])
+            };
+            result.push_nonempty(key as u16, container);
+        }
+        result
+    }
+}
+
+impl Index<HashMap<String, RoaringBitmap>> {
+    pub fn index8_7(config: &Config) -> Result<Self, Box<dyn Error>> {
+        // Collect sorted article lists as in index8, and compress them afterwards
+        let mut article_lists: HashMap<String, Vec<usize>> = HashMap::new();
+
+        let articles_iter = read_and_clean_file_to_iter(config)?;
+        let mut article_titles: Vec<String> = Vec::new();
+
+        for (title, contents) in articles_iter {
+            if !title.is_empty() {
+                article_titles.push(title.to_string());
+                let article_number = article_titles.len() - 1;
+                for word in article_terms(&title, &contents) {
+                    let v = article_lists.entry(word.to_string()).or_default();
+                    if v.last() != Some(&article_number) {
+                        v.push(article_number)
+                    }
+                }
+            }
+        }
+
+        let database = article_lists
+            .into_iter()
+            .map(|(word, articles)| (word, RoaringBitmap::from_sorted(&articles)))
+            .collect();
+
+        Ok(Index {
+            database,
+            article_titles,
+        })
+    }
+
+    pub fn roaring_to_articlelist(&self, bitmap: RoaringBitmap) -> ArticleTitles {
+        bitmap
+            .to_vec()
+            .into_iter()
+            .map(|i| self.article_titles[i].clone())
+            .collect()
+    }
+
+    pub fn single_search(&self, word: &str) -> ArticleTitles {
+        match self.database.get(word) {
+            Some(bitmap) => self.roaring_to_articlelist(bitmap.clone()),
+            None => vec![],
+        }
+    }
+
+    pub fn boolean_search_roaring(&self, exp: &str) -> ArticleTitles {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => {
+                self.roaring_to_articlelist(self.evaluate_syntax_tree_roaring(node))
+            }
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn evaluate_syntax_tree_roaring(&self, node: AstNode) -> RoaringBitmap {
+        match node {
+            AstNode::Invert(child) => self
+                .evaluate_syntax_tree_roaring(*child)
+                .not(self.article_titles.len()),
+            AstNode::Binary(BinaryOp::And, left_child, right_child) => {
+                match (*left_child, *right_child) {
+                    // a & !b is a difference, so the complement of b is never built
+                    (left, AstNode::Invert(right)) | (AstNode::Invert(right), left) => self
+                        .evaluate_syntax_tree_roaring(left)
+                        .andnot(&self.evaluate_syntax_tree_roaring(*right)),
+                    (left, right) => self
+                        .evaluate_syntax_tree_roaring(left)
+                        .and(&self.evaluate_syntax_tree_roaring(right)),
+                }
+            }
+            AstNode::Binary(BinaryOp::Or, left_child, right_child) => self
+                .evaluate_syntax_tree_roaring(*left_child)
+                .or(&self.evaluate_syntax_tree_roaring(*right_child)),
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
+            AstNode::Name(word) => self.database.get(&word).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl Search for Index<HashMap<String, RoaringBitmap>> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch => self.single_search(&query.search_string),
+            SearchType::BooleanSearch(x) if x == "Roaring" => {
+                self.boolean_search_roaring(&query.search_string)
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::gen_query::gen_a_lot_of_runs_bool;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::{BTreeSet, HashSet};
+
+    fn setup_real() -> Index<HashMap<String, RoaringBitmap>> {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "8_7".to_string(),
+        ]);
+        Index::index8_7(&config).unwrap()
+    }
+
+    fn setup_test() -> Index<HashMap<String, RoaringBitmap>> {
+        let mut database: HashMap<String, RoaringBitmap> = HashMap::new();
+        database.insert("word1".to_string(), RoaringBitmap::from_sorted(&[0]));
+        database.insert(
+            "word2".to_string(),
+            RoaringBitmap::from_sorted(&[0, 1, 2, 3, 4, 5, 6, 7]),
+        );
+        database.insert(
+            "word3".to_string(),
+            RoaringBitmap::from_sorted(&[0, 2, 4, 6]),
+        );
+        database.insert("word4".to_string(), RoaringBitmap::from_sorted(&[1, 2, 3]));
+        let mut article_titles: Vec<String> = Vec::new();
+        for i in 0..100 {
+            article_titles.push(format!("article {}", i).to_string());
+        }
+        Index {
+            database,
+            article_titles,
+        }
+    }
+
+    fn search_match(index: &Index<HashMap<String, RoaringBitmap>>, query: &str, titles: Vec<&str>) {
+        dbg!(&query.to_string());
+        let index_result: HashSet<String> = HashSet::from_iter(index.boolean_search_roaring(query));
+        assert_eq!(
+            index_result,
+            HashSet::from_iter(titles.iter().map(|s| s.to_string()))
+        )
+    }
+
+    // Sparse, dense and run-heavy article sets spanning several containers
+    fn random_sets(rng: &mut StdRng, universe: usize) -> Vec<BTreeSet<usize>> {
+        let sparse: BTreeSet<usize> = (0..2000).map(|_| rng.gen_range(0..universe)).collect();
+        let dense: BTreeSet<usize> = (0..universe).filter(|_| rng.gen_bool(0.6)).collect();
+        let mut runs: BTreeSet<usize> = BTreeSet::new();
+        for _ in 0..20 {
+            let start = rng.gen_range(0..universe);
+            let len = rng.gen_range(1..10000);
+            runs.extend(start..min(start + len, universe));
+        }
+        vec![sparse, dense, runs, BTreeSet::new()]
+    }
+
+    fn to_roaring(set: &BTreeSet<usize>) -> RoaringBitmap {
+        RoaringBitmap::from_sorted(&set.iter().copied().collect::<Vec<usize>>())
+    }
+
+    #[test]
+    fn picks_container_by_density() {
+        let sparse = RoaringBitmap::from_sorted(&[1, 5, 9, 100]);
+        assert!(matches!(sparse.containers[0].1, Container::Array(_)));
+
+        let run = RoaringBitmap::from_sorted(&(0..5000).collect::<Vec<usize>>());
+        assert_eq!(run.containers[0].1, Container::Run(vec![(0, 4999)]));
+
+        let dense = RoaringBitmap::from_sorted(&(0..60000).step_by(3).collect::<Vec<usize>>());
+        assert!(matches!(dense.containers[0].1, Container::Bitmap(_)));
+
+        let two_chunks = RoaringBitmap::from_sorted(&[3, 65536 + 3]);
+        assert_eq!(
+            two_chunks.containers,
+            vec![
+                (0, Container::Array(vec![3])),
+                (1, Container::Array(vec![3]))
+            ]
+        );
+    }
+
+    #[test]
+    fn roundtrip_and_contains() {
+        let mut rng = StdRng::seed_from_u64(8008135);
+        for set in random_sets(&mut rng, 200000) {
+            let bitmap = to_roaring(&set);
+            assert_eq!(bitmap.to_vec(), set.iter().copied().collect::<Vec<usize>>());
+            assert_eq!(bitmap.cardinality(), set.len());
+            for a in (0..200000).step_by(997) {
+                assert_eq!(bitmap.contains(a), set.contains(&a));
+            }
+        }
+    }
+
+    #[test]
+    fn operations_match_reference_sets() {
+        let mut rng = StdRng::seed_from_u64(8008135);
+        let universe = 200000;
+        let sets = random_sets(&mut rng, universe);
+        for a in &sets {
+            for b in &sets {
+                let (ra, rb) = (to_roaring(a), to_roaring(b));
+                assert_eq!(
+                    ra.and(&rb).to_vec(),
+                    a.intersection(b).copied().collect::<Vec<usize>>()
+                );
+                assert_eq!(
+                    ra.or(&rb).to_vec(),
+                    a.union(b).copied().collect::<Vec<usize>>()
+                );
+                assert_eq!(
+                    ra.andnot(&rb).to_vec(),
+                    a.difference(b).copied().collect::<Vec<usize>>()
+                );
+            }
+            assert_eq!(
+                to_roaring(a).not(universe).to_vec(),
+                (0..universe)
+                    .filter(|x| !a.contains(x))
+                    .collect::<Vec<usize>>()
+            );
+        }
+    }
+
+    #[test]
+    fn not_respects_number_of_titles() {
+        let bitmap = RoaringBitmap::from_sorted(&[0, 2, 4]);
+        assert_eq!(bitmap.not(6).to_vec(), vec![1, 3, 5]);
+        assert_eq!(RoaringBitmap::new().not(3).to_vec(), vec![0, 1, 2]);
+        assert_eq!(
+            RoaringBitmap::from_sorted(&[0, 1, 2]).not(3).to_vec(),
+            vec![]
+        );
+        assert!(RoaringBitmap::new().not(0).is_empty());
+    }
+
+    #[test]
+    fn boolean_search_for_words_in_wiki100_kb() {
+        let index = setup_real();
+
+        search_match(
+            &index,
+            "the | autism",
+            vec!["Anarchism", "Autism", "A", "Albedo"],
+        );
+        search_match(&index, "autism", vec!["Autism"]); // A word that should only be in one article
+        search_match(&index, "bi-hemispherical", vec!["Albedo"]); // Check for no splitting of 'bi-hemispherical'
+    }
+
+    #[test]
+    fn find_a_word() {
+        let index = setup_test();
+        search_match(&index, "  word1 ", vec!["article 0"]);
+    }
+
+    #[test]
+    fn ands_two_words() {
+        let index = setup_test();
+        search_match(&index, "word1 & word3", vec!["article 0"]);
+    }
+
+    #[test]
+    fn or_and_and() {
+        let index = setup_test();
+        search_match(
+            &index,
+            "word1 | (word3 & word4)",
+            vec!["article 0", "article 2"],
+        );
+    }
+
+    #[test]
+    fn and_not_is_a_difference() {
+        let index = setup_test();
+        search_match(
+            &index,
+            "word2 & !word3",
+            vec!["article 1", "article 3", "article 5", "article 7"],
+        );
+        search_match(&index, "!word3 & word4", vec!["article 1", "article 3"]);
+    }
+
+    #[test]
+    fn word_not_in_database() {
+        let index = setup_test();
+        search_match(&index, "nowhere", vec![]);
+        search_match(&index, "word1 & nowhere", vec![]);
+    }
+
+    #[test]
+    fn the_empty_query() {
+        let index = setup_test();
+        search_match(&index, "", vec![]);
+    }
+
+    #[test]
+    fn erroneous_query_finds_nothing() {
+        let index = setup_test();
+        search_match(&index, "word1((", vec![]);
+    }
+
+    #[test]
+    fn boolean_search_with_iversions() {
+        let index = setup_real();
+
+        search_match(&index, "!the", vec![]);
+        search_match(&index, "!letter", vec!["Anarchism", "Albedo", "Autism"]);
+        search_match(&index, "letter & !the", vec![]);
+        search_match(&index, "!letter & political", vec!["Anarchism"]);
+        search_match(&index, "!letter & !political", vec!["Albedo", "Autism"]);
+        search_match(&index, "letter or !the", vec!["A"]);
+        search_match(
+            &index,
+            "!(letter and political)",
+            vec!["A", "Anarchism", "Albedo", "Autism"],
+        );
+    }
+
+    #[test]
+    fn index8_7_and_index8_get_the_same_results() {
+        let file = "data/WestburyLab.wikicorp.201004_100KB.txt".to_string();
+        let config = Config {
+            file_path: file.clone(),
+            indexno: "8_7".to_string(),
+        };
+        let index8 = Index::index8(&config).unwrap();
+        let index8_7 = Index::index8_7(&config).unwrap();
+
+        for depth_vec in gen_a_lot_of_runs_bool(file, 10) {
+            for query in &depth_vec {
+                assert_eq!(
+                    index8.boolean_search_naive(query),
+                    index8_7.boolean_search_roaring(query)
+                );
+            }
+        }
+    }
+}
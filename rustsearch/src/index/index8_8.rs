@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::index7_1::{BitsetProgram, Leaf};
+use super::ArticleTitles;
+
+impl Index<HashMap<String, Vec<usize>>> {
+    // Like the Bitvecs search, but the article lists are only turned into bits one chunk at a time,
+    // while the whole tree is evaluated by the chunked kernel from index7_1
+    pub fn boolean_search_simd(&self, exp: &str) -> ArticleTitles {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => {
+                self.bitvec_to_articlelist(self.evaluate_syntax_tree_simd(&node))
+            }
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn evaluate_syntax_tree_simd(&self, node: &AstNode) -> Vec<usize> {
+        let program = BitsetProgram::compile(node, &|word: &str| match self.database.get(word) {
+            Some(articles) => Leaf::Articles(articles),
+            None => Leaf::Empty,
+        });
+        program
+            .evaluate(self.article_titles.len().div_ceil(64))
+            .into_iter()
+            .map(|w| w as usize)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::Config;
+    use crate::index::gen_query::gen_a_lot_of_runs_bool;
+    use std::collections::HashSet;
+
+    fn setup_real() -> Index<HashMap<String, Vec<usize>>> {
+        let config = Config::build(&[
+            "".to_string(),
+            "data/WestburyLab.wikicorp.201004_100KB.txt".to_string(),
+            "8_8".to_string(),
+        ]);
+        Index::index8(&config).unwrap()
+    }
+
+    fn setup_test() -> Index<HashMap<String, Vec<usize>>> {
+        let mut database: HashMap<String, Vec<usize>> = HashMap::new();
+        database.insert("word1".to_string(), vec![0]);
+        database.insert("word2".to_string(), vec![0, 1, 2, 3, 4, 5, 6, 7]);
+        database.insert("word3".to_string(), vec![0, 2, 4, 6]);
+        database.insert("word4".to_string(), vec![1, 2, 3]);
+        let mut article_titles: Vec<String> = Vec::new();
+        for i in 0..100 {
+            article_titles.push(format!("article {}", i).to_string());
+        }
+        Index {
+            database,
+            article_titles,
+        }
+    }
+
+    fn search_match(index: &Index<HashMap<String, Vec<usize>>>, query: &str, titles: Vec<&str>) {
+        dbg!(&query.to_string());
+        let index_result: HashSet<String> = HashSet::from_iter(index.boolean_search_simd(query));
+        assert_eq!(
+            index_result,
+            HashSet::from_iter(titles.iter().map(|s| s.to_string()))
+        )
+    }
+
+    #[test]
+    fn boolean_search_for_words_in_wiki100_kb() {
+        let index = setup_real();
+
+        search_match(
+            &index,
+            "the | autism",
+            vec!["Anarchism", "Autism", "A", "Albedo"],
+        );
+        search_match(&index, "autism", vec!["Autism"]); // A word that should only be in one article
+        search_match(&index, "bi-hemispherical", vec!["Albedo"]); // Check for no splitting of 'bi-hemispherical'
+    }
+
+    #[test]
+    fn find_a_word() {
+        let index = setup_test();
+        search_match(&index, "  word1 ", vec!["article 0"]);
+    }
+
+    #[test]
+    fn ands_two_words() {
+        let index = setup_test();
+        search_match(&index, "word1 & word3", vec!["article 0"]);
+    }
+
+    #[test]
+    fn or_and_and() {
+        let index = setup_test();
+        search_match(
+            &index,
+            "word1 | (word3 & word4)",
+            vec!["article 0", "article 2"],
+        );
+    }
+
+    #[test]
+    fn word_not_in_database() {
+        let index = setup_test();
+        search_match(&index, "nowhere", vec![]);
+        search_match(&index, "word1 & nowhere", vec![]);
+    }
+
+    #[test]
+    fn the_empty_query() {
+        let index = setup_test();
+        search_match(&index, "", vec![]);
+    }
+
+    #[test]
+    fn erroneous_query_finds_nothing() {
+        let index = setup_test();
+        search_match(&index, "word1((", vec![]);
+    }
+
+    #[test]
+    fn boolean_search_with_iversions() {
+        let index = setup_real();
+
+        search_match(&index, "!the", vec![]);
+        search_match(&index, "!letter", vec!["Anarchism", "Albedo", "Autism"]);
+        search_match(&index, "!letter & !political", vec!["Albedo", "Autism"]);
+        search_match(&index, "letter or !the", vec!["A"]);
+        search_match(
+            &index,
+            "!(letter and political)",
+            vec!["A", "Anarchism", "Albedo", "Autism"],
+        );
+    }
+
+    #[test]
+    fn empty_index() {
+        let index: Index<HashMap<String, Vec<usize>>> = Index {
+            database: HashMap::new(),
+            article_titles: Vec::new(),
+        };
+        assert_eq!(index.boolean_search_simd("word1"), Vec::<String>::new());
+        assert_eq!(index.boolean_search_simd("!word1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn simd_and_naive_get_the_same_results() {
+        let file = "data/WestburyLab.wikicorp.201004_100KB.txt".to_string();
+        let index8 = Index::index8(&Config {
+            file_path: file.clone(),
+            indexno: "8_8".to_string(),
+        })
+        .unwrap();
+
+        for depth_vec in gen_a_lot_of_runs_bool(file, 10) {
+            for query in &depth_vec {
+                assert_eq!(
+                    index8.boolean_search_naive(query),
+                    index8.boolean_search_simd(query)
+                );
+            }
+        }
+    }
+}
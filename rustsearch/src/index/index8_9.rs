@@ -0,0 +1,682 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+
+use crate::helpers::*;
+use crate::index::Index;
+use crate::parsing::*;
+
+use super::ranking::{Bm25, Scorer, TfIdf};
+use super::*;
+
+// Postings that keep how often the word occurs in each article, so the articles can be ranked.
+// Articles are scored by a Scorer, BM25 unless another one is set, and the postings know the
+// highest score the word gives any article, overall and in each block of BLOCK_SIZE postings.
+// The top-k search uses these bounds to skip the articles that can't make it into the top k
+// (block-max WAND).
+
+pub const BLOCK_SIZE: usize = 64;
+
+// The bounds are made a little larger than the scores, so adding them up in another order than
+// the scores are added can never make a bound smaller than a real score
+const BOUND_SLACK: f64 = 1e-9;
+
+pub struct Block {
+    pub last_article: usize,
+    pub max_score: f64,
+}
+
+pub struct FreqPostings {
+    pub articles: Vec<usize>,
+    pub freqs: Vec<u32>,
+    pub blocks: Vec<Block>,
+    pub max_score: f64,
+}
+
+pub struct FreqDatabase {
+    postings: HashMap<String, FreqPostings>,
+    article_lens: Vec<u32>,
+    avg_article_len: f64,
+    // The lengths of the TF-IDF vectors of the articles
+    article_norms: Vec<f64>,
+    scorer: Box<dyn Scorer>,
+}
+
+// An article and its score, ordered so the better hit is greater: a higher score, or the same
+// score and an earlier article
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub article: usize,
+    pub score: f64,
+}
+
+impl Eq for Hit {}
+
+impl PartialOrd for Hit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Hit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(other.article.cmp(&self.article))
+    }
+}
+
+// The k best hits seen so far
+struct TopK {
+    k: usize,
+    heap: BinaryHeap<Reverse<Hit>>,
+}
+
+impl TopK {
+    fn new(k: usize) -> Self {
+        TopK {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    // The score an article must beat to get in. Articles are offered in increasing order, so one
+    // with the same score as the worst hit never gets in.
+    fn threshold(&self) -> f64 {
+        match self.heap.peek() {
+            Some(Reverse(worst)) if self.heap.len() == self.k => worst.score,
+            _ => f64::NEG_INFINITY,
+        }
+    }
+
+    fn offer(&mut self, hit: Hit) {
+        self.heap.push(Reverse(hit));
+        if self.heap.len() > self.k {
+            self.heap.pop();
+        }
+    }
+
+    fn into_sorted_vec(self) -> Vec<Hit> {
+        // Sorting the Reverse wrappers ascending puts the best hit first
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(hit)| hit)
+            .collect()
+    }
+}
+
+struct Cursor<'a> {
+    postings: &'a FreqPostings,
+    word_weight: f64,
+    query_weight: f64,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn max_score(&self) -> f64 {
+        self.query_weight * self.postings.max_score
+    }
+
+    fn article(&self) -> usize {
+        match self.postings.articles.get(self.pos) {
+            Some(&article) => article,
+            None => usize::MAX,
+        }
+    }
+
+    // Moves to the first posting at or after the article
+    fn advance_to(&mut self, article: usize) {
+        let rest = &self.postings.articles[self.pos..];
+        self.pos += rest.partition_point(|&a| a < article);
+    }
+
+    // The bound and the last article of the block holding the article, if there is one
+    fn block_at(&self, article: usize) -> Option<&'a Block> {
+        let first = self.pos / BLOCK_SIZE;
+        let blocks = &self.postings.blocks[first..];
+        blocks.get(blocks.partition_point(|b| b.last_article < article))
+    }
+}
+
+impl Index<FreqDatabase> {
+    pub fn index8_9(config: &Config) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_articles(read_and_clean_file_to_iter(config)?))
+    }
+
+    pub fn from_articles(articles: Vec<(String, Vec<String>)>) -> Self {
+        let mut postings: HashMap<String, FreqPostings> = HashMap::new();
+        let mut article_titles: Vec<String> = Vec::new();
+        let mut article_lens: Vec<u32> = Vec::new();
+
+        for (title, contents) in articles {
+            if !title.is_empty() {
+                let article = article_titles.len();
+                // The length BM25 normalizes by is that of the body
+                article_titles.push(title.clone());
+                article_lens.push(contents.len() as u32);
+                for word in article_terms(&title, &contents) {
+                    let p = postings.entry(word).or_insert_with(|| FreqPostings {
+                        articles: Vec::new(),
+                        freqs: Vec::new(),
+                        blocks: Vec::new(),
+                        max_score: 0.0,
+                    });
+                    if p.articles.last() == Some(&article) {
+                        *p.freqs.last_mut().unwrap() += 1;
+                    } else {
+                        p.articles.push(article);
+                        p.freqs.push(1);
+                    }
+                }
+            }
+        }
+
+        let n_articles = article_titles.len();
+        let mut article_norms = vec![0.0; n_articles];
+        for p in postings.values() {
+            let idf = TfIdf::idf(n_articles, p.articles.len());
+            for (&a, &f) in p.articles.iter().zip(&p.freqs) {
+                article_norms[a] += (TfIdf::tf(f) * idf).powi(2);
+            }
+        }
+        for norm in article_norms.iter_mut() {
+            *norm = norm.sqrt();
+        }
+
+        let total_len: u64 = article_lens.iter().map(|&l| l as u64).sum();
+        let mut database = FreqDatabase {
+            postings,
+            avg_article_len: total_len as f64 / n_articles.max(1) as f64,
+            article_lens,
+            article_norms,
+            scorer: Box::new(Bm25::default()),
+        };
+        database.compute_bounds();
+
+        Index {
+            database,
+            article_titles,
+        }
+    }
+
+    // Ranks the articles with another scorer from now on
+    pub fn set_scorer(&mut self, scorer: Box<dyn Scorer>) {
+        self.database.scorer = scorer;
+        self.database.compute_bounds();
+    }
+
+    // The words of a boolean query that count towards the score, i.e. every word that isn't
+    // negated, each once. Top-k searches rank the articles matching the query by these words, so
+    // an article that matches without any of them, like every article for "!a", has no score and
+    // is left out.
+    pub fn query_words(node: &AstNode) -> Vec<String> {
+        fn collect(node: &AstNode, negated: bool, words: &mut Vec<String>) {
+            match node {
+                AstNode::Invert(child) => collect(child, !negated, words),
+                AstNode::Binary(_, left, right) => {
+                    collect(left, negated, words);
+                    collect(right, negated, words);
+                }
+                AstNode::Name(word) => {
+                    if !negated && !words.contains(word) {
+                        words.push(word.to_string())
+                    }
+                }
+            }
+        }
+        let mut words = Vec::new();
+        collect(node, false, &mut words);
+        words
+    }
+
+    fn cursors(&self, words: &[String]) -> Vec<Cursor<'_>> {
+        let db = &self.database;
+        let mut cursors: Vec<Cursor> = words
+            .iter()
+            .filter_map(|word| db.postings.get(word))
+            .map(|postings| Cursor {
+                postings,
+                word_weight: db.scorer.word_weight(db, postings.articles.len()),
+                query_weight: 1.0,
+                pos: 0,
+            })
+            .collect();
+        let word_weights: Vec<f64> = cursors.iter().map(|c| c.word_weight).collect();
+        for (cursor, w) in cursors
+            .iter_mut()
+            .zip(db.scorer.query_weights(&word_weights))
+        {
+            cursor.query_weight = w;
+        }
+        cursors
+    }
+
+    // Whether the article matches the boolean query
+    pub fn matches(&self, node: &AstNode, article: usize) -> bool {
+        match node {
+            AstNode::Invert(child) => !self.matches(child, article),
+            AstNode::Binary(BinaryOp::And, left, right) => {
+                self.matches(left, article) && self.matches(right, article)
+            }
+            AstNode::Binary(BinaryOp::Or, left, right) => {
+                self.matches(left, article) || self.matches(right, article)
+            }
+            AstNode::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
+            AstNode::Name(word) => match self.database.postings.get(word) {
+                Some(p) => p.articles.binary_search(&article).is_ok(),
+                None => false,
+            },
+        }
+    }
+
+    pub fn top_k_exhaustive(&self, words: &[String], k: usize) -> Vec<Hit> {
+        self.top_k_exhaustive_filtered(words, k, &|_| true)
+    }
+
+    // Scores every accepted article containing one of the words. The scores of each article are
+    // added up in the order of the words, like in top_k_wand, so both give the exact same scores.
+    pub fn top_k_exhaustive_filtered(
+        &self,
+        words: &[String],
+        k: usize,
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Vec<Hit> {
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for cursor in self.cursors(words) {
+            let postings = cursor.postings;
+            for (&article, &freq) in postings.articles.iter().zip(&postings.freqs) {
+                *scores.entry(article).or_insert(0.0) +=
+                    self.database.score(&cursor, article, freq);
+            }
+        }
+        let mut top = TopK::new(k);
+        if k > 0 {
+            let mut articles: Vec<usize> = scores.keys().copied().filter(|&a| accept(a)).collect();
+            articles.sort_unstable();
+            for article in articles {
+                top.offer(Hit {
+                    article,
+                    score: scores[&article],
+                });
+            }
+        }
+        top.into_sorted_vec()
+    }
+
+    pub fn top_k_wand(&self, words: &[String], k: usize, block_max: bool) -> Vec<Hit> {
+        self.top_k_wand_filtered(words, k, block_max, &|_| true)
+    }
+
+    // The k best accepted articles for the words, best first. Without block_max it is plain WAND,
+    // which only uses the bound of each word over all its postings. Articles that are not
+    // accepted are skipped before they are scored, and never raise the threshold.
+    pub fn top_k_wand_filtered(
+        &self,
+        words: &[String],
+        k: usize,
+        block_max: bool,
+        accept: &dyn Fn(usize) -> bool,
+    ) -> Vec<Hit> {
+        let mut cursors = self.cursors(words);
+        let mut top = TopK::new(k);
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut order: Vec<usize> = (0..cursors.len()).collect();
+
+        loop {
+            order.sort_by_key(|&c| cursors[c].article());
+            let threshold = top.threshold();
+
+            // The pivot is the first article where the words up to it could beat the threshold
+            let mut bound = 0.0;
+            let mut pivot = None;
+            for (p, &c) in order.iter().enumerate() {
+                if cursors[c].article() == usize::MAX {
+                    break;
+                }
+                bound += cursors[c].max_score();
+                if bound > threshold {
+                    pivot = Some(p);
+                    break;
+                }
+            }
+            let mut p = match pivot {
+                Some(p) => p,
+                None => break,
+            };
+            let pivot_article = cursors[order[p]].article();
+            while p + 1 < order.len() && cursors[order[p + 1]].article() == pivot_article {
+                p += 1;
+            }
+
+            if block_max {
+                let mut block_bound = 0.0;
+                let mut next = match order.get(p + 1) {
+                    Some(&c) => cursors[c].article(),
+                    None => usize::MAX,
+                };
+                for &c in &order[..=p] {
+                    if let Some(block) = cursors[c].block_at(pivot_article) {
+                        block_bound += cursors[c].query_weight * block.max_score;
+                        next = next.min(block.last_article + 1);
+                    }
+                }
+                // No article before next can beat the threshold with the words in these blocks
+                if block_bound <= threshold {
+                    for &c in &order[..=p] {
+                        cursors[c].advance_to(next);
+                    }
+                    continue;
+                }
+            }
+
+            if cursors[order[0]].article() == pivot_article {
+                let accepted = accept(pivot_article);
+                let mut score = 0.0;
+                for cursor in cursors.iter_mut() {
+                    if cursor.article() == pivot_article {
+                        if accepted {
+                            let freq = cursor.postings.freqs[cursor.pos];
+                            score += self.database.score(cursor, pivot_article, freq);
+                        }
+                        cursor.pos += 1;
+                    }
+                }
+                if accepted {
+                    top.offer(Hit {
+                        article: pivot_article,
+                        score,
+                    });
+                }
+            } else {
+                for &c in &order[..p] {
+                    cursors[c].advance_to(pivot_article);
+                }
+            }
+        }
+        top.into_sorted_vec()
+    }
+
+    pub fn ranked_search(&self, exp: &str, k: usize) -> ArticleTitles {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => self
+                .top_k_wand_filtered(&Self::query_words(&node), k, true, &|article| {
+                    self.matches(&node, article)
+                })
+                .into_iter()
+                .map(|hit| self.article_titles[hit.article].to_string())
+                .collect(),
+            _ => Vec::new(), // Either an error or the expression has no nodes
+        }
+    }
+
+    pub fn single_search(&self, query: &str) -> ArticleTitles {
+        match self.database.postings.get(query) {
+            Some(postings) => postings
+                .articles
+                .iter()
+                .map(|&a| self.article_titles[a].to_string())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl FreqDatabase {
+    pub fn n_articles(&self) -> usize {
+        self.article_lens.len()
+    }
+
+    pub fn article_len(&self, article: usize) -> u32 {
+        self.article_lens[article]
+    }
+
+    pub fn avg_article_len(&self) -> f64 {
+        self.avg_article_len
+    }
+
+    pub fn article_norm(&self, article: usize) -> f64 {
+        self.article_norms[article]
+    }
+
+    fn score(&self, cursor: &Cursor, article: usize, freq: u32) -> f64 {
+        cursor.query_weight
+            * self
+                .scorer
+                .article_score(self, article, freq, cursor.word_weight)
+    }
+
+    // Bounds the scores of the current scorer
+    fn compute_bounds(&mut self) {
+        let mut postings = std::mem::take(&mut self.postings);
+        for p in postings.values_mut() {
+            let word_weight = self.scorer.word_weight(self, p.articles.len());
+            p.blocks = p
+                .articles
+                .chunks(BLOCK_SIZE)
+                .zip(p.freqs.chunks(BLOCK_SIZE))
+                .map(|(articles, freqs)| Block {
+                    last_article: *articles.last().unwrap(),
+                    max_score: articles
+                        .iter()
+                        .zip(freqs)
+                        .map(|(&a, &f)| self.scorer.article_score(self, a, f, word_weight))
+                        .fold(0.0, f64::max)
+                        * (1.0 + BOUND_SLACK),
+                })
+                .collect();
+            p.max_score = p.blocks.iter().map(|b| b.max_score).fold(0.0, f64::max);
+        }
+        self.postings = postings;
+    }
+}
+
+// The number of articles a top-k search from the Search trait returns
+pub const DEFAULT_K: usize = 10;
+
+impl Search for Index<FreqDatabase> {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        match &query.search_type {
+            SearchType::SingleWordSearch => self.single_search(&query.search_string),
+            SearchType::BooleanSearch(x) if x == "TopK" => {
+                self.ranked_search(&query.search_string, DEFAULT_K)
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::gen_query::gen_a_lot_of_runs_bool;
+
+    const FILE: &str = "data/WestburyLab.wikicorp.201004_100KB.txt";
+
+    fn setup_real() -> Index<FreqDatabase> {
+        Index::index8_9(&Config {
+            file_path: FILE.to_string(),
+            indexno: "8_9".to_string(),
+        })
+        .unwrap()
+    }
+
+    fn words(exp: &str) -> Vec<String> {
+        match Expr::from_string(exp) {
+            Ok(Expr(ExprData::HasNodes(node))) => Index::query_words(&node),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn query_words_skip_negated_words() {
+        let name = |w: &str| Box::new(AstNode::Name(w.to_string()));
+        let not = |node: Box<AstNode>| Box::new(AstNode::Invert(node));
+        // ! ! a or (a or ! (b and ! c))
+        let node = AstNode::Binary(
+            BinaryOp::Or,
+            not(not(name("a"))),
+            Box::new(AstNode::Binary(
+                BinaryOp::Or,
+                name("a"),
+                not(Box::new(AstNode::Binary(
+                    BinaryOp::And,
+                    name("b"),
+                    not(name("c")),
+                ))),
+            )),
+        );
+        assert_eq!(Index::query_words(&node), vec!["a", "c"]);
+        assert_eq!(words("a and (b or ! c)"), vec!["a", "b"]);
+        assert_eq!(words("! a"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn hits_order_by_score_then_article() {
+        let mut hits = vec![
+            Hit {
+                article: 3,
+                score: 1.0,
+            },
+            Hit {
+                article: 1,
+                score: 2.0,
+            },
+            Hit {
+                article: 0,
+                score: 1.0,
+            },
+        ];
+        hits.sort_by(|a, b| b.cmp(a));
+        let articles: Vec<usize> = hits.iter().map(|h| h.article).collect();
+        assert_eq!(articles, vec![1, 0, 3]);
+    }
+
+    #[test]
+    fn frequencies_and_bounds_are_kept() {
+        let index = setup_real();
+        for postings in index.database.postings.values() {
+            assert_eq!(postings.articles.len(), postings.freqs.len());
+            assert!(postings.articles.windows(2).all(|w| w[0] < w[1]));
+            assert_eq!(
+                postings.blocks.len(),
+                (postings.articles.len() - 1) / BLOCK_SIZE + 1
+            );
+            let db = &index.database;
+            let word_weight = db.scorer.word_weight(db, postings.articles.len());
+            for (i, (&a, &f)) in postings.articles.iter().zip(&postings.freqs).enumerate() {
+                let score = db.scorer.article_score(db, a, f, word_weight);
+                assert!(score <= postings.blocks[i / BLOCK_SIZE].max_score);
+                assert!(score <= postings.max_score);
+            }
+        }
+        let the = &index.database.postings["the"];
+        assert!(the.freqs.iter().any(|&f| f > 1));
+    }
+
+    #[test]
+    fn wand_gives_the_exhaustive_top_k() {
+        let mut index = setup_real();
+        let queries = gen_a_lot_of_runs_bool(FILE.to_string(), 10);
+        let scorers: Vec<Box<dyn Scorer>> = vec![Box::new(Bm25::default()), Box::new(TfIdf)];
+        for scorer in scorers {
+            index.set_scorer(scorer);
+            for q in queries.iter().flatten() {
+                let words = words(q);
+                for k in [1, 3, 10, 1000] {
+                    let expected = index.top_k_exhaustive(&words, k);
+                    assert_eq!(index.top_k_wand(&words, k, false), expected, "{}", q);
+                    assert_eq!(index.top_k_wand(&words, k, true), expected, "{}", q);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wand_handles_common_words_and_ties() {
+        let index = setup_real();
+        // Common words have many blocks, and articles without "icantbefound" tie a lot
+        let queries = [
+            "the or of",
+            "the or and or in or a",
+            "is or icantbefound",
+            "the",
+        ];
+        for q in queries {
+            let words = words(q);
+            for k in [0, 1, 2, 5, 50] {
+                let expected = index.top_k_exhaustive(&words, k);
+                assert!(expected.len() <= k);
+                assert_eq!(index.top_k_wand(&words, k, true), expected, "{}", q);
+                assert_eq!(index.top_k_wand(&words, k, false), expected, "{}", q);
+            }
+        }
+    }
+
+    #[test]
+    fn ranked_search_keeps_to_the_boolean_query() {
+        let index = setup_real();
+        let index8 = Index::index8(&Config {
+            file_path: FILE.to_string(),
+            indexno: "8".to_string(),
+        })
+        .unwrap();
+        let mut queries = vec![
+            "the & autism".to_string(),
+            "the & !autism".to_string(),
+            "(anarchism | albedo) & !(light & political)".to_string(),
+            "!the".to_string(),
+        ];
+        queries.extend(gen_a_lot_of_runs_bool(FILE.to_string(), 5).concat());
+        for q in queries {
+            let node = match Expr::from_string(&q) {
+                Ok(Expr(ExprData::HasNodes(node))) => node,
+                _ => continue, // Some generated queries don't parse, like "! ! a"
+            };
+            let boolean = index8.boolean_search_naive(&q);
+            let ranked = index.ranked_search(&q, 1000);
+            assert!(ranked.iter().all(|t| boolean.contains(t)), "{}", q);
+            let expected = index.top_k_exhaustive_filtered(&words(&q), 3, &|article| {
+                index8
+                    .evaluate_syntax_tree_naive(node.clone())
+                    .contains(&article)
+            });
+            assert_eq!(
+                index.top_k_wand_filtered(&words(&q), 3, true, &|a| index.matches(&node, a)),
+                expected,
+                "{}",
+                q
+            );
+        }
+        assert_eq!(
+            index.ranked_search("the & autism", 10),
+            vec!["Autism".to_string()]
+        );
+        assert!(index.ranked_search("!the", 10).is_empty());
+    }
+
+    #[test]
+    fn ranked_search_returns_the_best_titles_first() {
+        let index = setup_real();
+        let query = Query {
+            search_string: "anarchism or anarchist".to_string(),
+            search_type: SearchType::BooleanSearch("TopK".to_string()),
+        };
+        let titles = index.search(&query);
+        let expected: Vec<String> = index
+            .top_k_exhaustive(&words(&query.search_string), DEFAULT_K)
+            .into_iter()
+            .map(|hit| index.article_titles[hit.article].to_string())
+            .collect();
+        assert_eq!(titles, expected);
+        assert_eq!(titles[0], "Anarchism");
+        assert!(index.ranked_search("icantbefound", 5).is_empty());
+        assert_eq!(index.ranked_search("Title:Autism", 5), vec!["Autism"]);
+        assert_eq!(index.ranked_search("title:a & !a", 5), Vec::<String>::new());
+        assert!(index.ranked_search("", 5).is_empty());
+    }
+}
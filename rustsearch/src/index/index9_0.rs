@@ -5,6 +5,7 @@ use crate::index::Index;
 
 use super::*;
 
+#[derive(Clone)]
 pub struct TrieNodeLin {
     pub children_vec: Vec<(char, TrieNodeLin)>,
     pub article_vec: Option<Vec<usize>>,
@@ -24,11 +25,18 @@ impl TrieNodeLin {
     }
 }
 
+#[derive(Clone)]
 pub struct TrieLin {
     pub root: TrieNodeLin,
     pub n_titles: usize,
 }
 
+impl Default for TrieLin {
+    fn default() -> Self {
+        TrieLin::new()
+    }
+}
+
 impl TrieLin {
     pub fn new() -> TrieLin {
         TrieLin {
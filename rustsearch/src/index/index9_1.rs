@@ -6,6 +6,7 @@ use crate::index::Index;
 
 use super::*;
 
+#[derive(Clone)]
 pub struct TrieNode {
     pub children_map: HashMap<char, TrieNode>,
     pub article_vec: Option<Vec<usize>>,
@@ -24,11 +25,18 @@ impl TrieNode {
     }
 }
 
+#[derive(Clone)]
 pub struct Trie {
     pub root: TrieNode,
     pub n_titles: usize,
 }
 
+impl Default for Trie {
+    fn default() -> Self {
+        Trie::new()
+    }
+}
+
 impl Trie {
     pub fn new() -> Trie {
         Trie {
@@ -69,7 +77,7 @@ impl Trie {
             };
         }
         // At the end of the string, the last current node is final
-        self.articlevec_to_bitvec(current.article_vec.as_ref().unwrap())
+        self.articlevec_to_bitvec(current.article_vec.as_ref().unwrap_or(&Vec::new()))
     }
 
     pub fn find_single(&self, string_val: &String) -> Vec<usize> {
@@ -82,7 +90,7 @@ impl Trie {
             };
         }
         // At the end of the string, the last current node is final
-        self.articlevec_to_bitvec(current.article_vec.as_ref().unwrap())
+        self.articlevec_to_bitvec(current.article_vec.as_ref().unwrap_or(&Vec::new()))
     }
 
     fn get_subtree_match(&self, node: &TrieNode) -> Vec<usize> {
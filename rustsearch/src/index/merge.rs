@@ -0,0 +1,50 @@
+// Merges of sorted article lists, for the boolean searches of the indices that don't keep their
+// postings as the Vec<usize> of index8
+
+pub fn and(left: impl Iterator<Item = usize>, right: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut result: Vec<usize> = Vec::new();
+    let mut right = right.peekable();
+    for l in left {
+        while right.next_if(|&r| r < l).is_some() {}
+        match right.peek() {
+            Some(&r) if r == l => result.push(l),
+            Some(_) => {}
+            None => break,
+        }
+    }
+    result
+}
+
+pub fn or(left: impl Iterator<Item = usize>, right: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut result: Vec<usize> = Vec::new();
+    let mut left = left.peekable();
+    let mut right = right.peekable();
+    loop {
+        let next = match (left.peek(), right.peek()) {
+            (Some(&l), Some(&r)) if l < r => left.next(),
+            (Some(&l), Some(&r)) if l > r => right.next(),
+            (Some(_), Some(_)) => {
+                right.next();
+                left.next()
+            }
+            (Some(_), None) => left.next(),
+            (None, Some(_)) => right.next(),
+            (None, None) => break,
+        };
+        result.push(next.unwrap());
+    }
+    result
+}
+
+pub fn invert(child: Vec<usize>, n_titles: usize) -> Vec<usize> {
+    let mut result: Vec<usize> = Vec::new();
+    let mut p = 0;
+    for i in 0..n_titles {
+        if p < child.len() && child[p] == i {
+            p += 1;
+        } else {
+            result.push(i);
+        }
+    }
+    result
+}
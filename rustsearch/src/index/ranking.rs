@@ -0,0 +1,215 @@
+use crate::index::index8_9::FreqDatabase;
+
+// Rankers for the term-frequency postings of index8_9. The score of an article is the sum, over
+// the query words it contains, of the weight of the word in the query times the score the word
+// gives the article. That keeps the scores a sum of one part per word, which the top-k search
+// bounds one word at a time.
+pub trait Scorer: Send + Sync {
+    // A weight for the word as a whole, computed once from the number of articles it occurs in
+    fn word_weight(&self, stats: &FreqDatabase, n_containing: usize) -> f64;
+
+    // The score the word gives an article it occurs freq times in
+    fn article_score(
+        &self,
+        stats: &FreqDatabase,
+        article: usize,
+        freq: u32,
+        word_weight: f64,
+    ) -> f64;
+
+    // How much each word of the query counts, from the word weights of the query words.
+    // Every word counts once, unless the scorer normalizes the query.
+    fn query_weights(&self, word_weights: &[f64]) -> Vec<f64> {
+        vec![1.0; word_weights.len()]
+    }
+}
+
+// Okapi BM25
+pub struct Bm25 {
+    pub k1: f64,
+    pub b: f64,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Bm25 { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Scorer for Bm25 {
+    fn word_weight(&self, stats: &FreqDatabase, n_containing: usize) -> f64 {
+        let n = stats.n_articles() as f64;
+        let df = n_containing as f64;
+        (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    fn article_score(
+        &self,
+        stats: &FreqDatabase,
+        article: usize,
+        freq: u32,
+        word_weight: f64,
+    ) -> f64 {
+        let tf = freq as f64;
+        let len_norm =
+            1.0 - self.b + self.b * stats.article_len(article) as f64 / stats.avg_article_len();
+        word_weight * tf * (self.k1 + 1.0) / (tf + self.k1 * len_norm)
+    }
+}
+
+// The cosine of the angle between the TF-IDF vectors of the query and the article. A word weighs
+// (1 + ln tf) * ln(N / df) in an article, and ln(N / df) in the query. The lengths of the article
+// vectors are computed when the index is built.
+pub struct TfIdf;
+
+impl TfIdf {
+    pub fn tf(freq: u32) -> f64 {
+        1.0 + (freq as f64).ln()
+    }
+
+    pub fn idf(n_articles: usize, n_containing: usize) -> f64 {
+        (n_articles as f64 / n_containing as f64).ln()
+    }
+}
+
+impl Scorer for TfIdf {
+    fn word_weight(&self, stats: &FreqDatabase, n_containing: usize) -> f64 {
+        TfIdf::idf(stats.n_articles(), n_containing)
+    }
+
+    fn article_score(
+        &self,
+        stats: &FreqDatabase,
+        article: usize,
+        freq: u32,
+        word_weight: f64,
+    ) -> f64 {
+        let norm = stats.article_norm(article);
+        // A norm of 0 means every word of the article occurs in every article
+        match norm == 0.0 {
+            true => 0.0,
+            false => TfIdf::tf(freq) * word_weight / norm,
+        }
+    }
+
+    fn query_weights(&self, word_weights: &[f64]) -> Vec<f64> {
+        let norm = word_weights.iter().map(|w| w * w).sum::<f64>().sqrt();
+        match norm == 0.0 {
+            true => vec![0.0; word_weights.len()],
+            false => word_weights.iter().map(|w| w / norm).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::index8_9::Hit;
+    use crate::index::Index;
+
+    // Every article has the same title, whose term has no weight in TF-IDF, so the scores below
+    // come from the bodies alone
+    fn setup_test() -> Index<FreqDatabase> {
+        let articles = [
+            ("article", "word1 word2 word2"),
+            ("article", "word2 word3"),
+            ("article", "word3 word4 word4 word4"),
+        ];
+        Index::<FreqDatabase>::from_articles(
+            articles
+                .iter()
+                .map(|(title, contents)| {
+                    (
+                        title.to_string(),
+                        contents.split(' ').map(|w| w.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    fn assert_hits(hits: Vec<Hit>, expected: &[(usize, f64)]) {
+        assert_eq!(hits.len(), expected.len(), "{:?}", hits);
+        for (hit, &(article, score)) in hits.iter().zip(expected) {
+            assert_eq!(hit.article, article, "{:?}", hits);
+            assert!((hit.score - score).abs() < 1e-5, "{:?}", hits);
+        }
+    }
+
+    #[test]
+    fn tf_idf_norms_are_precomputed() {
+        let index = setup_test();
+        // article 0: ln 3 for word1, (1 + ln 2) * ln 1.5 for word2
+        assert!((index.database.article_norm(0) - 1.295476).abs() < 1e-5);
+        // article 1: ln 1.5 for both words
+        assert!((index.database.article_norm(1) - 1.5f64.ln() * 2f64.sqrt()).abs() < 1e-9);
+        assert!((index.database.article_norm(2) - 2.340943).abs() < 1e-5);
+    }
+
+    #[test]
+    fn tf_idf_cosine_of_one_word() {
+        let mut index = setup_test();
+        index.set_scorer(Box::new(TfIdf));
+        // article 1 has the same weight for both of its words, so its vector is 45 degrees off
+        assert_hits(
+            index.top_k_exhaustive(&words(&["word2"]), 10),
+            &[(1, 0.5f64.sqrt()), (0, 0.529937)],
+        );
+    }
+
+    #[test]
+    fn tf_idf_cosine_of_two_words() {
+        let mut index = setup_test();
+        index.set_scorer(Box::new(TfIdf));
+        let expected = [(2, 0.696420), (0, 0.599664)];
+        assert_hits(
+            index.top_k_exhaustive(&words(&["word1", "word4"]), 10),
+            &expected,
+        );
+        assert_hits(
+            index.top_k_wand(&words(&["word1", "word4"]), 10, true),
+            &expected,
+        );
+        // Words that aren't in the index don't change the angle
+        assert_hits(
+            index.top_k_exhaustive(&words(&["word4", "nothing", "word1"]), 1),
+            &expected[..1],
+        );
+    }
+
+    #[test]
+    fn words_in_every_article_score_nothing() {
+        let mut index = Index::<FreqDatabase>::from_articles(vec![
+            ("a".to_string(), words(&["same"])),
+            ("b".to_string(), words(&["same", "same"])),
+        ]);
+        index.set_scorer(Box::new(TfIdf));
+        assert_hits(
+            index.top_k_exhaustive(&words(&["same"]), 10),
+            &[(0, 0.0), (1, 0.0)],
+        );
+    }
+
+    #[test]
+    fn bm25_by_hand() {
+        let index = setup_test();
+        // Only article 0 has word1. Its length is the average, so the score is the idf.
+        assert_hits(
+            index.top_k_exhaustive(&words(&["word1"]), 10),
+            &[(0, (1.0 + 2.5 / 1.5f64).ln())],
+        );
+        // word4 occurs 3 times in article 2, which is 4 words long against an average of 3
+        let len_norm = 0.25 + 0.75 * 4.0 / 3.0;
+        assert_hits(
+            index.top_k_exhaustive(&words(&["word4"]), 10),
+            &[(
+                2,
+                (1.0 + 2.5 / 1.5f64).ln() * 3.0 * 2.2 / (3.0 + 1.2 * len_norm),
+            )],
+        );
+    }
+}
@@ -0,0 +1,656 @@
+use std::collections::HashMap;
+use std::iter::once;
+use std::ops::Range;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use crate::index::incremental::Documents;
+use crate::index::Index;
+
+use super::*;
+
+// An immutable part of the index. doc_ids holds the global number of every article in the
+// segment, in increasing order, and every segment only holds larger numbers than the ones before it.
+#[derive(Clone)]
+pub struct Segment {
+    index: Index<HashMap<String, Vec<usize>>>,
+    doc_ids: Vec<usize>,
+}
+
+impl Segment {
+    fn new() -> Segment {
+        Segment {
+            index: Index {
+                database: HashMap::new(),
+                article_titles: Vec::new(),
+            },
+            doc_ids: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.doc_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.doc_ids.is_empty()
+    }
+
+    fn local_id(&self, doc_id: usize) -> Option<usize> {
+        self.doc_ids.binary_search(&doc_id).ok()
+    }
+
+    // Merges neighbouring segments, leaving out their deleted articles. The postings can simply be
+    // appended to each other, since the articles of later segments have larger numbers.
+    fn merge(parts: &[(Arc<Segment>, Arc<Vec<bool>>)]) -> Segment {
+        let mut merged = Segment::new();
+        for (segment, deleted) in parts {
+            let mut new_ids: Vec<Option<usize>> = Vec::with_capacity(segment.len());
+            for (local, title) in segment.index.article_titles.iter().enumerate() {
+                if deleted[local] {
+                    new_ids.push(None);
+                } else {
+                    new_ids.push(Some(merged.len()));
+                    merged.doc_ids.push(segment.doc_ids[local]);
+                    merged.index.article_titles.push(title.clone());
+                }
+            }
+            for (word, articles) in &segment.index.database {
+                merged
+                    .index
+                    .database
+                    .entry(word.clone())
+                    .or_default()
+                    .extend(articles.iter().filter_map(|&a| new_ids[a]));
+            }
+        }
+        merged
+            .index
+            .database
+            .retain(|_, articles| !articles.is_empty());
+        merged
+    }
+}
+
+// Segments are grouped in tiers by size, with every tier merge_factor times larger than the one
+// below it. When merge_factor neighbouring segments are in the same tier, they are merged into one
+// segment of the next tier.
+#[derive(Debug, Clone, Copy)]
+pub struct TieredMergePolicy {
+    pub segment_size: usize,
+    pub merge_factor: usize,
+}
+
+impl Default for TieredMergePolicy {
+    fn default() -> Self {
+        TieredMergePolicy {
+            segment_size: 1000,
+            merge_factor: 10,
+        }
+    }
+}
+
+impl TieredMergePolicy {
+    pub fn tier(&self, n_docs: usize) -> usize {
+        let mut tier = 0;
+        let mut size = self.segment_size;
+        while n_docs > size {
+            size *= self.merge_factor;
+            tier += 1;
+        }
+        tier
+    }
+
+    // The segments to merge next, given the number of live articles in every segment. Segments
+    // with only deleted articles are dropped first, and then the smallest tier is merged.
+    pub fn find_merge(&self, sizes: &[usize]) -> Option<Range<usize>> {
+        if let Some(empty) = sizes.iter().position(|&size| size == 0) {
+            return Some(empty..empty + 1);
+        }
+        let tiers: Vec<usize> = sizes.iter().map(|&size| self.tier(size)).collect();
+        let mut best: Option<Range<usize>> = None;
+        for window in 0..(tiers.len() + 1).saturating_sub(self.merge_factor) {
+            let tier = tiers[window];
+            let same_tier = tiers[window..window + self.merge_factor]
+                .iter()
+                .all(|&t| t == tier);
+            let smaller = match &best {
+                Some(b) => tier < tiers[b.start],
+                None => true,
+            };
+            if same_tier && smaller {
+                best = Some(window..window + self.merge_factor);
+            }
+        }
+        best
+    }
+}
+
+// The deleted flags are shared with the searches and merges that copied them, so a delete copies
+// them first if any of those still hold them
+struct SegmentEntry {
+    segment: Arc<Segment>,
+    deleted: Arc<Vec<bool>>,
+    n_deleted: usize,
+}
+
+impl SegmentEntry {
+    fn live(&self) -> usize {
+        self.segment.len() - self.n_deleted
+    }
+}
+
+struct State {
+    segments: Vec<SegmentEntry>,
+    // New articles are collected here until there are enough for a segment. Searches share it, so
+    // it is copied if an article is added while one of them still holds it.
+    buffer: Arc<Segment>,
+    buffer_deleted: Arc<Vec<bool>>,
+    next_doc_id: usize,
+    // Counts the flushes, and how many of them the merger has dealt with
+    generation: u64,
+    merged_generation: u64,
+    merging: bool,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    policy: TieredMergePolicy,
+    work: Condvar,
+    idle: Condvar,
+}
+
+// A merge that has been started, but not yet put in place of the segments it merges. No other
+// merge starts until it is dropped, whether it was finished or not.
+pub struct PendingMerge {
+    shared: Arc<Shared>,
+    range: Range<usize>,
+    parts: Vec<(Arc<Segment>, Arc<Vec<bool>>)>,
+}
+
+impl Drop for PendingMerge {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.merging = false;
+            self.shared.idle.notify_all();
+        }
+    }
+}
+
+// An index made of immutable segments. New articles are collected in a buffer, which becomes a
+// new segment when it is full. Searches go through every segment, and a background thread merges
+// segments following the merge policy.
+pub struct SegmentedIndex {
+    shared: Arc<Shared>,
+    merger: Option<JoinHandle<()>>,
+}
+
+impl SegmentedIndex {
+    pub fn new(policy: TieredMergePolicy) -> SegmentedIndex {
+        assert!(policy.segment_size > 0 && policy.merge_factor > 1);
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                segments: Vec::new(),
+                buffer: Arc::new(Segment::new()),
+                buffer_deleted: Arc::new(Vec::new()),
+                next_doc_id: 0,
+                generation: 0,
+                merged_generation: 0,
+                merging: false,
+                shutdown: false,
+            }),
+            policy,
+            work: Condvar::new(),
+            idle: Condvar::new(),
+        });
+        let merger_shared = Arc::clone(&shared);
+        let merger = thread::spawn(move || SegmentedIndex::merge_loop(&merger_shared));
+        SegmentedIndex {
+            shared,
+            merger: Some(merger),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+
+    // Returns the global article number of the new article
+    pub fn add_document(&self, title: &str, contents: &[String]) -> usize {
+        let mut state = self.lock();
+        let doc_id = state.next_doc_id;
+        state.next_doc_id += 1;
+        let buffer = Arc::make_mut(&mut state.buffer);
+        buffer.index.add_document(title, contents);
+        buffer.doc_ids.push(doc_id);
+        Arc::make_mut(&mut state.buffer_deleted).push(false);
+        if state.buffer.len() >= self.shared.policy.segment_size {
+            self.flush_locked(&mut state);
+        }
+        doc_id
+    }
+
+    // Turns the buffered articles into a segment, even if there are fewer than segment_size
+    pub fn flush(&self) {
+        let mut state = self.lock();
+        self.flush_locked(&mut state);
+    }
+
+    fn flush_locked(&self, state: &mut State) {
+        if state.buffer.is_empty() {
+            return;
+        }
+        let segment = std::mem::replace(&mut state.buffer, Arc::new(Segment::new()));
+        let deleted = std::mem::take(&mut state.buffer_deleted);
+        let n_deleted = deleted.iter().filter(|&&d| d).count();
+        state.segments.push(SegmentEntry {
+            segment,
+            deleted,
+            n_deleted,
+        });
+        state.generation += 1;
+        self.shared.work.notify_one();
+    }
+
+    // Returns false if there is no such article, or it is already deleted
+    pub fn delete_document(&self, doc_id: usize) -> bool {
+        let mut state = self.lock();
+        let state = &mut *state;
+        if let Some(local) = state.buffer.local_id(doc_id) {
+            let deleted = Arc::make_mut(&mut state.buffer_deleted);
+            return !std::mem::replace(&mut deleted[local], true);
+        }
+        for entry in state.segments.iter_mut() {
+            if let Some(local) = entry.segment.local_id(doc_id) {
+                if entry.deleted[local] {
+                    return false;
+                }
+                Arc::make_mut(&mut entry.deleted)[local] = true;
+                entry.n_deleted += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Runs the query on every segment and the buffer, in the order of their articles. Only the
+    // segments and their deleted flags are shared under the lock, and the search runs without it.
+    fn for_each_match<F>(&self, query: &Query, mut f: F)
+    where
+        F: FnMut(&Segment, usize),
+    {
+        let parts: Vec<(Arc<Segment>, Arc<Vec<bool>>)> = {
+            let state = self.lock();
+            state
+                .segments
+                .iter()
+                .map(|entry| (Arc::clone(&entry.segment), Arc::clone(&entry.deleted)))
+                .chain(once((
+                    Arc::clone(&state.buffer),
+                    Arc::clone(&state.buffer_deleted),
+                )))
+                .collect()
+        };
+        for (segment, deleted) in &parts {
+            for local in segment.index.matching_articles(query) {
+                if !deleted[local] {
+                    f(segment, local);
+                }
+            }
+        }
+    }
+
+    // The global numbers of the matching articles, in increasing order
+    pub fn search_articles(&self, query: &Query) -> Vec<usize> {
+        let mut result: Vec<usize> = Vec::new();
+        self.for_each_match(query, |segment, local| result.push(segment.doc_ids[local]));
+        result
+    }
+
+    pub fn n_segments(&self) -> usize {
+        self.lock().segments.len()
+    }
+
+    // The number of articles in every segment, deleted ones included
+    pub fn segment_sizes(&self) -> Vec<usize> {
+        self.lock()
+            .segments
+            .iter()
+            .map(|entry| entry.segment.len())
+            .collect()
+    }
+
+    // Blocks until the merger has dealt with every flushed segment
+    pub fn wait_for_merges(&self) {
+        let mut state = self.lock();
+        while state.merged_generation != state.generation || state.merging {
+            state = self.shared.idle.wait(state).unwrap();
+        }
+    }
+
+    // Picks the next segments to merge, following the merge policy
+    pub fn start_merge(&self) -> Option<PendingMerge> {
+        SegmentedIndex::start_merge_shared(&self.shared)
+    }
+
+    fn start_merge_shared(shared: &Arc<Shared>) -> Option<PendingMerge> {
+        let mut state = shared.state.lock().unwrap();
+        if state.merging {
+            return None;
+        }
+        let sizes: Vec<usize> = state.segments.iter().map(|entry| entry.live()).collect();
+        let range = shared.policy.find_merge(&sizes)?;
+        state.merging = true;
+        let parts = state.segments[range.clone()]
+            .iter()
+            .map(|entry| (Arc::clone(&entry.segment), Arc::clone(&entry.deleted)))
+            .collect();
+        Some(PendingMerge {
+            shared: Arc::clone(shared),
+            range,
+            parts,
+        })
+    }
+
+    // Merges the segments without holding the lock, and then puts the result in their place
+    pub fn finish_merge(&self, merge: PendingMerge) {
+        SegmentedIndex::finish_merge_shared(&self.shared, merge)
+    }
+
+    fn finish_merge_shared(shared: &Shared, merge: PendingMerge) {
+        let merged = Segment::merge(&merge.parts);
+
+        let mut state = shared.state.lock().unwrap();
+        // Articles deleted while the merge was running are deleted in the merged segment too
+        let mut deleted = vec![false; merged.len()];
+        let mut n_deleted = 0;
+        for (entry, (_, deleted_before)) in
+            state.segments[merge.range.clone()].iter().zip(&merge.parts)
+        {
+            for (local, &is_deleted) in entry.deleted.iter().enumerate() {
+                if is_deleted && !deleted_before[local] {
+                    let merged_local = merged.local_id(entry.segment.doc_ids[local]).unwrap();
+                    deleted[merged_local] = true;
+                    n_deleted += 1;
+                }
+            }
+        }
+        state.segments.drain(merge.range.clone());
+        if !merged.is_empty() {
+            state.segments.insert(
+                merge.range.start,
+                SegmentEntry {
+                    segment: Arc::new(merged),
+                    deleted: Arc::new(deleted),
+                    n_deleted,
+                },
+            );
+        }
+        // Dropping the merge lets the next one start
+    }
+
+    // Runs merges until the merge policy finds nothing more to merge
+    pub fn maybe_merge(&self) {
+        while let Some(merge) = self.start_merge() {
+            self.finish_merge(merge);
+        }
+    }
+
+    fn merge_loop(shared: &Arc<Shared>) {
+        loop {
+            let generation = {
+                let mut state = shared.state.lock().unwrap();
+                while state.merged_generation == state.generation && !state.shutdown {
+                    state = shared.work.wait(state).unwrap();
+                }
+                if state.shutdown {
+                    return;
+                }
+                state.generation
+            };
+            while let Some(merge) = SegmentedIndex::start_merge_shared(shared) {
+                SegmentedIndex::finish_merge_shared(shared, merge);
+            }
+            let mut state = shared.state.lock().unwrap();
+            state.merged_generation = generation;
+            shared.idle.notify_all();
+        }
+    }
+}
+
+impl Drop for SegmentedIndex {
+    fn drop(&mut self) {
+        self.lock().shutdown = true;
+        self.shared.work.notify_all();
+        if let Some(merger) = self.merger.take() {
+            merger.join().unwrap();
+        }
+    }
+}
+
+impl Search for SegmentedIndex {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        let mut result: ArticleTitles = Vec::new();
+        self.for_each_match(query, |segment, local| {
+            result.push(segment.index.article_titles[local].clone())
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::*;
+    use crate::index::gen_query::gen_a_lot_of_runs_bool;
+
+    const FILE: &str = "data/WestburyLab.wikicorp.201004_100KB.txt";
+
+    fn words(s: &str) -> Vec<String> {
+        s.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    fn boolean(search_string: &str) -> Query {
+        Query {
+            search_string: search_string.to_string(),
+            search_type: SearchType::BooleanSearch("Naive".to_string()),
+        }
+    }
+
+    fn policy(segment_size: usize, merge_factor: usize) -> TieredMergePolicy {
+        TieredMergePolicy {
+            segment_size,
+            merge_factor,
+        }
+    }
+
+    #[test]
+    fn tiers_grow_by_the_merge_factor() {
+        let policy = policy(10, 3);
+        assert_eq!(policy.tier(0), 0);
+        assert_eq!(policy.tier(10), 0);
+        assert_eq!(policy.tier(11), 1);
+        assert_eq!(policy.tier(30), 1);
+        assert_eq!(policy.tier(31), 2);
+        assert_eq!(policy.tier(90), 2);
+    }
+
+    #[test]
+    fn merge_policy_picks_the_smallest_tier() {
+        let policy = policy(10, 3);
+        assert_eq!(policy.find_merge(&[]), None);
+        assert_eq!(policy.find_merge(&[10, 10]), None);
+        assert_eq!(policy.find_merge(&[10, 10, 10]), Some(0..3));
+        assert_eq!(policy.find_merge(&[30, 10, 10]), None);
+        assert_eq!(policy.find_merge(&[30, 30, 30, 10, 10, 10]), Some(3..6));
+        assert_eq!(policy.find_merge(&[30, 30, 30, 10, 10]), Some(0..3));
+        assert_eq!(policy.find_merge(&[30, 0, 10]), Some(1..2));
+    }
+
+    #[test]
+    fn searches_run_without_the_lock() {
+        let index = SegmentedIndex::new(policy(2, 3));
+        for i in 0..3 {
+            index.add_document(&format!("article {}", i), &words("word"));
+        }
+        // Adding and deleting while a search is running would block if it held the lock, and the
+        // search sees the index as it was when it started
+        let mut found = Vec::new();
+        index.for_each_match(&boolean("word"), |segment, local| {
+            index.add_document("new article", &words("word"));
+            index.delete_document(0);
+            found.push(segment.doc_ids[local]);
+        });
+        assert_eq!(found, vec![0, 1, 2]);
+        assert_eq!(index.search_articles(&boolean("word")), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn full_buffers_become_segments_that_are_merged() {
+        let index = SegmentedIndex::new(policy(2, 3));
+        for i in 0..5 {
+            index.add_document(&format!("article {}", i), &words("word"));
+        }
+        index.wait_for_merges();
+        assert_eq!(index.segment_sizes(), vec![2, 2]);
+        // The buffered article can be found before it is flushed
+        assert_eq!(index.search_articles(&boolean("word")), vec![0, 1, 2, 3, 4]);
+
+        index.add_document("article 5", &words("word"));
+        index.wait_for_merges();
+        assert_eq!(index.segment_sizes(), vec![6]);
+
+        for i in 6..18 {
+            index.add_document(&format!("article {}", i), &words("other"));
+        }
+        index.wait_for_merges();
+        assert_eq!(index.segment_sizes(), vec![18]);
+        assert_eq!(index.search(&boolean("word & !other")).len(), 6);
+        assert_eq!(index.search(&boolean("other")).len(), 12);
+    }
+
+    #[test]
+    fn deleted_articles_are_dropped_when_merged() {
+        let index = SegmentedIndex::new(policy(2, 2));
+        let a = index.add_document("A", &words("one"));
+        let b = index.add_document("B", &words("one two"));
+        assert!(index.delete_document(a));
+        assert!(!index.delete_document(a));
+        assert!(!index.delete_document(99));
+        assert_eq!(index.search(&boolean("one")), vec!["B"]);
+        assert_eq!(index.search(&boolean("!two")), Vec::<String>::new());
+
+        index.add_document("C", &words("two"));
+        index.add_document("D", &words("one"));
+        index.wait_for_merges();
+        // A is gone for good
+        assert_eq!(index.segment_sizes(), vec![3]);
+        assert_eq!(index.search(&boolean("one")), vec!["B", "D"]);
+
+        // Segments with only deleted articles disappear
+        index.delete_document(b);
+        index.delete_document(b + 1);
+        index.delete_document(b + 2);
+        index.maybe_merge();
+        assert_eq!(index.n_segments(), 0);
+        assert_eq!(index.search(&boolean("one | two")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn deletes_during_a_merge_are_kept() {
+        let index = SegmentedIndex::new(policy(2, 3));
+        // Stop the background merger from taking the merge first
+        index.lock().merging = true;
+        for i in 0..6 {
+            index.add_document(&format!("article {}", i), &words("word"));
+        }
+        loop {
+            let state = index.lock();
+            if state.merged_generation == state.generation {
+                break;
+            }
+        }
+        index.lock().merging = false;
+
+        let merge = index.start_merge().unwrap();
+        assert!(index.start_merge().is_none());
+        index.delete_document(3);
+        index.finish_merge(merge);
+        index.wait_for_merges();
+
+        assert_eq!(index.segment_sizes(), vec![6]);
+        assert_eq!(index.search_articles(&boolean("word")), vec![0, 1, 2, 4, 5]);
+        assert!(!index.delete_document(3));
+    }
+
+    #[test]
+    fn dropped_merges_let_the_next_one_start() {
+        let index = SegmentedIndex::new(policy(2, 3));
+        index.lock().merging = true;
+        for i in 0..6 {
+            index.add_document(&format!("article {}", i), &words("word"));
+        }
+        loop {
+            let state = index.lock();
+            if state.merged_generation == state.generation {
+                break;
+            }
+        }
+        index.lock().merging = false;
+
+        drop(index.start_merge().unwrap());
+        index.wait_for_merges();
+        assert_eq!(index.segment_sizes(), vec![2, 2, 2]);
+        index.maybe_merge();
+        assert_eq!(index.segment_sizes(), vec![6]);
+    }
+
+    #[test]
+    fn segmented_and_index8_get_the_same_results() {
+        let config = Config {
+            file_path: FILE.to_string(),
+            indexno: "8".to_string(),
+        };
+        let index8 = Index::index8(&config).unwrap();
+        let articles: Vec<(String, Vec<String>)> = read_and_clean_file_to_iter(&config)
+            .unwrap()
+            .into_iter()
+            .filter(|(title, _)| !title.is_empty())
+            .collect();
+
+        let index = SegmentedIndex::new(policy(1, 2));
+        for (title, contents) in &articles {
+            index.add_document(title, contents);
+        }
+        index.wait_for_merges();
+
+        let queries = gen_a_lot_of_runs_bool(FILE.to_string(), 10);
+        for depth_vec in &queries {
+            for query in depth_vec {
+                assert_eq!(
+                    index8.boolean_search_naive(query),
+                    index.search(&boolean(query))
+                );
+            }
+        }
+
+        // After deleting the first article, search as if it had never been added
+        let mut reference: Index<HashMap<String, Vec<usize>>> = Index {
+            database: HashMap::new(),
+            article_titles: Vec::new(),
+        };
+        for (title, contents) in articles.iter().skip(1) {
+            reference.add_document(title, contents);
+        }
+        index.delete_document(0);
+        for depth_vec in &queries {
+            for query in depth_vec {
+                assert_eq!(
+                    reference.boolean_search_naive(query),
+                    index.search(&boolean(query))
+                );
+            }
+        }
+    }
+}
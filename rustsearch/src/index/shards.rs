@@ -0,0 +1,710 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::helpers::*;
+use crate::index::incremental::Documents;
+use crate::index::Index;
+
+use super::*;
+
+// An index split into shards, each holding some of the articles. A query is sent to every shard
+// at once, and the matches are merged back into the order the articles have in the corpus.
+//
+// A shard answers with the global article number of every match along with its title. Shards
+// can live in this process (LocalShard) or behind a socket (RemoteShard talking to a
+// ShardServer), which stands in for a shard running in another process.
+
+// The number and error of every shard that failed to answer
+pub type ShardErrors = Vec<(usize, io::Error)>;
+
+pub trait Shard: Send + Sync {
+    // The global article numbers and titles of the matching articles, in increasing order
+    fn search_articles(&self, query: &Query) -> io::Result<Vec<(usize, String)>>;
+}
+
+// How articles are spread over the shards
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partition {
+    // Each shard gets one contiguous range of articles
+    Range,
+    // Each article goes to the shard picked by a hash of its number
+    Hash,
+}
+
+impl Partition {
+    pub fn shard(&self, article: usize, n_articles: usize, n_shards: usize) -> usize {
+        match self {
+            Partition::Range => article * n_shards / n_articles.max(1),
+            Partition::Hash => (mix(article as u64) % n_shards as u64) as usize,
+        }
+    }
+}
+
+// The finalizer of splitmix64, so neighbouring articles land on unrelated shards
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+pub struct LocalShard<T> {
+    index: Index<T>,
+    // The global article number of every article in the shard, in increasing order
+    global_ids: Vec<usize>,
+}
+
+impl<T> LocalShard<T>
+where
+    Index<T>: Documents,
+{
+    pub fn new(index: Index<T>) -> Self {
+        LocalShard {
+            index,
+            global_ids: Vec::new(),
+        }
+    }
+
+    // Articles must be added in increasing order of their global numbers
+    pub fn add_document(&mut self, global_id: usize, title: &str, contents: &[String]) {
+        self.index.add_document(title, contents);
+        self.global_ids.push(global_id);
+    }
+
+    pub fn n_articles(&self) -> usize {
+        self.global_ids.len()
+    }
+}
+
+impl<T> Shard for LocalShard<T>
+where
+    Index<T>: Documents + Send + Sync,
+{
+    fn search_articles(&self, query: &Query) -> io::Result<Vec<(usize, String)>> {
+        if !self.index.supports(&query.search_type) {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "The index of the shard can't answer this kind of search",
+            ));
+        }
+        Ok(self
+            .index
+            .matching_articles(query)
+            .into_iter()
+            .map(|a| (self.global_ids[a], self.index.title(a).to_string()))
+            .collect())
+    }
+}
+
+// Queries and answers go over the socket as frames: a 4 byte little-endian length followed by
+// that many bytes, so nothing in a query or a title can be mistaken for the end of it. A query is
+// three frames: the kind of search, its argument and the search string. The answer starts with
+// a status byte. After ANSWER_FOUND comes the number of matches as 8 little-endian bytes, followed
+// by the article number, in the same way, and the title frame of each. After the other statuses
+// comes a frame with the error.
+
+const ANSWER_FOUND: u8 = 0;
+const ANSWER_UNSUPPORTED: u8 = 1;
+const ANSWER_FAILED: u8 = 2;
+
+// How long a server waits for the query of a client that has connected
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn write_frame(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(bytes.len()).map_err(|_| invalid_data("Frame too long"))?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(bytes)
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_frame(input: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(input)? as u64;
+    // Read only what arrives instead of allocating what the length claims
+    let mut bytes = Vec::new();
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "The frame was cut off",
+        ));
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("Frame is not valid UTF-8"))
+}
+
+fn encode_query(query: &Query) -> io::Result<Vec<u8>> {
+    let (kind, argument) = match &query.search_type {
+        SearchType::SingleWordSearch => ("single", ""),
+        SearchType::BooleanSearch(x) => ("boolean", x.as_str()),
+        SearchType::PrefixSearch => ("prefix", ""),
+        SearchType::ExactSearch(x) => ("exact", x.as_str()),
+        SearchType::FuzzySearch => ("fuzzy", ""),
+        SearchType::TermSearch(x) => ("term", x.as_str()),
+    };
+    let mut bytes = Vec::new();
+    for part in [kind, argument, &query.search_string] {
+        write_frame(&mut bytes, part.as_bytes())?;
+    }
+    Ok(bytes)
+}
+
+fn decode_query(input: &mut impl Read) -> io::Result<Query> {
+    let (kind, argument, search_string) =
+        (read_frame(input)?, read_frame(input)?, read_frame(input)?);
+    let search_type = match kind.as_str() {
+        "single" => SearchType::SingleWordSearch,
+        "boolean" => SearchType::BooleanSearch(argument),
+        "prefix" => SearchType::PrefixSearch,
+        "exact" => SearchType::ExactSearch(argument),
+        "fuzzy" => SearchType::FuzzySearch,
+        "term" => SearchType::TermSearch(argument),
+        _ => return Err(invalid_data("Unknown kind of search")),
+    };
+    Ok(Query {
+        search_string,
+        search_type,
+    })
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn serve_connection(shard: &dyn Shard, stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    let query = decode_query(&mut BufReader::new(stream.try_clone()?))?;
+
+    let mut out = BufWriter::new(stream);
+    match shard.search_articles(&query) {
+        Ok(found) => {
+            out.write_all(&[ANSWER_FOUND])?;
+            out.write_all(&(found.len() as u64).to_le_bytes())?;
+            for (article, title) in found {
+                out.write_all(&(article as u64).to_le_bytes())?;
+                write_frame(&mut out, title.as_bytes())?;
+            }
+        }
+        Err(e) => {
+            let status = match e.kind() {
+                ErrorKind::Unsupported => ANSWER_UNSUPPORTED,
+                _ => ANSWER_FAILED,
+            };
+            out.write_all(&[status])?;
+            write_frame(&mut out, e.to_string().as_bytes())?;
+        }
+    }
+    out.flush()
+}
+
+// Answers queries for a shard on a local socket until it is dropped
+pub struct ShardServer {
+    addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ShardServer {
+    pub fn serve<S: Shard + 'static>(shard: S) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&shutdown);
+        let shard = Arc::new(shard);
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                // Every connection is served on its own thread, so a slow client holds up no
+                // other, and a broken connection only fails the query it carried
+                if let Ok(stream) = stream {
+                    let shard = Arc::clone(&shard);
+                    thread::spawn(move || {
+                        let _ = serve_connection(&*shard, stream);
+                    });
+                }
+            }
+        });
+        Ok(ShardServer {
+            addr,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for ShardServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the accept loop so it sees the flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+pub struct RemoteShard {
+    addr: SocketAddr,
+}
+
+impl RemoteShard {
+    pub fn new(addr: SocketAddr) -> Self {
+        RemoteShard { addr }
+    }
+}
+
+impl Shard for RemoteShard {
+    fn search_articles(&self, query: &Query) -> io::Result<Vec<(usize, String)>> {
+        let mut stream = TcpStream::connect(self.addr)?;
+        stream.write_all(&encode_query(query)?)?;
+        let mut answer = BufReader::new(stream);
+
+        let mut status = [0];
+        answer.read_exact(&mut status)?;
+        match status[0] {
+            ANSWER_FOUND => {}
+            ANSWER_UNSUPPORTED => {
+                return Err(io::Error::new(
+                    ErrorKind::Unsupported,
+                    read_frame(&mut answer)?,
+                ))
+            }
+            ANSWER_FAILED => return Err(io::Error::other(read_frame(&mut answer)?)),
+            _ => return Err(invalid_data("Malformed answer")),
+        }
+        let n_found = read_u64(&mut answer)?;
+        let mut found = Vec::new();
+        for _ in 0..n_found {
+            let article = usize::try_from(read_u64(&mut answer)?)
+                .map_err(|_| invalid_data("Malformed answer"))?;
+            found.push((article, read_frame(&mut answer)?));
+        }
+        Ok(found)
+    }
+}
+
+pub struct ShardedIndex {
+    shards: Vec<Box<dyn Shard>>,
+}
+
+impl ShardedIndex {
+    pub fn new(shards: Vec<Box<dyn Shard>>) -> Self {
+        ShardedIndex { shards }
+    }
+
+    // Splits the articles of the corpus over n_shards shards, each an index like the given one
+    pub fn partition<T>(
+        articles: Vec<(String, Vec<String>)>,
+        empty: Index<T>,
+        n_shards: usize,
+        partition: Partition,
+    ) -> Vec<LocalShard<T>>
+    where
+        Index<T>: Documents + Clone,
+    {
+        let articles: Vec<(String, Vec<String>)> = articles
+            .into_iter()
+            .filter(|(title, _)| !title.is_empty())
+            .collect();
+        let mut shards: Vec<LocalShard<T>> = (0..n_shards)
+            .map(|_| LocalShard::new(empty.clone()))
+            .collect();
+        let n_articles = articles.len();
+        for (article, (title, contents)) in articles.into_iter().enumerate() {
+            let shard = partition.shard(article, n_articles, n_shards);
+            shards[shard].add_document(article, &title, &contents);
+        }
+        shards
+    }
+
+    pub fn index_sharded(
+        config: &Config,
+        n_shards: usize,
+        partition: Partition,
+    ) -> Result<Self, Box<dyn Error>> {
+        let empty: Index<HashMap<String, Vec<usize>>> = Index {
+            database: HashMap::new(),
+            article_titles: Vec::new(),
+        };
+        let shards = ShardedIndex::partition(
+            read_and_clean_file_to_iter(config)?,
+            empty,
+            n_shards,
+            partition,
+        );
+        Ok(ShardedIndex::new(
+            shards
+                .into_iter()
+                .map(|shard| Box::new(shard) as Box<dyn Shard>)
+                .collect(),
+        ))
+    }
+
+    pub fn n_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    // Runs the query on every shard in parallel, and merges the matches by article number. Fails
+    // if any shard fails to answer.
+    pub fn search_articles(&self, query: &Query) -> Result<Vec<(usize, String)>, Box<dyn Error>> {
+        let answers = self.ask_shards(query);
+        let answers = answers.into_iter().collect::<io::Result<Vec<_>>>()?;
+        Ok(merge_answers(answers))
+    }
+
+    // Like search_articles, but with the matches of the shards that answered, along with the
+    // number and error of every shard that didn't
+    pub fn search_available(&self, query: &Query) -> (Vec<(usize, String)>, ShardErrors) {
+        let mut answers = Vec::new();
+        let mut failed = Vec::new();
+        for (shard, answer) in self.ask_shards(query).into_iter().enumerate() {
+            match answer {
+                Ok(found) => answers.push(found),
+                Err(e) => failed.push((shard, e)),
+            }
+        }
+        (merge_answers(answers), failed)
+    }
+
+    fn ask_shards(&self, query: &Query) -> Vec<io::Result<Vec<(usize, String)>>> {
+        thread::scope(|s| {
+            let handles: Vec<_> = self
+                .shards
+                .iter()
+                .map(|shard| s.spawn(move || shard.search_articles(query)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(io::Error::other("The shard panicked")))
+                })
+                .collect()
+        })
+    }
+}
+
+// A k-way merge of the answers, which are each sorted by article number
+fn merge_answers(answers: Vec<Vec<(usize, String)>>) -> Vec<(usize, String)> {
+    let mut iters: Vec<_> = answers.into_iter().map(|a| a.into_iter()).collect();
+    let mut heads = BinaryHeap::new();
+    let mut titles: Vec<Option<String>> = vec![None; iters.len()];
+    for (shard, iter) in iters.iter_mut().enumerate() {
+        if let Some((article, title)) = iter.next() {
+            heads.push(Reverse((article, shard)));
+            titles[shard] = Some(title);
+        }
+    }
+
+    let mut merged = Vec::new();
+    while let Some(Reverse((article, shard))) = heads.pop() {
+        merged.push((article, titles[shard].take().unwrap()));
+        if let Some((article, title)) = iters[shard].next() {
+            heads.push(Reverse((article, shard)));
+            titles[shard] = Some(title);
+        }
+    }
+    merged
+}
+
+// A shard that fails to answer leaves its matches out, rather than failing the whole search. The
+// failures are returned by search_available.
+impl Search for ShardedIndex {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        let (found, _) = self.search_available(query);
+        found.into_iter().map(|(_, title)| title).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::gen_query::{gen_a_lot_of_runs_bool, gen_a_lot_of_runs_tries};
+    use crate::index::index9_1::Trie;
+
+    use super::*;
+
+    const FILE: &str = "data/WestburyLab.wikicorp.201004_100KB.txt";
+
+    fn query(search_string: &str, search_type: SearchType) -> Query {
+        Query {
+            search_string: search_string.to_string(),
+            search_type,
+        }
+    }
+
+    fn boolean(search_string: &str) -> Query {
+        query(
+            search_string,
+            SearchType::BooleanSearch("Naive".to_string()),
+        )
+    }
+
+    fn config() -> Config {
+        Config {
+            file_path: FILE.to_string(),
+            indexno: "8".to_string(),
+        }
+    }
+
+    fn words(s: &str) -> Vec<String> {
+        s.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    fn empty_index8() -> Index<HashMap<String, Vec<usize>>> {
+        Index {
+            database: HashMap::new(),
+            article_titles: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn partitions_cover_every_shard() {
+        for partition in [Partition::Range, Partition::Hash] {
+            let mut sizes = vec![0; 4];
+            for article in 0..1000 {
+                sizes[partition.shard(article, 1000, 4)] += 1;
+            }
+            assert!(sizes.iter().all(|&size| size > 150), "{:?}", sizes);
+        }
+        let shards: Vec<usize> = (0..10).map(|a| Partition::Range.shard(a, 10, 3)).collect();
+        assert_eq!(shards, vec![0, 0, 0, 0, 1, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn queries_survive_encoding() {
+        let queries = vec![
+            query("word", SearchType::SingleWordSearch),
+            query(
+                "a & (b | !c)",
+                SearchType::BooleanSearch("Naive".to_string()),
+            ),
+            query("anarch*", SearchType::PrefixSearch),
+            query("a b\tc", SearchType::ExactSearch("KMP".to_string())),
+            query("a\nb\n", SearchType::ExactSearch("KMP".to_string())),
+            query("", SearchType::BooleanSearch("Naive".to_string())),
+            query("wrod", SearchType::FuzzySearch),
+            query("a..b", SearchType::TermSearch("Range".to_string())),
+        ];
+        for q in queries {
+            let bytes = encode_query(&q).unwrap();
+            let decoded = decode_query(&mut &bytes[..]).unwrap();
+            assert_eq!(decoded.search_string, q.search_string);
+            assert_eq!(decoded.search_type.to_string(), q.search_type.to_string());
+            assert!(decode_query(&mut &bytes[..bytes.len() - 1]).is_err());
+        }
+        let mut nonsense = Vec::new();
+        for part in ["nonsense", "", "word"] {
+            write_frame(&mut nonsense, part.as_bytes()).unwrap();
+        }
+        assert!(decode_query(&mut &nonsense[..]).is_err());
+    }
+
+    #[test]
+    fn matches_come_back_in_corpus_order() {
+        let articles: Vec<(String, Vec<String>)> = (0..10)
+            .map(|i| {
+                let contents = if i % 3 == 0 { "fizz" } else { "buzz" };
+                (format!("article {}", i), words(contents))
+            })
+            .collect();
+        let shards = ShardedIndex::partition(articles, empty_index8(), 3, Partition::Hash);
+        assert_eq!(shards.iter().map(|s| s.n_articles()).sum::<usize>(), 10);
+        let index = ShardedIndex::new(
+            shards
+                .into_iter()
+                .map(|s| Box::new(s) as Box<dyn Shard>)
+                .collect(),
+        );
+        assert_eq!(index.n_shards(), 3);
+        assert_eq!(
+            index.search(&boolean("fizz")),
+            vec!["article 0", "article 3", "article 6", "article 9"]
+        );
+        let found = index.search_articles(&boolean("!fizz")).unwrap();
+        let numbers: Vec<usize> = found.iter().map(|(a, _)| *a).collect();
+        assert_eq!(numbers, vec![1, 2, 4, 5, 7, 8]);
+    }
+
+    #[test]
+    fn sharded_index8_matches_one_index() {
+        let reference = Index::index8(&config()).unwrap();
+        let queries = gen_a_lot_of_runs_bool(FILE.to_string(), 10);
+        for partition in [Partition::Range, Partition::Hash] {
+            let index = ShardedIndex::index_sharded(&config(), 3, partition).unwrap();
+            for depth_vec in &queries {
+                for q in depth_vec {
+                    assert_eq!(index.search(&boolean(q)), reference.search(&boolean(q)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sharded_index9_matches_one_index() {
+        let articles = read_and_clean_file_to_iter(&config()).unwrap();
+        let mut reference = Index {
+            database: Trie::new(),
+            article_titles: Vec::new(),
+        };
+        for (title, contents) in articles.iter().filter(|(t, _)| !t.is_empty()) {
+            reference.add_document(title, contents);
+        }
+        let empty = Index {
+            database: Trie::new(),
+            article_titles: Vec::new(),
+        };
+        let shards = ShardedIndex::partition(articles, empty, 4, Partition::Hash);
+        let index = ShardedIndex::new(
+            shards
+                .into_iter()
+                .map(|s| Box::new(s) as Box<dyn Shard>)
+                .collect(),
+        );
+        for q in gen_a_lot_of_runs_tries(FILE.to_string(), 100, true) {
+            let q = query(&q, SearchType::PrefixSearch);
+            assert_eq!(index.search(&q), reference.search(&q));
+        }
+    }
+
+    #[test]
+    fn remote_shards_give_the_same_answers() {
+        let articles = read_and_clean_file_to_iter(&config()).unwrap();
+        let local = ShardedIndex::index_sharded(&config(), 3, Partition::Range).unwrap();
+        let servers: Vec<ShardServer> =
+            ShardedIndex::partition(articles, empty_index8(), 3, Partition::Range)
+                .into_iter()
+                .map(|shard| ShardServer::serve(shard).unwrap())
+                .collect();
+        let remote = ShardedIndex::new(
+            servers
+                .iter()
+                .map(|server| Box::new(RemoteShard::new(server.addr())) as Box<dyn Shard>)
+                .collect(),
+        );
+        for depth_vec in gen_a_lot_of_runs_bool(FILE.to_string(), 3) {
+            for q in depth_vec {
+                assert_eq!(
+                    remote.search_articles(&boolean(&q)).unwrap(),
+                    local.search_articles(&boolean(&q)).unwrap()
+                );
+            }
+        }
+
+        // Titles and queries may hold the characters the frames are made of
+        let server = ShardServer::serve({
+            let mut shard = LocalShard::new(empty_index8());
+            shard.add_document(7, "two\nlines\tand a tab", &words("a\nb"));
+            shard
+        })
+        .unwrap();
+        let framed = RemoteShard::new(server.addr());
+        assert_eq!(
+            framed
+                .search_articles(&query("a\nb", SearchType::SingleWordSearch))
+                .unwrap(),
+            vec![(7, "two\nlines\tand a tab".to_string())]
+        );
+
+        // Without its server, a shard can't answer. The index can't give every match, but still
+        // gives the ones of the shards that answered.
+        let addr = servers[1].addr();
+        drop(servers);
+        let broken = ShardedIndex::new(vec![
+            Box::new(RemoteShard::new(server.addr())),
+            Box::new(RemoteShard::new(addr)),
+        ]);
+        let q = query("a\nb", SearchType::SingleWordSearch);
+        assert!(broken.search_articles(&q).is_err());
+        let (found, failed) = broken.search_available(&q);
+        assert_eq!(found, vec![(7, "two\nlines\tand a tab".to_string())]);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, 1);
+        assert_eq!(broken.search(&q), vec!["two\nlines\tand a tab"]);
+    }
+
+    struct PanickingShard;
+
+    impl Shard for PanickingShard {
+        fn search_articles(&self, _query: &Query) -> io::Result<Vec<(usize, String)>> {
+            panic!("A broken shard")
+        }
+    }
+
+    #[test]
+    fn unsupported_searches_fail_only_the_query() {
+        let shard = || {
+            let mut shard = LocalShard::new(empty_index8());
+            shard.add_document(3, "article", &words("word"));
+            shard
+        };
+        let prefix = query("wo", SearchType::PrefixSearch);
+        let single = query("word", SearchType::SingleWordSearch);
+        let found = vec![(3, "article".to_string())];
+
+        let local = shard();
+        assert_eq!(
+            local.search_articles(&prefix).unwrap_err().kind(),
+            ErrorKind::Unsupported
+        );
+        assert_eq!(local.search_articles(&single).unwrap(), found);
+
+        // The server goes on answering after a query it can't
+        let server = ShardServer::serve(shard()).unwrap();
+        let remote = RemoteShard::new(server.addr());
+        assert_eq!(
+            remote.search_articles(&prefix).unwrap_err().kind(),
+            ErrorKind::Unsupported
+        );
+        assert_eq!(remote.search_articles(&single).unwrap(), found);
+
+        let index = ShardedIndex::new(vec![Box::new(shard()), Box::new(PanickingShard)]);
+        assert!(index.search_articles(&single).is_err());
+        let (answered, failed) = index.search_available(&single);
+        assert_eq!(answered, found);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, 1);
+    }
+
+    #[test]
+    fn idle_clients_hold_up_no_query() {
+        let server = ShardServer::serve({
+            let mut shard = LocalShard::new(empty_index8());
+            shard.add_document(0, "article", &words("word"));
+            shard
+        })
+        .unwrap();
+        // Connects but never sends a query
+        let _idle = TcpStream::connect(server.addr()).unwrap();
+        let remote = RemoteShard::new(server.addr());
+        assert_eq!(
+            remote
+                .search_articles(&query("word", SearchType::SingleWordSearch))
+                .unwrap(),
+            vec![(0, "article".to_string())]
+        );
+        // Nor does it keep the server from stopping
+        drop(server);
+    }
+}
@@ -0,0 +1,840 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::index::incremental::IncrementalIndex;
+use crate::index::Index;
+
+use super::*;
+
+// An index kept in a directory, which survives crashes at any point:
+//
+//   MANIFEST           the sequence number of the current snapshot and log, replaced by a rename
+//   snapshot-<seq>     the index as it was at the last checkpoint
+//   wal-<seq>.log      every add and delete since the checkpoint, written before it is applied
+//
+// The snapshot, the log and the manifest are made of records: the payload length and the CRC32
+// of the payload as u32, followed by the payload. Numbers are little endian. When the log is
+// replayed, it ends at the first record that is cut off or doesn't match its checksum.
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+// The CRC-32 used by zip and ethernet
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc = CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(payload.len() + 8);
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32(payload).to_le_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+// The payloads of the complete, intact records at the start of the bytes, and where they end
+pub fn read_records(bytes: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while bytes.len() - pos >= 8 {
+        let len = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap());
+        if bytes.len() - pos - 8 < len {
+            break;
+        }
+        let payload = &bytes[pos + 8..pos + 8 + len];
+        if crc32(payload) != crc {
+            break;
+        }
+        records.push(payload);
+        pos += 8 + len;
+    }
+    (records, pos)
+}
+
+fn invalid_data(msg: &str) -> Box<dyn Error> {
+    Box::new(io::Error::new(ErrorKind::InvalidData, msg))
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn put_u8(&mut self, n: u8) {
+        self.0.push(n);
+    }
+
+    fn put_u64(&mut self, n: usize) {
+        self.0.extend_from_slice(&(n as u64).to_le_bytes());
+    }
+
+    fn put_str(&mut self, s: &str) {
+        self.put_u64(s.len());
+        self.0.extend_from_slice(s.as_bytes());
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() - self.pos < n {
+            return None;
+        }
+        self.pos += n;
+        Some(&self.bytes[self.pos - n..self.pos])
+    }
+
+    fn get_u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn get_u64(&mut self) -> Option<usize> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().unwrap()) as usize)
+    }
+
+    fn get_str(&mut self) -> Option<String> {
+        let len = self.get_u64()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalOp {
+    Add {
+        doc_id: usize,
+        title: String,
+        contents: Vec<String>,
+    },
+    Delete {
+        doc_id: usize,
+    },
+}
+
+impl WalOp {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Encoder::default();
+        match self {
+            WalOp::Add {
+                doc_id,
+                title,
+                contents,
+            } => {
+                out.put_u8(1);
+                out.put_u64(*doc_id);
+                out.put_str(title);
+                out.put_u64(contents.len());
+                for word in contents {
+                    out.put_str(word);
+                }
+            }
+            WalOp::Delete { doc_id } => {
+                out.put_u8(2);
+                out.put_u64(*doc_id);
+            }
+        }
+        out.0
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<WalOp> {
+        let mut input = Decoder::new(payload);
+        let op = match input.get_u8()? {
+            1 => {
+                let doc_id = input.get_u64()?;
+                let title = input.get_str()?;
+                let n_words = input.get_u64()?;
+                let mut contents = Vec::new();
+                for _ in 0..n_words {
+                    contents.push(input.get_str()?);
+                }
+                WalOp::Add {
+                    doc_id,
+                    title,
+                    contents,
+                }
+            }
+            2 => WalOp::Delete {
+                doc_id: input.get_u64()?,
+            },
+            _ => return None,
+        };
+        match input.is_done() {
+            true => Some(op),
+            false => None,
+        }
+    }
+}
+
+// An index whose changes are written to a log before they are made, so it can be rebuilt after a
+// crash. Articles keep the number they were given by add_document across restarts.
+pub struct DurableIndex {
+    dir: PathBuf,
+    seq: usize,
+    // None only while the index is being opened
+    wal: Option<File>,
+    index: IncrementalIndex<HashMap<String, Vec<usize>>>,
+    // The article number of every article in the index, by position, and the other way around
+    doc_ids: Vec<usize>,
+    positions: HashMap<usize, usize>,
+    next_doc_id: usize,
+}
+
+fn snapshot_path(dir: &Path, seq: usize) -> PathBuf {
+    dir.join(format!("snapshot-{:08}", seq))
+}
+
+fn wal_path(dir: &Path, seq: usize) -> PathBuf {
+    dir.join(format!("wal-{:08}.log", seq))
+}
+
+// Makes sure renames and new files in the directory are on disk
+fn sync_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+// The sequence number in the name of a snapshot or log, or of one being written, and None for
+// the other files in the directory, which are not ours
+fn checkpoint_file_seq(name: &str) -> Option<usize> {
+    let name = name.strip_suffix(".tmp").unwrap_or(name);
+    let digits = match name.strip_prefix("snapshot-") {
+        Some(digits) => digits,
+        None => name.strip_prefix("wal-")?.strip_suffix(".log")?,
+    };
+    match digits.len() == 8 && digits.bytes().all(|b| b.is_ascii_digit()) {
+        true => digits.parse().ok(),
+        false => None,
+    }
+}
+
+// Writes the file next to its final place, and renames it there once it is on disk
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+impl DurableIndex {
+    // Opens the index in the directory, or creates an empty one if there is none
+    pub fn open(dir: &str) -> Result<Self, Box<dyn Error>> {
+        let dir = PathBuf::from(dir);
+        let manifest_path = dir.join("MANIFEST");
+        if !manifest_path.exists() {
+            fs::create_dir_all(&dir)?;
+            let mut index = DurableIndex::empty(&dir);
+            index.checkpoint()?;
+            return Ok(index);
+        }
+
+        let manifest = fs::read(&manifest_path)?;
+        let seq = match read_records(&manifest) {
+            (records, end) if records.len() == 1 && end == manifest.len() => {
+                Decoder::new(records[0])
+                    .get_u64()
+                    .ok_or_else(|| invalid_data("Broken manifest"))?
+            }
+            _ => return Err(invalid_data("Broken manifest")),
+        };
+
+        let mut index = DurableIndex::load_snapshot(&dir, seq)?;
+
+        // Replay the log, and cut off a record that was only partly written when the process died
+        let mut wal = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(wal_path(&dir, seq))?;
+        let bytes = fs::read(wal_path(&dir, seq))?;
+        let (records, end) = read_records(&bytes);
+        for record in records {
+            match WalOp::decode(record) {
+                Some(op) => index.apply(op),
+                None => return Err(invalid_data("Broken record in the log")),
+            }
+        }
+        if end < bytes.len() {
+            wal.set_len(end as u64)?;
+            wal.sync_all()?;
+        }
+        wal.seek(SeekFrom::End(0))?;
+        index.wal = Some(wal);
+
+        index.remove_old_files()?;
+        Ok(index)
+    }
+
+    fn empty(dir: &Path) -> Self {
+        DurableIndex::from_parts(
+            dir,
+            0,
+            Index {
+                database: HashMap::new(),
+                article_titles: Vec::new(),
+            },
+            Vec::new(),
+            0,
+        )
+    }
+
+    fn from_parts(
+        dir: &Path,
+        seq: usize,
+        index: Index<HashMap<String, Vec<usize>>>,
+        doc_ids: Vec<usize>,
+        next_doc_id: usize,
+    ) -> Self {
+        let positions = doc_ids.iter().enumerate().map(|(p, &d)| (d, p)).collect();
+        DurableIndex {
+            dir: dir.to_path_buf(),
+            seq,
+            wal: None,
+            index: IncrementalIndex::new(index),
+            doc_ids,
+            positions,
+            next_doc_id,
+        }
+    }
+
+    fn load_snapshot(dir: &Path, seq: usize) -> Result<Self, Box<dyn Error>> {
+        let bytes = fs::read(snapshot_path(dir, seq))?;
+        let payload = match read_records(&bytes) {
+            (records, end) if records.len() == 1 && end == bytes.len() => records[0],
+            _ => return Err(invalid_data("Broken snapshot")),
+        };
+        DurableIndex::decode_snapshot(dir, seq, payload)
+            .ok_or_else(|| invalid_data("Broken snapshot"))
+    }
+
+    fn decode_snapshot(dir: &Path, seq: usize, payload: &[u8]) -> Option<Self> {
+        let mut input = Decoder::new(payload);
+        let next_doc_id = input.get_u64()?;
+        let n_docs = input.get_u64()?;
+        let mut doc_ids = Vec::new();
+        let mut article_titles = Vec::new();
+        for _ in 0..n_docs {
+            doc_ids.push(input.get_u64()?);
+            article_titles.push(input.get_str()?);
+        }
+        let n_terms = input.get_u64()?;
+        let mut database: HashMap<String, Vec<usize>> = HashMap::new();
+        for _ in 0..n_terms {
+            let term = input.get_str()?;
+            let n_articles = input.get_u64()?;
+            let mut articles = Vec::new();
+            for _ in 0..n_articles {
+                articles.push(input.get_u64()?);
+            }
+            database.insert(term, articles);
+        }
+        if !input.is_done() {
+            return None;
+        }
+        Some(DurableIndex::from_parts(
+            dir,
+            seq,
+            Index {
+                database,
+                article_titles,
+            },
+            doc_ids,
+            next_doc_id,
+        ))
+    }
+
+    fn encode_snapshot(&self) -> Vec<u8> {
+        let index = self.index.get_index();
+        let mut out = Encoder::default();
+        out.put_u64(self.next_doc_id);
+        out.put_u64(self.doc_ids.len());
+        for (doc_id, title) in self.doc_ids.iter().zip(&index.article_titles) {
+            out.put_u64(*doc_id);
+            out.put_str(title);
+        }
+        out.put_u64(index.database.len());
+        for (term, articles) in &index.database {
+            out.put_str(term);
+            out.put_u64(articles.len());
+            for &a in articles {
+                out.put_u64(a);
+            }
+        }
+        out.0
+    }
+
+    fn apply(&mut self, op: WalOp) {
+        match op {
+            WalOp::Add {
+                doc_id,
+                title,
+                contents,
+            } => {
+                let position = self.index.add_document(&title, &contents);
+                self.doc_ids.push(doc_id);
+                self.positions.insert(doc_id, position);
+                self.next_doc_id = self.next_doc_id.max(doc_id + 1);
+            }
+            WalOp::Delete { doc_id } => {
+                if let Some(position) = self.positions.remove(&doc_id) {
+                    self.index.delete_document(position);
+                }
+            }
+        }
+    }
+
+    // A record that is only partly written would end the replay, and the records written after it
+    // would be lost, so it is cut off again when the write fails. If that fails too, the log is
+    // closed and takes no more records.
+    fn log(&mut self, op: &WalOp) -> Result<(), Box<dyn Error>> {
+        let wal = self
+            .wal
+            .as_mut()
+            .ok_or_else(|| invalid_data("The log is not open, or a write to it failed"))?;
+        let end = wal.seek(SeekFrom::End(0))?;
+        if let Err(e) = wal
+            .write_all(&frame(&op.encode()))
+            .and_then(|_| wal.sync_data())
+        {
+            let cut_off = wal
+                .set_len(end)
+                .and_then(|_| wal.seek(SeekFrom::Start(end)))
+                .and_then(|_| wal.sync_data());
+            if cut_off.is_err() {
+                self.wal = None;
+            }
+            return Err(Box::new(e));
+        }
+        Ok(())
+    }
+
+    // Returns the number of the new article once the addition is on disk
+    pub fn add_document(
+        &mut self,
+        title: &str,
+        contents: &[String],
+    ) -> Result<usize, Box<dyn Error>> {
+        let op = WalOp::Add {
+            doc_id: self.next_doc_id,
+            title: title.to_string(),
+            contents: contents.to_vec(),
+        };
+        self.log(&op)?;
+        let doc_id = self.next_doc_id;
+        self.apply(op);
+        Ok(doc_id)
+    }
+
+    // Returns false if there is no such article, or it is already deleted
+    pub fn delete_document(&mut self, doc_id: usize) -> Result<bool, Box<dyn Error>> {
+        if !self.positions.contains_key(&doc_id) {
+            return Ok(false);
+        }
+        let op = WalOp::Delete { doc_id };
+        self.log(&op)?;
+        self.apply(op);
+        Ok(true)
+    }
+
+    // The numbers of the articles in the index, in increasing order
+    pub fn documents(&self) -> Vec<usize> {
+        let mut docs: Vec<usize> = self.positions.keys().copied().collect();
+        docs.sort_unstable();
+        docs
+    }
+
+    // Writes a new snapshot without the deleted articles and starts a new, empty log. The new
+    // files only take over when the manifest is renamed into place, so a crash in between leaves
+    // the old snapshot and log in charge.
+    pub fn checkpoint(&mut self) -> Result<(), Box<dyn Error>> {
+        let new_ids = self.index.compact();
+        self.doc_ids = self
+            .doc_ids
+            .iter()
+            .zip(&new_ids)
+            .filter(|(_, new_id)| new_id.is_some())
+            .map(|(&doc_id, _)| doc_id)
+            .collect();
+        self.positions = self
+            .doc_ids
+            .iter()
+            .enumerate()
+            .map(|(p, &d)| (d, p))
+            .collect();
+
+        let seq = self.seq + 1;
+        write_atomically(
+            &snapshot_path(&self.dir, seq),
+            &frame(&self.encode_snapshot()),
+        )?;
+        let wal = File::create(wal_path(&self.dir, seq))?;
+        wal.sync_all()?;
+        let mut manifest = Encoder::default();
+        manifest.put_u64(seq);
+        write_atomically(&self.dir.join("MANIFEST"), &frame(&manifest.0))?;
+
+        // The old log is no longer replayed, so nothing more may go into it. Until the new one is
+        // known to be on disk, there is no log, and writes fail instead of being lost.
+        self.seq = seq;
+        self.wal = None;
+        sync_dir(&self.dir)?;
+        self.wal = Some(wal);
+        self.remove_old_files()
+    }
+
+    // Removes the files of earlier checkpoints, and any left behind by a crash during one. Other
+    // files in the directory are left alone.
+    fn remove_old_files(&self) -> Result<(), Box<dyn Error>> {
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let old = match checkpoint_file_seq(&name) {
+                Some(seq) => seq != self.seq || name.ends_with(".tmp"),
+                None => name == "MANIFEST.tmp",
+            };
+            if old {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Search for DurableIndex {
+    fn search(&self, query: &Query) -> ArticleTitles {
+        self.index.search(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("rustsearch_wal_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.into_os_string().into_string().unwrap()
+    }
+
+    fn words(s: &str) -> Vec<String> {
+        s.split(' ').map(|w| w.to_string()).collect()
+    }
+
+    fn boolean(search_string: &str) -> Query {
+        Query {
+            search_string: search_string.to_string(),
+            search_type: SearchType::BooleanSearch("Naive".to_string()),
+        }
+    }
+
+    fn copy_dir(from: &str, to: &str) {
+        let _ = fs::remove_dir_all(to);
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            fs::copy(entry.path(), Path::new(to).join(entry.file_name())).unwrap();
+        }
+    }
+
+    // Applies the operations to a fresh index and reports what is left
+    fn expected_state(ops: &[WalOp]) -> (Vec<usize>, Vec<String>) {
+        let dir = temp_dir("expected");
+        let mut index = DurableIndex::open(&dir).unwrap();
+        for op in ops {
+            index.log(op).unwrap();
+            index.apply(op.clone());
+        }
+        let state = (index.documents(), index.search(&boolean("word | other")));
+        fs::remove_dir_all(&dir).unwrap();
+        state
+    }
+
+    #[test]
+    fn sequence_numbers_of_checkpoint_files() {
+        assert_eq!(checkpoint_file_seq("snapshot-00000012"), Some(12));
+        assert_eq!(checkpoint_file_seq("snapshot-00000012.tmp"), Some(12));
+        assert_eq!(checkpoint_file_seq("wal-00000003.log"), Some(3));
+        for name in [
+            "MANIFEST",
+            "wal-00000003",
+            "snapshot-12",
+            "snapshot-0000001x",
+            "a.tmp",
+        ] {
+            assert_eq!(checkpoint_file_seq(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn ops_survive_encoding() {
+        let ops = vec![
+            WalOp::Add {
+                doc_id: 7,
+                title: "Ça dorada".to_string(),
+                contents: words("one two"),
+            },
+            WalOp::Add {
+                doc_id: 8,
+                title: "".to_string(),
+                contents: vec![],
+            },
+            WalOp::Delete { doc_id: 7 },
+        ];
+        for op in ops {
+            assert_eq!(WalOp::decode(&op.encode()), Some(op.clone()));
+            let encoded = op.encode();
+            assert_eq!(WalOp::decode(&encoded[..encoded.len() - 1]), None);
+        }
+        assert_eq!(WalOp::decode(&[3]), None);
+    }
+
+    #[test]
+    fn changes_survive_a_restart() {
+        let dir = temp_dir("restart");
+        {
+            let mut index = DurableIndex::open(&dir).unwrap();
+            assert_eq!(index.add_document("A", &words("word")).unwrap(), 0);
+            assert_eq!(index.add_document("B", &words("word other")).unwrap(), 1);
+            assert!(index.delete_document(0).unwrap());
+            assert!(!index.delete_document(0).unwrap());
+        }
+        let mut index = DurableIndex::open(&dir).unwrap();
+        assert_eq!(index.documents(), vec![1]);
+        assert_eq!(index.search(&boolean("word")), vec!["B"]);
+
+        // Numbers are not handed out again, even after the deleted article is compacted away
+        index.checkpoint().unwrap();
+        assert_eq!(index.add_document("C", &words("word")).unwrap(), 2);
+        index.delete_document(1).unwrap();
+        index.checkpoint().unwrap();
+        drop(index);
+
+        let mut index = DurableIndex::open(&dir).unwrap();
+        assert_eq!(index.documents(), vec![2]);
+        assert_eq!(index.search(&boolean("word")), vec!["C"]);
+        assert_eq!(index.add_document("D", &words("other")).unwrap(), 3);
+        assert_eq!(index.search(&boolean("!word")), vec!["D"]);
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["MANIFEST", "snapshot-00000003", "wal-00000003.log"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn log_truncated_at_any_byte_replays_the_complete_records() {
+        let dir = temp_dir("truncate");
+        let ops = vec![
+            WalOp::Add {
+                doc_id: 0,
+                title: "A".to_string(),
+                contents: words("word"),
+            },
+            WalOp::Add {
+                doc_id: 1,
+                title: "B".to_string(),
+                contents: words("other word"),
+            },
+            WalOp::Delete { doc_id: 0 },
+            WalOp::Add {
+                doc_id: 2,
+                title: "C".to_string(),
+                contents: words("other"),
+            },
+        ];
+        {
+            let mut index = DurableIndex::open(&dir).unwrap();
+            index.add_document("A", &words("word")).unwrap();
+            index.add_document("B", &words("other word")).unwrap();
+            index.delete_document(0).unwrap();
+            index.add_document("C", &words("other")).unwrap();
+        }
+        let wal = fs::read(wal_path(Path::new(&dir), 1)).unwrap();
+        let record_ends: Vec<usize> = ops
+            .iter()
+            .scan(0, |end, op| {
+                *end += op.encode().len() + 8;
+                Some(*end)
+            })
+            .collect();
+        assert_eq!(*record_ends.last().unwrap(), wal.len());
+
+        let crashed = temp_dir("truncate_crashed");
+        for cut in 0..=wal.len() {
+            copy_dir(&dir, &crashed);
+            fs::write(wal_path(Path::new(&crashed), 1), &wal[..cut]).unwrap();
+
+            let complete = record_ends.iter().filter(|&&end| end <= cut).count();
+            let mut index = DurableIndex::open(&crashed).unwrap();
+            assert_eq!(
+                (index.documents(), index.search(&boolean("word | other"))),
+                expected_state(&ops[..complete]),
+                "cut at {}",
+                cut
+            );
+
+            // The torn record is gone, so new records follow the last complete one
+            let doc_id = index.add_document("D", &words("word")).unwrap();
+            drop(index);
+            let index = DurableIndex::open(&crashed).unwrap();
+            assert!(index.documents().contains(&doc_id));
+        }
+        fs::remove_dir_all(&crashed).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replay_stops_at_a_corrupted_record() {
+        let dir = temp_dir("corrupt");
+        {
+            let mut index = DurableIndex::open(&dir).unwrap();
+            index.add_document("A", &words("word")).unwrap();
+            index.add_document("B", &words("word")).unwrap();
+            index.add_document("C", &words("word")).unwrap();
+        }
+        let path = wal_path(Path::new(&dir), 1);
+        let mut wal = fs::read(&path).unwrap();
+        let first_len = WalOp::Add {
+            doc_id: 0,
+            title: "A".to_string(),
+            contents: words("word"),
+        }
+        .encode()
+        .len()
+            + 8;
+        // Flip a bit in the title of the second record
+        wal[first_len + 8 + 1 + 8 + 8] ^= 1;
+        fs::write(&path, &wal).unwrap();
+
+        let index = DurableIndex::open(&dir).unwrap();
+        assert_eq!(index.documents(), vec![0]);
+        assert_eq!(fs::read(&path).unwrap().len(), first_len);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_closes_the_log() {
+        let dir = temp_dir("failed_write");
+        let mut index = DurableIndex::open(&dir).unwrap();
+        index.add_document("A", &words("word")).unwrap();
+        // Writes to the log fail, and so does cutting it off
+        index.wal = Some(File::open(wal_path(Path::new(&dir), 1)).unwrap());
+        assert!(index.add_document("B", &words("word")).is_err());
+        assert!(index.wal.is_none());
+        assert!(index.add_document("C", &words("word")).is_err());
+        drop(index);
+
+        let mut index = DurableIndex::open(&dir).unwrap();
+        assert_eq!(index.documents(), vec![0]);
+        assert_eq!(index.add_document("D", &words("word")).unwrap(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_checkpoint_keeps_logging_to_the_replayed_log() {
+        let dir = temp_dir("failed_checkpoint");
+        let mut index = DurableIndex::open(&dir).unwrap();
+        index.add_document("A", &words("word")).unwrap();
+        // The manifest can't be written, so the old one stays in charge
+        fs::create_dir(Path::new(&dir).join("MANIFEST.tmp")).unwrap();
+        assert!(index.checkpoint().is_err());
+        index.add_document("B", &words("word")).unwrap();
+        drop(index);
+
+        fs::remove_dir(Path::new(&dir).join("MANIFEST.tmp")).unwrap();
+        let index = DurableIndex::open(&dir).unwrap();
+        assert_eq!(index.documents(), vec![0, 1]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn crash_during_checkpoint_keeps_the_old_state() {
+        let dir = temp_dir("checkpoint");
+        {
+            let mut index = DurableIndex::open(&dir).unwrap();
+            index.add_document("A", &words("word")).unwrap();
+            index.add_document("B", &words("other")).unwrap();
+        }
+        // A checkpoint that died before the manifest was renamed
+        fs::write(snapshot_path(Path::new(&dir), 2), b"half a snapshot").unwrap();
+        fs::write(Path::new(&dir).join("snapshot-00000003.tmp"), b"half").unwrap();
+        fs::write(Path::new(&dir).join("MANIFEST.tmp"), b"half a manifest").unwrap();
+        // Files of the user
+        let theirs = ["notes.txt", "notes.tmp", "snapshot-old", "wal-1.log"];
+        for name in theirs {
+            fs::write(Path::new(&dir).join(name), b"keep me").unwrap();
+        }
+
+        let mut index = DurableIndex::open(&dir).unwrap();
+        assert_eq!(index.documents(), vec![0, 1]);
+        assert!(!snapshot_path(Path::new(&dir), 2).exists());
+        assert!(!Path::new(&dir).join("snapshot-00000003.tmp").exists());
+        assert!(!Path::new(&dir).join("MANIFEST.tmp").exists());
+        index.checkpoint().unwrap();
+        for name in theirs {
+            assert_eq!(fs::read(Path::new(&dir).join(name)).unwrap(), b"keep me");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn broken_manifest_is_an_error() {
+        let dir = temp_dir("manifest");
+        DurableIndex::open(&dir).unwrap();
+        fs::write(Path::new(&dir).join("MANIFEST"), b"nonsense").unwrap();
+        assert!(DurableIndex::open(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
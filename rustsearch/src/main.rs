@@ -19,13 +19,17 @@ fn main() {
 
 fn user_dialog() {
     let mut loaded_indices: HashMap<String, Box<dyn Search>> = HashMap::new();
-    let index_names = ["6", "7", "8", "9.0", "9.1", "10", "11"];
+    let index_names = ["6", "7", "8", "8.6", "8.7", "8.9", "9.0", "9.1", "10", "10.4", "10.6", "11", "11.2", "11.3", "12", "12.1", "13"];
 
     let available_search_types: HashMap<String, Vec<SearchType>> = HashMap::from_iter([
         ("6".to_string(), vec![SingleWordSearch]),
         (
             "7".to_string(),
-            vec![SingleWordSearch, BooleanSearch("".to_string())],
+            vec![
+                SingleWordSearch,
+                BooleanSearch("".to_string()),
+                BooleanSearch("Simd".to_string()),
+            ],
         ),
         (
             "8".to_string(),
@@ -36,8 +40,22 @@ fn user_dialog() {
                 BooleanSearch("BinarySearch".to_string()),
                 BooleanSearch("Hybrid".to_string()),
                 BooleanSearch("Bitvecs".to_string()),
+                BooleanSearch("Difference".to_string()),
+                BooleanSearch("Simd".to_string()),
             ],
         ),
+        (
+            "8.6".to_string(),
+            vec![SingleWordSearch, BooleanSearch("SkipPointers".to_string())],
+        ),
+        (
+            "8.7".to_string(),
+            vec![SingleWordSearch, BooleanSearch("Roaring".to_string())],
+        ),
+        (
+            "8.9".to_string(),
+            vec![SingleWordSearch, BooleanSearch("TopK".to_string())],
+        ),
         ("9.0".to_string(), vec![SingleWordSearch, PrefixSearch]),
         ("9.1".to_string(), vec![SingleWordSearch, PrefixSearch]),
         (
@@ -47,6 +65,20 @@ fn user_dialog() {
                 ExactSearch("KMP".to_string()),
                 ExactSearch("BoyerMoore".to_string()),
                 ExactSearch("ApostolicoGiancarlo".to_string()),
+                ExactSearch("AhoCorasick".to_string()),
+                ExactSearch("Myers".to_string()),
+                ExactSearch("Myers/2".to_string()),
+            ],
+        ),
+        (
+            "10.4".to_string(),
+            vec![SingleWordSearch, ExactSearch("Regex".to_string())],
+        ),
+        (
+            "10.6".to_string(),
+            vec![
+                ExactSearch("Folded".to_string()),
+                ExactSearch("Folded/accents".to_string()),
             ],
         ),
         (
@@ -57,6 +89,37 @@ fn user_dialog() {
                 ExactSearch("TripleBoyerMoore".to_string()),
             ],
         ),
+        (
+            "11.2".to_string(),
+            vec![SingleWordSearch, BooleanSearch("Positional".to_string())],
+        ),
+        (
+            "11.3".to_string(),
+            vec![
+                SingleWordSearch,
+                FuzzySearch,
+                ExactSearch("NgramBoyerMoore".to_string()),
+                BooleanSearch("Phrases".to_string()),
+            ],
+        ),
+        (
+            "12".to_string(),
+            vec![SingleWordSearch, BooleanSearch("".to_string())],
+        ),
+        (
+            "12.1".to_string(),
+            vec![
+                SingleWordSearch,
+                BooleanSearch("".to_string()),
+                PrefixSearch,
+                TermSearch("Range".to_string()),
+                TermSearch("Regex".to_string()),
+            ],
+        ),
+        (
+            "13".to_string(),
+            vec![SingleWordSearch, ExactSearch("SuffixArray".to_string())],
+        ),
     ]);
 
     // let config = Config::build(&[
@@ -1,13 +1,26 @@
 #![allow(dead_code)]
 use std::collections::VecDeque;
 
+use crate::helpers::field_term;
+
 const MAX_RECURSION: u16 = 20;
 const MAX_LEN: usize = 200;
 
+/// The field unqualified words search. "body:word" is the same as "word".
+pub const BODY_FIELD: &str = "body";
+pub const TITLE_FIELD: &str = "title";
+
+pub const NEAR_NEEDS_POSITIONS: &str = "NEAR/k needs an index with the positions of words";
+pub const PHRASE_NEEDS_PHRASES: &str = "quoted phrases need an index that can find phrases";
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BinaryOp {
     And,
     Or,
+    /// "NEAR/k" and "ONEAR/k": both sides occur within k words of each other, and for ONEAR
+    /// the left side comes first. Only indices that know where words are can answer it, so
+    /// `Expr::from_string` rejects it and those indices parse with `from_string_with_positions`.
+    Near { distance: usize, ordered: bool },
 }
 
 impl BinaryOp {
@@ -15,6 +28,7 @@ impl BinaryOp {
         match self {
             Self::And => '&',
             Self::Or => '|',
+            Self::Near { .. } => unreachable!("NEAR/k is only written as text"),
         }
     }
     pub fn from_char(c: char) -> Option<Self> {
@@ -28,7 +42,17 @@ impl BinaryOp {
         match text {
             "and" => Some(Self::And),
             "or" => Some(Self::Or),
-            _ => None,
+            _ => {
+                let (ordered, distance) = match text.split_once('/')? {
+                    ("near", distance) => (false, distance),
+                    ("onear", distance) => (true, distance),
+                    _ => return None,
+                };
+                Some(Self::Near {
+                    distance: distance.parse().ok()?,
+                    ordered,
+                })
+            }
         }
     }
 }
@@ -47,6 +71,8 @@ fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
     enum ParseState {
         AnyExpected,
         InName,
+        /// Inside a quoted phrase, which ends at the next quote.
+        InPhrase,
         /// Currently in a binary operation repersented with symbols instead of words.
         InSymbolBinOp(BinaryOp),
     }
@@ -60,6 +86,19 @@ fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
     let mut cur_name = String::new();
 
     for c in s.chars() {
+        if state == ParseState::InPhrase {
+            if c == '"' {
+                tokens.push(Token::Name {
+                    text: phrase_term(&cur_name)?,
+                });
+                cur_name = String::new();
+                state = ParseState::AnyExpected;
+            } else {
+                cur_name.push(c);
+            }
+            continue;
+        }
+
         if let ParseState::InSymbolBinOp(op) = state {
             state = ParseState::AnyExpected;
             if c == op.as_char() {
@@ -70,7 +109,7 @@ fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
 
         if state == ParseState::InName {
             let end_cur_token = match c {
-                '(' | ')' | '!' => true,
+                '(' | ')' | '!' | '"' => true,
                 _ if BinaryOp::from_char(c) != None => true,
                 _ if c.is_whitespace() => true,
                 _ => false,
@@ -80,7 +119,9 @@ fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
                 if let Some(op) = BinaryOp::from_text(&lower) {
                     tokens.push(Token::BinaryOp(op));
                 } else {
-                    tokens.push(Token::Name { text: cur_name });
+                    tokens.push(Token::Name {
+                        text: qualified_name(cur_name),
+                    });
                 }
                 cur_name = String::new();
                 state = ParseState::AnyExpected;
@@ -99,6 +140,10 @@ fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
                 '(' => tokens.push(Token::OpenBracket),
                 ')' => tokens.push(Token::CloseBracket),
                 '!' => tokens.push(Token::Invert),
+                '"' => {
+                    state = ParseState::InPhrase;
+                    cur_name = String::new();
+                }
                 // ignore whitespace
                 _ if c.is_whitespace() => {}
                 _ => {
@@ -109,17 +154,43 @@ fn lex(s: &str) -> Result<Vec<Token>, &'static str> {
             }
         }
     }
+    if state == ParseState::InPhrase {
+        return Err("unterminated phrase");
+    }
     if !cur_name.is_empty() {
         let lower = cur_name.to_ascii_lowercase();
         if let Some(op) = BinaryOp::from_text(&lower) {
             tokens.push(Token::BinaryOp(op));
         } else {
-            tokens.push(Token::Name { text: cur_name });
+            tokens.push(Token::Name {
+                text: qualified_name(cur_name),
+            });
         }
     }
     Ok(tokens)
 }
 
+/// Names of the form "field:word" search that field, under the term the word is indexed as. The
+/// field is matched case insensitively.
+fn qualified_name(name: String) -> String {
+    match name.split_once(':') {
+        Some((field, word)) if !field.is_empty() && !word.is_empty() => {
+            field_term(&field.to_ascii_lowercase(), word)
+        }
+        _ => name,
+    }
+}
+
+/// A quoted phrase is a name of its words separated by single spaces, so a name with a space
+/// is a phrase. Only the indices that can find phrases parse them.
+fn phrase_term(phrase: &str) -> Result<String, &'static str> {
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    match words.is_empty() {
+        true => Err("empty phrase"),
+        false => Ok(words.join(" ")),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 
 pub enum AstNode {
@@ -231,6 +302,9 @@ impl AstNode {
             Self::Name(name) => tags.contains(&&**name),
             Self::Binary(BinaryOp::And, a1, a2) => a1.matches(tags) && a2.matches(tags),
             Self::Binary(BinaryOp::Or, a1, a2) => a1.matches(tags) || a2.matches(tags),
+            Self::Binary(BinaryOp::Near { .. }, _, _) => {
+                unreachable!("{}", NEAR_NEEDS_POSITIONS)
+            }
         }
     }
 }
@@ -245,8 +319,36 @@ pub enum ExprData {
 pub struct Expr(pub ExprData); // wrap internal implementation details
 
 impl Expr {
+    /// An expression of words, for the indices that don't know where the words are.
     pub fn from_string(s: &str) -> Result<Self, &'static str> {
-        let mut tokens: VecDeque<Token> = lex(s)?.into_iter().collect();
+        Self::parse(s, false, false)
+    }
+
+    /// An expression that may have quoted phrases, but no NEAR/k.
+    pub fn from_string_with_phrases(s: &str) -> Result<Self, &'static str> {
+        Self::parse(s, true, false)
+    }
+
+    /// An expression that may have quoted phrases and NEAR/k, for the indices with the
+    /// positions of words.
+    pub fn from_string_with_positions(s: &str) -> Result<Self, &'static str> {
+        Self::parse(s, true, true)
+    }
+
+    fn parse(s: &str, phrases: bool, near: bool) -> Result<Self, &'static str> {
+        let tokens = lex(s)?;
+        for token in &tokens {
+            match token {
+                Token::BinaryOp(BinaryOp::Near { .. }) if !near => {
+                    return Err(NEAR_NEEDS_POSITIONS)
+                }
+                Token::Name { text } if !phrases && text.contains(' ') => {
+                    return Err(PHRASE_NEEDS_PHRASES)
+                }
+                _ => {}
+            }
+        }
+        let mut tokens: VecDeque<Token> = tokens.into_iter().collect();
         if tokens.is_empty() {
             return Ok(Self(ExprData::Empty));
         }
@@ -582,4 +684,116 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn near_operators() {
+        let near = |distance, ordered| BinaryOp::Near { distance, ordered };
+        assert_eq!(
+            Expr::from_string_with_positions("a NEAR/3 b").unwrap().0,
+            ExprData::HasNodes(AstNode::Binary(
+                near(3, false),
+                Box::new(AstNode::Name("a".to_string())),
+                Box::new(AstNode::Name("b".to_string())),
+            ))
+        );
+        assert_eq!(
+            Expr::from_string_with_positions("(a | b) onear/10 !c")
+                .unwrap()
+                .0,
+            ExprData::HasNodes(AstNode::Binary(
+                near(10, true),
+                Box::new(AstNode::Binary(
+                    BinaryOp::Or,
+                    Box::new(AstNode::Name("a".to_string())),
+                    Box::new(AstNode::Name("b".to_string())),
+                )),
+                Box::new(AstNode::Invert(Box::new(AstNode::Name("c".to_string())))),
+            ))
+        );
+        // Without a distance it is just a word
+        assert_eq!(
+            lex("near near/x").unwrap(),
+            vec![
+                Token::Name {
+                    text: "near".to_string()
+                },
+                Token::Name {
+                    text: "near/x".to_string()
+                },
+            ]
+        );
+        // Indices without positions can't answer it
+        assert_eq!(
+            Expr::from_string("a near/2 b"),
+            Err(NEAR_NEEDS_POSITIONS)
+        );
+        assert!(Expr::from_string("near & near/x").is_ok());
+    }
+
+    #[test]
+    fn quoted_phrases() {
+        assert_eq!(
+            Expr::from_string_with_phrases("\"mutual  aid\" and !\"State socialism\"")
+                .unwrap()
+                .0,
+            ExprData::HasNodes(AstNode::Binary(
+                BinaryOp::And,
+                Box::new(AstNode::Name("mutual aid".to_string())),
+                Box::new(AstNode::Invert(Box::new(AstNode::Name(
+                    "State socialism".to_string()
+                )))),
+            ))
+        );
+        // Operators and brackets in a phrase are words
+        assert_eq!(
+            lex("a\"b & (c)\"|d").unwrap(),
+            vec![
+                Token::Name {
+                    text: "a".to_string()
+                },
+                Token::Name {
+                    text: "b & (c)".to_string()
+                },
+                Token::BinaryOp(BinaryOp::Or),
+                Token::Name {
+                    text: "d".to_string()
+                },
+            ]
+        );
+        assert_eq!(lex("a & \"b c"), Err("unterminated phrase"));
+        // A quoted word is just the word
+        assert_eq!(Expr::from_string("\"a\""), Expr::from_string("a"));
+        assert_eq!(
+            Expr::from_string("a & !\"b c\""),
+            Err(PHRASE_NEEDS_PHRASES)
+        );
+        assert_eq!(
+            Expr::from_string_with_phrases("\"a b\" near/2 c"),
+            Err(NEAR_NEEDS_POSITIONS)
+        );
+        assert!(Expr::from_string_with_positions("\"a b\" near/2 c").is_ok());
+        assert_eq!(lex("a | \" \""), Err("empty phrase"));
+    }
+
+    #[test]
+    fn field_qualified_names() {
+        assert_eq!(
+            Expr::from_string("Title:Autism & body:disorder | disorder:")
+                .unwrap()
+                .0,
+            ExprData::HasNodes(AstNode::Binary(
+                BinaryOp::And,
+                Box::new(AstNode::Name("title:Autism".to_string())),
+                Box::new(AstNode::Binary(
+                    BinaryOp::Or,
+                    Box::new(AstNode::Name("disorder".to_string())),
+                    Box::new(AstNode::Name("disorder:".to_string())),
+                )),
+            ))
+        );
+        assert!(Expr::from_string("title:a & !b")
+            .unwrap()
+            .matches(&["title:a", "a"]));
+        assert!(!Expr::from_string("title:a").unwrap().matches(&["a"]));
+    }
 }